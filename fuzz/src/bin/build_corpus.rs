@@ -0,0 +1,65 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// fuzz/src/bin/build_corpus.rs - Seed the corpus from data files compiled by lits-cc.
+
+use lost_in_time::{split_statements, Limits};
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::Path,
+    process,
+};
+
+fn write(dir: &Path, name: &str, data: &[u8]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(name), data)
+}
+
+// whole files go to game_data, each statement goes to eval, and whatever follows each opcode goes
+// to object, since most statements start with an object
+fn add_file(corpus: &Path, file: &Path) -> io::Result<()> {
+    let data = fs::read(file)?;
+    let name = file
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("input");
+    let stem = file
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("input");
+    write(&corpus.join("game_data"), name, &data)?;
+
+    // statements are only found in the uncompressed data
+    let data = if lit_lz::is_compressed(&data) {
+        let mut raw = vec![];
+        lit_lz::Decoder::new(&data[..]).read_to_end(&mut raw)?;
+        raw
+    } else {
+        data
+    };
+
+    let (statements, _) = split_statements(&data, &Limits::new());
+    for (i, statement) in statements.into_iter().enumerate() {
+        let name = format!("{}-{}.dat", stem, i);
+        write(&corpus.join("eval"), &name, &data[statement.clone()])?;
+        write(&corpus.join("object"), &name, &data[statement.start + 2..statement.end])?;
+    }
+
+    println!("Added {}", file.display());
+    Ok(())
+}
+
+fn main() {
+    let files: Vec<String> = env::args().skip(1).collect();
+    if files.is_empty() {
+        eprintln!("Usage: build_corpus <compiled data file>...");
+        process::exit(1);
+    }
+
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+    for file in files {
+        if let Err(e) = add_file(&corpus, Path::new(&file)) {
+            eprintln!("Unable to add {}: {}", file, e);
+            process::exit(1);
+        }
+    }
+}
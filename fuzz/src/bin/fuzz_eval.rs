@@ -0,0 +1,17 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// fuzz/src/bin/fuzz_eval.rs - Fuzz evaluating statements, without verifying them first.
+
+use lit_fuzz::fuzz;
+use lost_in_time::{eval, GameData, ParserState};
+
+fn target(mut data: &[u8]) {
+    let mut game_data = GameData::new();
+    let mut state = ParserState::new();
+
+    // every statement reads at least its opcode, so this ends with the input
+    while let Ok(true) = eval(&mut data, &mut game_data, &mut state) {}
+}
+
+fn main() {
+    fuzz("eval", target)
+}
@@ -0,0 +1,24 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// fuzz/src/bin/fuzz_game_data.rs - Fuzz loading a whole data file and running its scripts.
+
+use lit_fuzz::fuzz;
+use lost_in_time::{GameData, Limits, LoadPolicy, Vm};
+
+fn target(data: &[u8]) {
+    // resources aren't built when only reporting, so no renderer is needed
+    let mut game_data =
+        match GameData::read_layers_with(&mut [data], LoadPolicy::ReportOnly, Limits::new()) {
+            Ok(d) => d,
+            Err(_) => return,
+        };
+
+    let mut vm = Vm::new();
+    let _ = game_data.run_script(&mut vm, "on_start");
+    for _ in 0..3 {
+        let _ = game_data.run_script(&mut vm, "on_update");
+    }
+}
+
+fn main() {
+    fuzz("game_data", target)
+}
@@ -0,0 +1,17 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// fuzz/src/bin/fuzz_object.rs - Fuzz reading a single object.
+
+use lit_fuzz::fuzz;
+use lost_in_time::{BytecodeObject, Limits, ParserState};
+
+fn target(mut data: &[u8]) {
+    let state = ParserState::new();
+    if let Ok(object) = BytecodeObject::read_limited(&mut data, &Limits::new()) {
+        let _ = object.stringify(&state);
+        let _ = object.as_color(&state);
+    }
+}
+
+fn main() {
+    fuzz("object", target)
+}
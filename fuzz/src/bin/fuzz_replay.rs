@@ -0,0 +1,34 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// fuzz/src/bin/fuzz_replay.rs - Fuzz reading replay files, and play whatever is read to the end.
+
+use lit_fuzz::fuzz;
+use lost_in_time::{InputReplay, InputSource};
+
+// replays that claim to be longer than this would only take time to play
+const MAX_FRAMES: u32 = 1 << 16;
+
+fn target(mut data: &[u8]) {
+    let mut replay = match InputReplay::read(&mut data) {
+        Ok(replay) => replay,
+        Err(_) => return,
+    };
+    if replay.frames() > MAX_FRAMES {
+        return;
+    }
+
+    // the checksums are skipped, so the replay can't go out of sync
+    while !replay.is_finished() {
+        let frame = replay.frame();
+        replay
+            .next_frame(&[])
+            .expect("a replay has input for every frame");
+        replay
+            .end_frame(None)
+            .expect("a replay can skip its checksums");
+        assert_eq!(replay.frame(), frame + 1);
+    }
+}
+
+fn main() {
+    fuzz("replay", target)
+}
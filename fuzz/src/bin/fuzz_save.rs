@@ -0,0 +1,23 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// fuzz/src/bin/fuzz_save.rs - Fuzz reading save files, and check that whatever is read is written
+// back the same way.
+
+use lit_fuzz::fuzz;
+use lost_in_time::SaveData;
+
+fn target(mut data: &[u8]) {
+    let save = match SaveData::read(&mut data) {
+        Ok(save) => save,
+        Err(_) => return,
+    };
+
+    let mut written = vec![];
+    save.write(&mut written)
+        .expect("a save that was read can be written");
+    let reread = SaveData::read(&mut &written[..]).expect("a written save can be read");
+    assert_eq!(save, reread);
+}
+
+fn main() {
+    fuzz("save", target)
+}
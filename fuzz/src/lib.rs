@@ -0,0 +1,224 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// fuzz/src/lib.rs - Small mutational fuzzer for the bytecode reader, which only needs the standard
+// library so that it builds and runs without network access.
+//
+// run a target with "cargo run --release --bin fuzz_eval -- [OPTIONS] [FILE...]". when given
+// files, each one is run once, which is how a saved input is reproduced. otherwise, every input in
+// corpus/<target> is run, followed by random changes to them. options:
+//   --runs=N     number of changed inputs to run, 0 to run forever (default 100000)
+//   --seed=N     seed for the random changes, so that a run can be repeated
+//   --timeout=N  seconds a single input may take (default 5)
+//   --max-mem=N  megabytes a single input may allocate (default 256)
+// inputs that panic, take too long or allocate too much are saved to artifacts/<target>.
+
+#![allow(clippy::new_without_default)]
+
+mod alloc;
+mod mutate;
+
+pub use mutate::{mutate, Rng};
+
+use std::{
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+#[global_allocator]
+static ALLOC: alloc::CountingAlloc = alloc::CountingAlloc;
+
+struct Options {
+    runs: u64,
+    seed: u64,
+    timeout: Duration,
+    max_mem: usize,
+    files: Vec<PathBuf>,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        runs: 100_000,
+        seed: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64),
+        timeout: Duration::from_secs(5),
+        max_mem: 256 << 20,
+        files: vec![],
+    };
+
+    for arg in env::args().skip(1) {
+        if !arg.starts_with("--") {
+            options.files.push(PathBuf::from(arg));
+            continue;
+        }
+
+        let (name, value) = match arg.find('=') {
+            Some(i) => (&arg[2..i], &arg[i + 1..]),
+            None => return Err(format!("Expected a value for {}", arg)),
+        };
+        let value: u64 = value
+            .parse()
+            .map_err(|_| format!("Expected a number for {}", arg))?;
+
+        match name {
+            "runs" => options.runs = value,
+            "seed" => options.seed = value,
+            "timeout" => options.timeout = Duration::from_secs(value),
+            "max-mem" => options.max_mem = (value as usize) << 20,
+            _ => return Err(format!("Unknown option: {}", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+// FNV-1a, to give saved inputs stable names
+fn hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn save(artifacts: &Path, kind: &str, data: &[u8]) {
+    let path = artifacts.join(format!("{}-{:016x}", kind, hash(data)));
+    match fs::create_dir_all(artifacts).and_then(|()| fs::write(&path, data)) {
+        Ok(()) => eprintln!("Saved input to {}", path.display()),
+        Err(e) => eprintln!("Unable to save input to {}: {}", path.display(), e),
+    }
+}
+
+struct Harness {
+    target: fn(&[u8]),
+    artifacts: PathBuf,
+    max_mem: usize,
+    // the input being run and when it started, shared with the watchdog
+    current: Arc<Mutex<(Vec<u8>, Option<Instant>)>>,
+    failures: usize,
+}
+
+impl Harness {
+    // the watchdog can't stop the target, so an input that takes too long ends the whole process
+    fn spawn_watchdog(&self, timeout: Duration) {
+        let current = self.current.clone();
+        let artifacts = self.artifacts.clone();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(100));
+            let current = match current.lock() {
+                Ok(c) => c,
+                Err(_) => return,
+            };
+
+            if let (data, Some(started)) = &*current {
+                if started.elapsed() > timeout {
+                    eprintln!("Input took longer than {:?}", timeout);
+                    save(&artifacts, "timeout", data);
+                    process::exit(2);
+                }
+            }
+        });
+    }
+
+    fn run(&mut self, data: &[u8]) {
+        if let Ok(mut current) = self.current.lock() {
+            current.0.clear();
+            current.0.extend_from_slice(data);
+            current.1 = Some(Instant::now());
+        }
+
+        let target = self.target;
+        let base = alloc::reset_peak();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| target(data)));
+        let used = alloc::peak().saturating_sub(base);
+
+        if let Ok(mut current) = self.current.lock() {
+            current.1 = None;
+        }
+
+        if res.is_err() {
+            self.failures += 1;
+            save(&self.artifacts, "crash", data);
+        } else if used > self.max_mem {
+            self.failures += 1;
+            eprintln!("Input allocated {} bytes", used);
+            save(&self.artifacts, "oom", data);
+        }
+    }
+}
+
+// read every input in a corpus directory, in a stable order
+fn read_corpus(dir: &Path) -> Vec<Vec<u8>> {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return vec![],
+    };
+    paths.sort();
+    paths.iter().filter_map(|p| fs::read(p).ok()).collect()
+}
+
+/// Run a fuzz target, then exit. The process fails if any input caused a problem.
+pub fn fuzz(name: &str, target: fn(&[u8])) -> ! {
+    let options = match parse_options() {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut harness = Harness {
+        target,
+        artifacts: root.join("artifacts").join(name),
+        max_mem: options.max_mem,
+        current: Arc::new(Mutex::new((vec![], None))),
+        failures: 0,
+    };
+    harness.spawn_watchdog(options.timeout);
+
+    // reproduce saved inputs
+    if !options.files.is_empty() {
+        for file in &options.files {
+            match fs::read(file) {
+                Ok(data) => {
+                    println!("Running {}", file.display());
+                    harness.run(&data);
+                }
+                Err(e) => eprintln!("Unable to read {}: {}", file.display(), e),
+            }
+        }
+        process::exit(if harness.failures == 0 { 0 } else { 1 });
+    }
+
+    // the corpus comes first, since it includes inputs for problems that have been fixed
+    let mut corpus = read_corpus(&root.join("corpus").join(name));
+    println!("Running {} corpus inputs for {}", corpus.len(), name);
+    for data in &corpus {
+        harness.run(data);
+    }
+    if corpus.is_empty() {
+        corpus.push(vec![]);
+    }
+
+    println!("Seed: {}", options.seed);
+    let mut rng = Rng::new(options.seed);
+    let mut run = 0;
+    while options.runs == 0 || run < options.runs {
+        let mut data = corpus[rng.below(corpus.len())].clone();
+        for _ in 0..=rng.below(4) {
+            mutate(&mut data, &mut rng);
+        }
+        harness.run(&data);
+
+        run += 1;
+        if run % 10_000 == 0 {
+            println!("{} runs, {} failures", run, harness.failures);
+        }
+    }
+
+    println!("{} runs, {} failures", run, harness.failures);
+    process::exit(if harness.failures == 0 { 0 } else { 1 });
+}
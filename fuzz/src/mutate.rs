@@ -0,0 +1,62 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// fuzz/src/mutate.rs - Random changes to inputs taken from the corpus.
+
+/// Small xorshift generator, so that a run can be repeated from its seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on zero
+        Self(seed | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in 0..max. max must not be zero.
+    #[inline]
+    pub fn below(&mut self, max: usize) -> usize {
+        (self.next_u64() % max as u64) as usize
+    }
+}
+
+// values that tend to sit on the edge of a length or a range
+const INTERESTING: &[u8] = &[0x00, 0x01, 0x02, 0x05, 0x06, 0x7F, 0x80, 0xFE, 0xFF];
+
+/// Apply a single random change to an input.
+pub fn mutate(data: &mut Vec<u8>, rng: &mut Rng) {
+    if data.is_empty() {
+        data.push(rng.next_u64() as u8);
+        return;
+    }
+
+    let pos = rng.below(data.len());
+    match rng.below(7) {
+        // flip a bit
+        0 => data[pos] ^= 1 << rng.below(8),
+        // replace a byte
+        1 => data[pos] = rng.next_u64() as u8,
+        // replace a byte with one that is likely to matter
+        2 => data[pos] = INTERESTING[rng.below(INTERESTING.len())],
+        // insert a byte
+        3 => data.insert(pos, rng.next_u64() as u8),
+        // remove some bytes
+        4 => {
+            let len = 1 + rng.below((data.len() - pos).min(16));
+            data.drain(pos..pos + len);
+        }
+        // copy a chunk to somewhere else
+        5 => {
+            let len = 1 + rng.below((data.len() - pos).min(64));
+            let chunk = data[pos..pos + len].to_vec();
+            let to = rng.below(data.len() + 1);
+            data.splice(to..to, chunk);
+        }
+        // cut the input short
+        _ => data.truncate(pos),
+    }
+}
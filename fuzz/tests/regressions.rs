@@ -0,0 +1,78 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// fuzz/tests/regressions.rs - Load every input that once made a fuzz target crash, hang or run out
+// of memory, so that "cargo test" notices when one of the fixes comes undone.
+
+use lost_in_time::{eval, BytecodeObject, GameData, Limits, LoadPolicy, ParserState, Vm};
+use std::{fs, path::Path};
+
+const POLICIES: [LoadPolicy; 3] = [
+    LoadPolicy::Strict,
+    LoadPolicy::Lenient,
+    LoadPolicy::ReportOnly,
+];
+
+// the inputs that were saved from a target's failures are named "regression-*"
+fn regressions(target: &str) -> Vec<(String, Vec<u8>)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("corpus")
+        .join(target);
+    let mut inputs: Vec<(String, Vec<u8>)> = fs::read_dir(&dir)
+        .expect("the corpus can be read")
+        .map(|entry| entry.expect("the corpus can be read").path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("regression-"))
+        })
+        .map(|path| {
+            let data = fs::read(&path).expect("a regression can be read");
+            (path.display().to_string(), data)
+        })
+        .collect();
+
+    assert!(!inputs.is_empty(), "{} has no regressions", dir.display());
+    inputs.sort();
+    inputs
+}
+
+#[test]
+fn game_data_regressions() {
+    for (name, data) in regressions("game_data") {
+        for policy in POLICIES.iter().copied() {
+            println!("{} ({:?})", name, policy);
+            let mut game_data =
+                match GameData::read_layers_with(&mut [&data[..]], policy, Limits::new()) {
+                    Ok(d) => d,
+                    Err(_) => continue,
+                };
+
+            let mut vm = Vm::new();
+            let _ = game_data.run_script(&mut vm, "on_start");
+            let _ = game_data.run_script(&mut vm, "on_update");
+        }
+    }
+}
+
+#[test]
+fn eval_regressions() {
+    // the same inputs, evaluated without being verified first
+    for (name, data) in regressions("eval") {
+        println!("{}", name);
+        let mut game_data = GameData::new();
+        let mut state = ParserState::new();
+        let mut stream = &data[..];
+        while let Ok(true) = eval(&mut stream, &mut game_data, &mut state) {}
+    }
+}
+
+#[test]
+fn object_regressions() {
+    let state = ParserState::new();
+    for (name, data) in regressions("object") {
+        println!("{}", name);
+        if let Ok(object) = BytecodeObject::read_limited(&mut &data[..], &Limits::new()) {
+            let _ = object.stringify(&state);
+            let _ = object.as_color(&state);
+        }
+    }
+}
@@ -0,0 +1,27 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lit-fmt/src/error.rs - Errors in format strings
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum FormatError {
+    #[error("Unmatched '{{' at position {0} of the format string")]
+    UnmatchedOpen(usize),
+    #[error("Unmatched '}}' at position {0} of the format string, use '}}}}' for a literal brace")]
+    UnmatchedClose(usize),
+    #[error("Invalid argument \"{1}\" at position {0} of the format string")]
+    InvalidArgument(usize, String),
+    #[error("Invalid format specifier \"{1}\" at position {0} of the format string")]
+    InvalidSpec(usize, String),
+    #[error(
+        "Width or precision {1} at position {0} of the format string is larger than {}",
+        crate::MAX_WIDTH
+    )]
+    TooWide(usize, usize),
+    #[error("Format string refers to argument {0}, but there are only {1} arguments")]
+    MissingArgument(usize, usize),
+    #[error("Argument {0} is never used by the format string")]
+    UnusedArgument(usize),
+    #[error("{0} formatting can only be used with numbers")]
+    NotANumber(&'static str),
+}
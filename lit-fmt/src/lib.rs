@@ -0,0 +1,113 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lit-fmt/src/lib.rs - Format strings for the log command, shared by the compiler and the engine
+//
+// the syntax follows Rust's: "{}" takes the next argument, "{0}" takes an argument by its index
+// and "{NAME}" takes a variable by its name. after a colon comes [[fill]align][+][#][0][width]
+// [.precision][type], where align is one of "<^>" and type is one of "xXbo". "{{" and "}}" are
+// literal braces.
+
+#![allow(clippy::new_without_default)]
+
+mod error;
+pub use error::FormatError;
+
+mod parse;
+pub use parse::{parse, Align, Argument, Kind, Piece, Spec, MAX_WIDTH};
+
+mod write;
+pub use write::{write_value, Value};
+
+/// Make sure that the arguments a format string refers to match the number of arguments given.
+/// Every argument has to exist, and every argument has to be used. The engine only requires the
+/// arguments to exist, so this is checked by the compiler.
+pub fn check_args(pieces: &[Piece<'_>], count: usize) -> Result<(), FormatError> {
+    let mut used = vec![false; count];
+
+    for piece in pieces {
+        if let Piece::Argument {
+            arg: Argument::Index(i),
+            ..
+        } = *piece
+        {
+            match used.get_mut(i) {
+                Some(u) => *u = true,
+                None => return Err(FormatError::MissingArgument(i, count)),
+            }
+        }
+    }
+
+    match used.iter().position(|u| !u) {
+        Some(i) => Err(FormatError::UnusedArgument(i)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: &str, values: &[Value<'_>]) -> Result<String, FormatError> {
+        let mut out = String::new();
+        for piece in parse(format)? {
+            match piece {
+                Piece::Literal(text) => out.push_str(text),
+                Piece::Argument {
+                    arg: Argument::Index(i),
+                    spec,
+                } => write_value(&mut out, values[i], &spec)?,
+                Piece::Argument { .. } => unreachable!(),
+            }
+        }
+        Ok(out)
+    }
+
+    #[test]
+    fn specs_are_written_like_rust() {
+        let n = Value::Number(-42);
+        assert_eq!(format("{}", &[n]).unwrap(), format!("{}", -42));
+        assert_eq!(format("{:>6}", &[n]).unwrap(), format!("{:>6}", -42));
+        assert_eq!(format("{:06}", &[n]).unwrap(), format!("{:06}", -42));
+        assert_eq!(format("{:#x}", &[n]).unwrap(), format!("{:#x}", -42));
+        assert_eq!(
+            format("{:*^9}", &[Value::Str("ab")]).unwrap(),
+            format!("{:*^9}", "ab")
+        );
+        assert_eq!(format("{:.2}", &[Value::Str("abc")]).unwrap(), "ab");
+        assert_eq!(format("{{{}}}", &[Value::Number(1)]).unwrap(), "{1}");
+    }
+
+    #[test]
+    fn width_and_precision_are_capped() {
+        let width = format!("{{:{}}}", MAX_WIDTH);
+        assert_eq!(
+            format(&width, &[Value::Number(1)]).unwrap().len(),
+            MAX_WIDTH
+        );
+
+        let pos = 2;
+        let over = MAX_WIDTH + 1;
+        assert_eq!(
+            parse(&format!("{{:{}}}", over)),
+            Err(FormatError::TooWide(pos, over))
+        );
+        assert_eq!(
+            parse(&format!("{{:.{}}}", over)),
+            Err(FormatError::TooWide(pos, over))
+        );
+        assert_eq!(
+            parse("{:99999999999999999999999}"),
+            Err(FormatError::TooWide(pos, usize::MAX))
+        );
+    }
+
+    #[test]
+    fn arguments_are_checked() {
+        let pieces = parse("{} {1}").unwrap();
+        assert_eq!(check_args(&pieces, 2), Ok(()));
+        assert_eq!(
+            check_args(&pieces, 1),
+            Err(FormatError::MissingArgument(1, 1))
+        );
+        assert_eq!(check_args(&pieces, 3), Err(FormatError::UnusedArgument(2)));
+    }
+}
@@ -0,0 +1,246 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lit-fmt/src/parse.rs - Split a format string into literal text and arguments
+
+use crate::FormatError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Display,
+    LowerHex,
+    UpperHex,
+    Binary,
+    Octal,
+}
+
+impl Kind {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Display => "Display",
+            Kind::LowerHex | Kind::UpperHex => "Hexadecimal",
+            Kind::Binary => "Binary",
+            Kind::Octal => "Octal",
+        }
+    }
+}
+
+/// Everything after the colon in an argument, e.g. "{:>8}" or "{:#06x}".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spec {
+    pub fill: char,
+    /// The alignment within the width. If not given, numbers are aligned right and everything
+    /// else is aligned left.
+    pub align: Option<Align>,
+    /// Always write the sign of a number.
+    pub plus: bool,
+    /// Prefix hexadecimal, binary and octal numbers with 0x, 0b or 0o.
+    pub alternate: bool,
+    /// Pad numbers with zeroes after their sign, instead of using the fill.
+    pub zero: bool,
+    pub width: Option<usize>,
+    /// The most characters of a string to write, or the fewest digits of a number.
+    pub precision: Option<usize>,
+    pub kind: Kind,
+}
+
+impl Spec {
+    pub fn new() -> Self {
+        Self {
+            fill: ' ',
+            align: None,
+            plus: false,
+            alternate: false,
+            zero: false,
+            width: None,
+            precision: None,
+            kind: Kind::Display,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Argument<'a> {
+    /// An element of the argument tuple. "{}" is given the next index that hasn't been used by
+    /// another "{}".
+    Index(usize),
+    /// A variable, looked up by its name.
+    Name(&'a str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Piece<'a> {
+    Literal(&'a str),
+    Argument { arg: Argument<'a>, spec: Spec },
+}
+
+#[inline]
+fn align(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '^' => Some(Align::Center),
+        '>' => Some(Align::Right),
+        _ => None,
+    }
+}
+
+/// The largest width or precision a format string may ask for. Format strings come from data
+/// files, and each column of padding or leading zero is written out.
+pub const MAX_WIDTH: usize = 1024;
+
+// read a run of digits from the front of a string. digits that don't fit are too large anyway
+fn number(s: &str) -> (Option<usize>, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let n = match end {
+        0 => None,
+        _ => Some(s[..end].parse().unwrap_or(usize::MAX)),
+    };
+    (n, &s[end..])
+}
+
+#[inline]
+fn check_width(pos: usize, n: usize) -> Result<usize, FormatError> {
+    if n > MAX_WIDTH {
+        return Err(FormatError::TooWide(pos, n));
+    }
+    Ok(n)
+}
+
+fn parse_argument<'a>(
+    pos: usize,
+    arg: &'a str,
+    next: &mut usize,
+) -> Result<Argument<'a>, FormatError> {
+    let mut chars = arg.chars();
+    match chars.next() {
+        None => {
+            *next += 1;
+            Ok(Argument::Index(*next - 1))
+        }
+        Some(c) if c.is_ascii_digit() => arg
+            .parse()
+            .map(Argument::Index)
+            .map_err(|_| FormatError::InvalidArgument(pos, arg.to_string())),
+        Some(c) if (c.is_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_') =>
+        {
+            Ok(Argument::Name(arg))
+        }
+        _ => Err(FormatError::InvalidArgument(pos, arg.to_string())),
+    }
+}
+
+fn parse_spec(pos: usize, s: &str) -> Result<Spec, FormatError> {
+    let mut spec = Spec::new();
+    let invalid = || FormatError::InvalidSpec(pos, s.to_string());
+    let mut rest = s;
+
+    // an alignment, possibly with a fill character in front of it
+    let mut chars = rest.chars();
+    match (chars.next(), chars.next()) {
+        (Some(fill), Some(a)) if align(a).is_some() => {
+            spec.fill = fill;
+            spec.align = align(a);
+            rest = &rest[fill.len_utf8() + 1..];
+        }
+        (Some(a), _) if align(a).is_some() => {
+            spec.align = align(a);
+            rest = &rest[1..];
+        }
+        _ => {}
+    }
+
+    if rest.starts_with('+') {
+        spec.plus = true;
+        rest = &rest[1..];
+    }
+    if rest.starts_with('#') {
+        spec.alternate = true;
+        rest = &rest[1..];
+    }
+    if rest.starts_with('0') {
+        spec.zero = true;
+        rest = &rest[1..];
+    }
+
+    let (width, after) = number(rest);
+    spec.width = width.map(|w| check_width(pos, w)).transpose()?;
+    rest = after;
+
+    if rest.starts_with('.') {
+        let (precision, after) = number(&rest[1..]);
+        spec.precision = Some(check_width(pos, precision.ok_or_else(invalid)?)?);
+        rest = after;
+    }
+
+    spec.kind = match rest {
+        "" => Kind::Display,
+        "x" => Kind::LowerHex,
+        "X" => Kind::UpperHex,
+        "b" => Kind::Binary,
+        "o" => Kind::Octal,
+        _ => return Err(invalid()),
+    };
+
+    Ok(spec)
+}
+
+#[inline]
+fn push_literal<'a>(pieces: &mut Vec<Piece<'a>>, text: &'a str) {
+    if !text.is_empty() {
+        pieces.push(Piece::Literal(text));
+    }
+}
+
+/// Parse a format string. Braces are escaped by doubling them.
+pub fn parse(format: &str) -> Result<Vec<Piece<'_>>, FormatError> {
+    let bytes = format.as_bytes();
+    let mut pieces = vec![];
+    let mut next = 0;
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            // keep the first of a pair of braces as part of the literal
+            b'{' | b'}' if bytes.get(i + 1) == Some(&bytes[i]) => {
+                push_literal(&mut pieces, &format[start..=i]);
+                i += 2;
+                start = i;
+            }
+            b'}' => return Err(FormatError::UnmatchedClose(i)),
+            b'{' => {
+                push_literal(&mut pieces, &format[start..i]);
+
+                let end = match format[i + 1..].find(['{', '}']) {
+                    Some(e) if bytes[i + 1 + e] == b'}' => i + 1 + e,
+                    _ => return Err(FormatError::UnmatchedOpen(i)),
+                };
+                let inner = &format[i + 1..end];
+
+                let (arg, spec) = match inner.find(':') {
+                    Some(colon) => (
+                        &inner[..colon],
+                        parse_spec(i + 2 + colon, &inner[colon + 1..])?,
+                    ),
+                    None => (inner, Spec::new()),
+                };
+                let arg = parse_argument(i + 1, arg, &mut next)?;
+                pieces.push(Piece::Argument { arg, spec });
+
+                i = end + 1;
+                start = i;
+            }
+            _ => i += 1,
+        }
+    }
+
+    push_literal(&mut pieces, &format[start..]);
+    Ok(pieces)
+}
@@ -0,0 +1,90 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lit-fmt/src/write.rs - Write a single argument according to its format specifier
+
+use crate::{Align, FormatError, Kind, Spec};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value<'a> {
+    Number(i32),
+    Str(&'a str),
+}
+
+fn pad(out: &mut String, fill: char, count: usize) {
+    for _ in 0..count {
+        out.push(fill);
+    }
+}
+
+/// Write a value to a string.
+pub fn write_value(out: &mut String, value: Value<'_>, spec: &Spec) -> Result<(), FormatError> {
+    // split the value into a sign, a prefix and the rest, so that zeroes go in between
+    let (sign, prefix, body, default_align) = match value {
+        Value::Number(n) => {
+            // like Rust, negative numbers are written in two's complement unless they're decimal
+            let (prefix, mut digits) = match spec.kind {
+                Kind::Display => ("", n.unsigned_abs().to_string()),
+                Kind::LowerHex => ("0x", format!("{:x}", n as u32)),
+                Kind::UpperHex => ("0x", format!("{:X}", n as u32)),
+                Kind::Binary => ("0b", format!("{:b}", n as u32)),
+                Kind::Octal => ("0o", format!("{:o}", n as u32)),
+            };
+
+            if let Some(precision) = spec.precision {
+                if digits.len() < precision {
+                    digits.insert_str(0, &"0".repeat(precision - digits.len()));
+                }
+            }
+
+            let sign = if spec.kind == Kind::Display && n < 0 {
+                "-"
+            } else if spec.plus {
+                "+"
+            } else {
+                ""
+            };
+
+            (
+                sign,
+                if spec.alternate { prefix } else { "" },
+                digits,
+                Align::Right,
+            )
+        }
+        Value::Str(s) => {
+            if spec.kind != Kind::Display {
+                return Err(FormatError::NotANumber(spec.kind.name()));
+            }
+
+            let body = match spec.precision {
+                Some(p) => s.chars().take(p).collect(),
+                None => s.to_string(),
+            };
+            ("", "", body, Align::Left)
+        }
+    };
+
+    let len = sign.len() + prefix.len() + body.chars().count();
+    let padding = spec.width.map_or(0, |w| w.saturating_sub(len));
+
+    if spec.zero && default_align == Align::Right {
+        // zeroes go after the sign and prefix, no matter the alignment
+        out.push_str(sign);
+        out.push_str(prefix);
+        pad(out, '0', padding);
+        out.push_str(&body);
+        return Ok(());
+    }
+
+    let (before, after) = match spec.align.unwrap_or(default_align) {
+        Align::Left => (0, padding),
+        Align::Center => (padding / 2, padding - padding / 2),
+        Align::Right => (padding, 0),
+    };
+
+    pad(out, spec.fill, before);
+    out.push_str(sign);
+    out.push_str(prefix);
+    out.push_str(&body);
+    pad(out, spec.fill, after);
+    Ok(())
+}
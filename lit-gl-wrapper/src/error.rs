@@ -1,6 +1,7 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // gl_utils/texture/error.rs - Check for GL errors.
 
+#[cfg(feature = "window")]
 use sdl2::video::WindowBuildError;
 use std::{
     boxed::Box,
@@ -35,6 +36,10 @@ pub enum GlCall {
     GetShaderInfoLog,
     TexImage1D,
     TexImage2D,
+    TexSubImage1D,
+    TexParameteri,
+    PixelStorei,
+    GetIntegerv,
     FramebufferTexture2D,
     Viewport,
     Uniform1i,
@@ -100,6 +105,7 @@ pub enum GlError {
     UniformNotFound(&'static str),
     #[error("{0}")]
     GlCall(#[from] GlCallError),
+    #[cfg(feature = "window")]
     #[error("{0}")]
     WindowBuild(#[from] WindowBuildError),
     #[error("{0}")]
@@ -2,7 +2,7 @@
 // lit-gl-wrapper/src/gl_renderer.rs - Renderer based on SDL2 and OpenGL
 
 use super::{Program, Quad, Shader, ShaderType};
-use crate::{GlError, ImgTexture, Renderer};
+use crate::{GlError, ImgTexture, IndexTexture, InputEvent, PaletteTexture, Renderer};
 use gl::types::GLfloat;
 use nalgebra::{
     base::{Matrix4, Unit, Vector3, Vector4},
@@ -21,6 +21,7 @@ pub struct GlRenderer {
     _gl_context: GLContext,
     gl: gl::Gl,
     sprite: Program,
+    indexed: Program,
 
     quad: Quad,
 }
@@ -62,12 +63,28 @@ impl GlRenderer {
             Program::new(&gl, &[vert, frag]).expect("Shader linking failed")
         };
 
+        // indexed sprites share the vertex shader, but look their colors up in a palette
+        let indexed = {
+            let vert_source = include_str!("./../../shaders/sprite.vert");
+            let frag_source = include_str!("./../../shaders/indexed.frag");
+            let vert = Shader::from_source(&gl, &vert_source, ShaderType::Vertex)
+                .expect("Vertex shader compilation failed");
+            let frag = Shader::from_source(&gl, &frag_source, ShaderType::Fragment)
+                .expect("Fragment shader compilation failed");
+            Program::new(&gl, &[vert, frag]).expect("Shader linking failed")
+        };
+
         // initialize the sprite render process
         let ortho =
             Orthographic3::<GLfloat>::new(0.0, WIDTH as GLfloat, HEIGHT as GLfloat, 0.0, -1.0, 1.0);
         sprite.activate()?;
         sprite.set_uniform("ortho", ortho.into_inner())?;
 
+        indexed.activate()?;
+        indexed.set_uniform("ortho", ortho.into_inner())?;
+        indexed.set_uniform("image", 0)?;
+        indexed.set_uniform("palette", 1)?;
+
         let quad = Quad::new(&gl)?;
         quad.bind(true)?;
         quad.unbind()?;
@@ -78,9 +95,61 @@ impl GlRenderer {
             _gl_context: gl_context,
             quad,
             sprite,
+            indexed,
             gl,
         })
     }
+
+    /// Get the OpenGL functions, to create textures with.
+    #[inline]
+    pub fn gl(&self) -> &gl::Gl {
+        &self.gl
+    }
+
+    /// The size of the window, in pixels.
+    #[inline]
+    pub fn window_size(&self) -> (u32, u32) {
+        self.window.size()
+    }
+
+    /// Clear the window, ready to draw a frame.
+    #[inline]
+    pub fn clear(&self) {
+        unsafe { self.gl.Clear(gl::COLOR_BUFFER_BIT) };
+    }
+
+    /// Show what has been drawn since the window was last cleared. The main loop does this by
+    /// itself, so this is only needed to draw outside of it.
+    #[inline]
+    pub fn present(&self) {
+        self.window.gl_swap_window();
+    }
+
+    // get the transform that moves the quad into place
+    fn sprite_transform(
+        position: Point2<GLfloat>,
+        size: Point2<GLfloat>,
+        rotation: GLfloat,
+    ) -> Transform3<GLfloat> {
+        let mut transform = Transform3::<GLfloat>::identity();
+
+        // shift position
+        transform *= Translation3::from(Vector3::new(position.x, position.y, 0.0));
+
+        // shift size
+        transform *= Translation3::from(Vector3::new(0.5 * size.x, 0.5 * size.y, 0.0));
+
+        // rotate matrix
+        transform *= Rotation3::from_axis_angle(&Unit::new_normalize(Vector3::z()), rotation);
+        transform *= Translation3::from(Vector3::new(-0.5 * size.x, -0.5 * size.y, 0.0));
+
+        // resize with size
+        transform *= Transform3::from_matrix_unchecked(Matrix4::from_diagonal(&Vector4::new(
+            size.x, size.y, 1.0, 1.0,
+        )));
+
+        transform
+    }
 }
 
 impl Renderer for GlRenderer {
@@ -88,7 +157,7 @@ impl Renderer for GlRenderer {
 
     fn main_loop<F>(&self, mut loop_function: F) -> Result<(), GlError>
     where
-        F: FnMut(&Self) -> Result<(), Box<dyn std::error::Error>>,
+        F: FnMut(&Self, &[InputEvent]) -> Result<bool, Box<dyn std::error::Error>>,
     {
         let mut event_pump = self.sdl_context.event_pump().map_err(GlError::Msg)?;
 
@@ -96,20 +165,33 @@ impl Renderer for GlRenderer {
         unsafe { self.gl.ClearColor(1.0, 1.0, 1.0, 1.0) };
 
         // main loop
+        let mut input = vec![];
         'lit: loop {
+            input.clear();
             for event in event_pump.poll_iter() {
                 // process the event
                 match event {
                     Event::Quit { .. } => break 'lit,
+                    // held keys repeat, but only the first press counts
+                    Event::KeyDown {
+                        keycode: Some(key),
+                        repeat: false,
+                        ..
+                    } => input.push(InputEvent::KeyDown(key as i32)),
+                    Event::KeyUp {
+                        keycode: Some(key), ..
+                    } => input.push(InputEvent::KeyUp(key as i32)),
                     _ => {}
                 }
             }
 
-            unsafe { self.gl.Clear(gl::COLOR_BUFFER_BIT) };
+            self.clear();
 
-            loop_function(self).map_err(GlError::GenericError)?;
+            if !loop_function(self, &input).map_err(GlError::GenericError)? {
+                break;
+            }
 
-            self.window.gl_swap_window();
+            self.present();
         }
 
         Ok(())
@@ -123,26 +205,33 @@ impl Renderer for GlRenderer {
         rotation: GLfloat,
     ) -> Result<(), GlError> {
         self.sprite.activate()?;
+        self.sprite
+            .set_uniform("transf", Self::sprite_transform(position, size, rotation))?;
 
-        let mut transform = Transform3::<GLfloat>::identity();
-
-        // shift position
-        transform *= Translation3::from(Vector3::new(position.x, position.y, 0.0));
-
-        // shift size
-        transform *= Translation3::from(Vector3::new(0.5 * size.x, 0.5 * size.y, 0.0));
+        unsafe { self.gl.ActiveTexture(gl::TEXTURE0) };
+        img.bind()?;
 
-        // rotate matrix
-        transform *= Rotation3::from_axis_angle(&Unit::new_normalize(Vector3::z()), rotation);
-        transform *= Translation3::from(Vector3::new(-0.5 * size.x, -0.5 * size.y, 0.0));
+        self.quad.bind(false)?;
+        self.quad.draw()?;
+        self.quad.unbind()?;
 
-        // resize with size
-        transform *= Transform3::from_matrix_unchecked(Matrix4::from_diagonal(&Vector4::new(
-            size.x, size.y, 1.0, 1.0,
-        )));
+        Ok(())
+    }
 
-        self.sprite.set_uniform("transf", transform)?;
+    fn draw_indexed_sprite(
+        &self,
+        img: &IndexTexture,
+        palette: &PaletteTexture,
+        position: Point2<GLfloat>,
+        size: Point2<GLfloat>,
+        rotation: GLfloat,
+    ) -> Result<(), GlError> {
+        self.indexed.activate()?;
+        self.indexed
+            .set_uniform("transf", Self::sprite_transform(position, size, rotation))?;
 
+        unsafe { self.gl.ActiveTexture(gl::TEXTURE1) };
+        palette.bind()?;
         unsafe { self.gl.ActiveTexture(gl::TEXTURE0) };
         img.bind()?;
 
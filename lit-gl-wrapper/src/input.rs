@@ -0,0 +1,9 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lit-gl-wrapper/src/input.rs - Input events, independent of the windowing library.
+
+/// Something the player did during a frame. Keys are identified by their SDL keycodes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum InputEvent {
+    KeyDown(i32),
+    KeyUp(i32),
+}
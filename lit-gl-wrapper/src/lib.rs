@@ -9,9 +9,14 @@ pub use error::*;
 mod frame_buffer;
 pub use frame_buffer::FrameBuffer;
 
+#[cfg(feature = "window")]
 mod gl_renderer;
+#[cfg(feature = "window")]
 pub use gl_renderer::*;
 
+mod input;
+pub use input::InputEvent;
+
 mod renderer;
 pub use renderer::Renderer;
 
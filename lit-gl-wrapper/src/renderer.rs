@@ -1,16 +1,18 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // renderer.rs - Renderer trait.
 
-use crate::ImgTexture;
+use crate::{ImgTexture, IndexTexture, InputEvent, PaletteTexture};
 use nalgebra::geometry::Point2;
 use std::{boxed::Box, error::Error};
 
 pub trait Renderer {
     type Error: Error;
 
+    /// Run a function every frame, with the input events of that frame, until the window is
+    /// closed or the function returns false.
     fn main_loop<F>(&self, f: F) -> Result<(), Self::Error>
     where
-        F: FnMut(&Self) -> Result<(), Box<dyn Error>>;
+        F: FnMut(&Self, &[InputEvent]) -> Result<bool, Box<dyn Error>>;
     fn draw_sprite(
         &self,
         img: &ImgTexture,
@@ -18,4 +20,13 @@ pub trait Renderer {
         size: Point2<f32>,
         rotation: f32,
     ) -> Result<(), Self::Error>;
+    /// Draw a texture of palette indices, looking its colors up in a palette.
+    fn draw_indexed_sprite(
+        &self,
+        img: &IndexTexture,
+        palette: &PaletteTexture,
+        position: Point2<f32>,
+        size: Point2<f32>,
+        rotation: f32,
+    ) -> Result<(), Self::Error>;
 }
@@ -6,7 +6,7 @@ use super::{
     TextureType,
 };
 use crate::GlError;
-use gl::types::{GLenum, GLfloat, GLint};
+use gl::types::{GLenum, GLint, GLubyte};
 use std::ffi::c_void;
 
 #[derive(Debug, Clone)]
@@ -51,7 +51,7 @@ impl TextureType for DIBufferType {
 pub struct ImgTextureType;
 
 impl TextureType for ImgTextureType {
-    type ValueType = GLfloat;
+    type ValueType = GLubyte;
 
     #[inline]
     fn bind_texture_location() -> GLenum {
@@ -60,10 +60,10 @@ impl TextureType for ImgTextureType {
 
     #[inline]
     fn tex_type() -> GLenum {
-        gl::FLOAT
+        gl::UNSIGNED_BYTE
     }
 
-    fn tex_image(gl: &gl::Gl, dimensions: &[u32], data: *const GLfloat) -> Result<(), GlError> {
+    fn tex_image(gl: &gl::Gl, dimensions: &[u32], data: *const GLubyte) -> Result<(), GlError> {
         if dimensions.len() != 2 {
             return Err(GlError::ImproperDimensions(2, dimensions.len()));
         }
@@ -85,3 +85,107 @@ impl TextureType for ImgTextureType {
         check_gl_error(gl, GlCall::TexImage2D)
     }
 }
+
+// sets nearest filtering on the bound texture, since indices and palette entries can't be blended
+fn nearest_filter(gl: &gl::Gl, location: GLenum) -> Result<(), GlError> {
+    for param in [gl::TEXTURE_MIN_FILTER, gl::TEXTURE_MAG_FILTER].iter() {
+        unsafe { gl.TexParameteri(location, *param, gl::NEAREST as GLint) };
+        check_gl_error(gl, GlCall::TexParameteri)?;
+    }
+
+    Ok(())
+}
+
+/// A texture of palette indices, one byte per pixel.
+#[derive(Debug, Clone)]
+pub struct IndexTextureType;
+
+impl TextureType for IndexTextureType {
+    type ValueType = GLubyte;
+
+    #[inline]
+    fn bind_texture_location() -> GLenum {
+        gl::TEXTURE_2D
+    }
+
+    #[inline]
+    fn tex_type() -> GLenum {
+        gl::UNSIGNED_BYTE
+    }
+
+    fn tex_image(gl: &gl::Gl, dimensions: &[u32], data: *const GLubyte) -> Result<(), GlError> {
+        if dimensions.len() != 2 {
+            return Err(GlError::ImproperDimensions(2, dimensions.len()));
+        }
+
+        // rows are one byte per pixel, so they aren't aligned to four bytes. the alignment is put
+        // back afterwards, since every other texture is uploaded with the default of four
+        let mut alignment: GLint = 4;
+        unsafe { gl.GetIntegerv(gl::UNPACK_ALIGNMENT, &mut alignment) };
+        check_gl_error(gl, GlCall::GetIntegerv)?;
+        unsafe { gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1) };
+        check_gl_error(gl, GlCall::PixelStorei)?;
+
+        unsafe {
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::R8 as GLint,
+                dimensions[0] as GLint,
+                dimensions[1] as GLint,
+                0,
+                gl::RED,
+                gl::UNSIGNED_BYTE,
+                data as *const c_void,
+            )
+        };
+        let uploaded = check_gl_error(gl, GlCall::TexImage2D);
+
+        // even if the upload failed
+        unsafe { gl.PixelStorei(gl::UNPACK_ALIGNMENT, alignment) };
+        check_gl_error(gl, GlCall::PixelStorei)?;
+        uploaded?;
+
+        nearest_filter(gl, gl::TEXTURE_2D)
+    }
+}
+
+/// A row of RGBA colors, four bytes each, that index textures look their colors up in.
+#[derive(Debug, Clone)]
+pub struct PaletteTextureType;
+
+impl TextureType for PaletteTextureType {
+    type ValueType = GLubyte;
+
+    #[inline]
+    fn bind_texture_location() -> GLenum {
+        gl::TEXTURE_1D
+    }
+
+    #[inline]
+    fn tex_type() -> GLenum {
+        gl::UNSIGNED_BYTE
+    }
+
+    fn tex_image(gl: &gl::Gl, dimensions: &[u32], data: *const GLubyte) -> Result<(), GlError> {
+        if dimensions.len() != 1 {
+            return Err(GlError::ImproperDimensions(1, dimensions.len()));
+        }
+
+        unsafe {
+            gl.TexImage1D(
+                gl::TEXTURE_1D,
+                0,
+                gl::RGBA8 as GLint,
+                dimensions[0] as GLint,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                data as *const c_void,
+            )
+        };
+        check_gl_error(gl, GlCall::TexImage1D)?;
+
+        nearest_filter(gl, gl::TEXTURE_1D)
+    }
+}
@@ -2,7 +2,7 @@
 // gl_utils/texture/mod.rs - OpenGL texture
 
 use crate::{check_gl_error, GlCall, GlError};
-use gl::types::{GLenum, GLuint};
+use gl::types::{GLenum, GLint, GLuint};
 use std::{ffi::c_void, fmt, marker::PhantomData};
 
 mod dimensions;
@@ -71,6 +71,36 @@ impl<T: TextureType> Texture<T> {
         unsafe { self.gl.BindTexture(T::bind_texture_location(), 0) };
         check_gl_error(&self.gl, GlCall::BindTexture)
     }
+
+    #[inline]
+    pub fn dimensions(&self) -> &[u32] {
+        &self.dimensions
+    }
+}
+
+impl Texture<PaletteTextureType> {
+    /// Replace the colors of a palette, without creating a new texture.
+    pub fn update(&self, data: &[u8]) -> Result<(), GlError> {
+        let len = self.dimensions[0] as usize;
+        if data.len() != len * 4 {
+            return Err(GlError::ImproperDimensions(len * 4, data.len()));
+        }
+
+        self.bind()?;
+        unsafe {
+            self.gl.TexSubImage1D(
+                gl::TEXTURE_1D,
+                0,
+                0,
+                len as GLint,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                data.as_ptr() as *const c_void,
+            )
+        };
+        check_gl_error(&self.gl, GlCall::TexSubImage1D)?;
+        self.unbind()
+    }
 }
 
 impl<T: TextureType> Drop for Texture<T> {
@@ -113,3 +143,5 @@ impl<T: TextureType> fmt::Debug for Texture<T> {
 // some specific types
 pub type ImgTexture = Texture<ImgTextureType>;
 pub type DIBuffer = Texture<DIBufferType>;
+pub type IndexTexture = Texture<IndexTextureType>;
+pub type PaletteTexture = Texture<PaletteTextureType>;
@@ -0,0 +1,201 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lit-lz/src/block.rs - LZ77 block codec, loosely modelled after the LZ4 block format
+
+use crate::LzError;
+
+// a block is a series of sequences, each of which looks like:
+//
+//  token (u8)      - high nibble is the literal length, low nibble is the match length minus 4
+//  [length bytes]  - if the literal nibble is 15, more bytes follow until one is below 255
+//  literals        - raw bytes copied to the output
+//  offset (u16 LE) - distance backwards into the output where the match begins
+//  [length bytes]  - if the match nibble is 15, more bytes follow until one is below 255
+//
+// the final sequence only contains literals and ends at the end of the block
+
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = 0xFFFF;
+const HASH_BITS: u32 = 12;
+
+#[inline]
+fn hash(bytes: &[u8]) -> usize {
+    let val = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (val.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+#[inline]
+fn write_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], mat: Option<(usize, usize)>) {
+    let lit_nibble = literals.len().min(15);
+    let match_nibble = mat.map_or(0, |(_, len)| (len - MIN_MATCH).min(15));
+    out.push(((lit_nibble << 4) | match_nibble) as u8);
+
+    if lit_nibble == 15 {
+        write_length(out, literals.len() - 15);
+    }
+    out.extend_from_slice(literals);
+
+    if let Some((offset, len)) = mat {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_nibble == 15 {
+            write_length(out, len - MIN_MATCH - 15);
+        }
+    }
+}
+
+/// Compress a block of bytes.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 16);
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut i = 0;
+
+    while i + MIN_MATCH <= input.len() {
+        let h = hash(&input[i..]);
+        let candidate = table[h];
+        table[h] = i;
+
+        // see if the candidate is a real match within range
+        if candidate != usize::MAX
+            && i - candidate <= MAX_OFFSET
+            && input[candidate..candidate + MIN_MATCH] == input[i..i + MIN_MATCH]
+        {
+            let mut len = MIN_MATCH;
+            while i + len < input.len() && input[candidate + len] == input[i + len] {
+                len += 1;
+            }
+
+            write_sequence(&mut out, &input[anchor..i], Some((i - candidate, len)));
+            i += len;
+            anchor = i;
+        } else {
+            i += 1;
+        }
+    }
+
+    write_sequence(&mut out, &input[anchor..], None);
+    out
+}
+
+#[inline]
+fn read_length(input: &[u8], pos: &mut usize, section: u32) -> Result<usize, LzError> {
+    let mut len = 0;
+    loop {
+        let byte = *input.get(*pos).ok_or(LzError::Corrupt(section))?;
+        *pos += 1;
+        len += byte as usize;
+        if byte != 255 {
+            return Ok(len);
+        }
+    }
+}
+
+/// Decompress a block of bytes into exactly `raw_len` bytes. `section` is only used for error
+/// reporting.
+pub fn decompress(input: &[u8], raw_len: usize, section: u32) -> Result<Vec<u8>, LzError> {
+    let mut out: Vec<u8> = Vec::with_capacity(raw_len);
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let token = input[pos];
+        pos += 1;
+
+        // copy over the literals
+        let mut lit_len = (token >> 4) as usize;
+        if lit_len == 15 {
+            lit_len += read_length(input, &mut pos, section)?;
+        }
+        let literals = input
+            .get(pos..pos + lit_len)
+            .ok_or(LzError::Corrupt(section))?;
+        if out.len() + lit_len > raw_len {
+            return Err(LzError::Corrupt(section));
+        }
+        out.extend_from_slice(literals);
+        pos += lit_len;
+
+        // the last sequence has no match
+        if pos == input.len() {
+            break;
+        }
+
+        let offset = input
+            .get(pos..pos + 2)
+            .map(|o| u16::from_le_bytes([o[0], o[1]]) as usize)
+            .ok_or(LzError::Corrupt(section))?;
+        pos += 2;
+        let mut match_len = (token & 0x0F) as usize + MIN_MATCH;
+        if match_len == 15 + MIN_MATCH {
+            match_len += read_length(input, &mut pos, section)?;
+        }
+
+        if offset == 0 || offset > out.len() || out.len() + match_len > raw_len {
+            return Err(LzError::Corrupt(section));
+        }
+
+        // matches may overlap with themselves, so copy byte by byte
+        let start = out.len() - offset;
+        for j in 0..match_len {
+            let byte = out[start + j];
+            out.push(byte);
+        }
+    }
+
+    if out.len() == raw_len {
+        Ok(out)
+    } else {
+        Err(LzError::Corrupt(section))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(input: &[u8]) {
+        let packed = compress(input);
+        assert_eq!(decompress(&packed, input.len(), 0).unwrap(), input);
+    }
+
+    #[test]
+    fn empty_block() {
+        round_trip(&[]);
+    }
+
+    #[test]
+    fn long_runs_shrink() {
+        // a run is a match that overlaps with itself
+        let mut input = vec![0xAA; 10_000];
+        input.extend_from_slice(b"lost in time");
+        input.extend_from_slice(&[7; 300]);
+        round_trip(&input);
+        assert!(compress(&input).len() < input.len() / 10);
+    }
+
+    #[test]
+    fn long_literals() {
+        // more than 15 + 255 literals, so the length takes several bytes
+        let input: Vec<u8> = (0..600u32).map(|i| (i * 7 + i / 3) as u8).collect();
+        round_trip(&input);
+    }
+
+    #[test]
+    fn wrong_length_is_corrupt() {
+        let packed = compress(b"abcdabcdabcdabcd");
+        assert_eq!(decompress(&packed, 15, 3), Err(LzError::Corrupt(3)));
+        assert_eq!(decompress(&packed, 17, 3), Err(LzError::Corrupt(3)));
+    }
+
+    #[test]
+    fn offset_before_start_is_corrupt() {
+        // no literals, then a match from 5 bytes before the start
+        assert_eq!(decompress(&[0x00, 5, 0], 4, 1), Err(LzError::Corrupt(1)));
+    }
+}
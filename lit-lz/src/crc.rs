@@ -0,0 +1,73 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lit-lz/src/crc.rs - CRC32 checksums (IEEE polynomial)
+
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// Running CRC32 checksum.
+#[derive(Clone)]
+pub struct Crc32 {
+    table: [u32; 256],
+    value: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        let mut table = [0; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut c = i as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 {
+                    POLYNOMIAL ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+            }
+            *entry = c;
+        }
+
+        Self {
+            table,
+            value: 0xFFFF_FFFF,
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        let table = &self.table;
+        self.value = bytes.iter().fold(self.value, |c, b| {
+            table[((c ^ *b as u32) & 0xFF) as usize] ^ (c >> 8)
+        });
+    }
+
+    #[inline]
+    pub fn finish(&self) -> u32 {
+        self.value ^ 0xFFFF_FFFF
+    }
+}
+
+/// Compute the CRC32 of a set of bytes.
+#[inline]
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        // the standard check value of CRC32 (IEEE)
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn running_checksum_matches() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), crc32(b"123456789"));
+    }
+}
@@ -0,0 +1,24 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lit-lz/src/error.rs - Errors that occur while decompressing
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum LzError {
+    #[error("Compressed stream does not begin with the expected header")]
+    BadMagic,
+    #[error("Unsupported compression format version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Compressed stream was truncated in section {0}")]
+    Truncated(u32),
+    #[error("Compressed section {0} is corrupt")]
+    Corrupt(u32),
+    #[error("Section {section} failed its integrity check (expected CRC32 {expected:08X}, found {found:08X})")]
+    ChecksumMismatch {
+        section: u32,
+        expected: u32,
+        found: u32,
+    },
+    #[error("Section of {0} bytes exceeds the maximum section size")]
+    SectionTooLarge(usize),
+}
@@ -0,0 +1,31 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lit-lz/src/lib.rs - LZ-style compression for data files
+
+#![allow(clippy::new_without_default)]
+
+mod block;
+pub use block::{compress, decompress};
+
+mod crc;
+pub use crc::{crc32, Crc32};
+
+mod error;
+pub use error::LzError;
+
+mod stream;
+pub use stream::{Decoder, Encoder};
+
+/// Magic bytes at the start of every compressed stream.
+pub const MAGIC: [u8; 4] = *b"LITZ";
+
+/// The version of the section format written by the encoder.
+pub const VERSION: u8 = 1;
+
+/// Maximum number of uncompressed bytes stored in a single section.
+pub const SECTION_SIZE: usize = 64 * 1024;
+
+/// Tell whether or not a set of leading bytes marks a compressed stream.
+#[inline]
+pub fn is_compressed(header: &[u8]) -> bool {
+    header.starts_with(&MAGIC)
+}
@@ -0,0 +1,314 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lit-lz/src/stream.rs - Read and Write adapters that operate on compressed sections
+
+use crate::{compress, crc32, decompress, LzError, MAGIC, SECTION_SIZE, VERSION};
+use std::io::{self, prelude::*};
+
+// a compressed stream consists of the magic bytes, a version byte and then a list of sections:
+//
+//  raw length (u32 BE)    - number of bytes once decompressed, zero marks the end of the stream
+//  packed length (u32 BE) - number of bytes stored, the high bit is set if the section is stored raw
+//  checksum (u32 BE)      - CRC32 of the decompressed bytes
+//  data
+const STORED_FLAG: u32 = 0x8000_0000;
+
+#[inline]
+fn invalid(err: LzError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Writer that compresses everything written to it.
+pub struct Encoder<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(&MAGIC)?;
+        inner.write_all(&[VERSION])?;
+
+        Ok(Self {
+            inner,
+            buffer: Vec::with_capacity(SECTION_SIZE),
+        })
+    }
+
+    fn write_section(&mut self) -> io::Result<()> {
+        let checksum = crc32(&self.buffer);
+        let packed = compress(&self.buffer);
+
+        // if compression doesn't help, store the section as-is
+        let (packed_len, data) = if packed.len() < self.buffer.len() {
+            (packed.len() as u32, &packed[..])
+        } else {
+            (self.buffer.len() as u32 | STORED_FLAG, &self.buffer[..])
+        };
+
+        self.inner
+            .write_all(&(self.buffer.len() as u32).to_be_bytes())?;
+        self.inner.write_all(&packed_len.to_be_bytes())?;
+        self.inner.write_all(&checksum.to_be_bytes())?;
+        self.inner.write_all(data)?;
+
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Write out any remaining data as well as the end marker, and return the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.buffer.is_empty() {
+            self.write_section()?;
+        }
+
+        self.inner.write_all(&[0; 12])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(SECTION_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+
+        if self.buffer.len() == SECTION_SIZE {
+            self.write_section()?;
+        }
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader that transparently decompresses a compressed stream.
+pub struct Decoder<R: Read> {
+    inner: R,
+    buffer: Vec<u8>,
+    position: usize,
+    section: u32,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buffer: vec![],
+            position: 0,
+            section: 0,
+            started: false,
+            finished: false,
+        }
+    }
+
+    // read exactly enough bytes to fill the buffer, reporting truncation instead of EOF
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        match self.inner.read_exact(buf) {
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                Err(invalid(LzError::Truncated(self.section)))
+            }
+            res => res,
+        }
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let mut header = [0; 5];
+        self.fill(&mut header)?;
+
+        if header[..4] != MAGIC {
+            return Err(invalid(LzError::BadMagic));
+        }
+        if header[4] != VERSION {
+            return Err(invalid(LzError::UnsupportedVersion(header[4])));
+        }
+
+        self.started = true;
+        Ok(())
+    }
+
+    fn read_section(&mut self) -> io::Result<()> {
+        let mut header = [0; 12];
+        self.fill(&mut header)?;
+
+        let raw_len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let packed_len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let expected = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+
+        if raw_len == 0 {
+            self.finished = true;
+            return Ok(());
+        }
+        if raw_len > SECTION_SIZE {
+            return Err(invalid(LzError::SectionTooLarge(raw_len)));
+        }
+
+        let stored = packed_len & STORED_FLAG != 0;
+        let packed_len = (packed_len & !STORED_FLAG) as usize;
+        if packed_len > SECTION_SIZE || (stored && packed_len != raw_len) {
+            return Err(invalid(LzError::Corrupt(self.section)));
+        }
+
+        let mut data = vec![0; packed_len];
+        self.fill(&mut data)?;
+
+        let data = if stored {
+            data
+        } else {
+            decompress(&data, raw_len, self.section).map_err(invalid)?
+        };
+
+        let found = crc32(&data);
+        if found != expected {
+            return Err(invalid(LzError::ChecksumMismatch {
+                section: self.section,
+                expected,
+                found,
+            }));
+        }
+
+        self.buffer = data;
+        self.position = 0;
+        self.section += 1;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.started {
+            self.read_header()?;
+        }
+
+        while self.position == self.buffer.len() {
+            if self.finished {
+                return Ok(0);
+            }
+
+            self.read_section()?;
+        }
+
+        let len = buf.len().min(self.buffer.len() - self.position);
+        buf[..len].copy_from_slice(&self.buffer[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the magic bytes and the version come before the first section
+    const HEADER_LEN: usize = 5;
+    const SECTION_HEADER_LEN: usize = 12;
+
+    fn encode(input: &[u8]) -> Vec<u8> {
+        let mut encoder = Encoder::new(vec![]).unwrap();
+        encoder.write_all(input).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn decode(stream: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = vec![];
+        Decoder::new(stream).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    fn lz_error(e: io::Error) -> LzError {
+        *e.into_inner()
+            .expect("decoder errors wrap an LzError")
+            .downcast::<LzError>()
+            .unwrap()
+    }
+
+    // bytes that don't compress, from a xorshift generator
+    fn noise(len: usize) -> Vec<u8> {
+        let mut x: u32 = 0x1234_5678;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty_stream() {
+        let stream = encode(&[]);
+        assert_eq!(stream.len(), HEADER_LEN + SECTION_HEADER_LEN);
+        assert_eq!(decode(&stream).unwrap(), b"");
+    }
+
+    #[test]
+    fn incompressible_input_is_stored() {
+        let input = noise(1000);
+        let stream = encode(&input);
+        let packed_len = u32::from_be_bytes([stream[9], stream[10], stream[11], stream[12]]);
+        assert_eq!(packed_len, input.len() as u32 | STORED_FLAG);
+        assert_eq!(decode(&stream).unwrap(), input);
+    }
+
+    #[test]
+    fn long_runs_span_sections() {
+        let input = vec![b'x'; SECTION_SIZE * 2 + 100];
+        let stream = encode(&input);
+        assert!(stream.len() < 2048);
+        assert_eq!(decode(&stream).unwrap(), input);
+    }
+
+    #[test]
+    fn flipped_payload_bit_fails_checksum() {
+        let mut stream = encode(&noise(100));
+        stream[HEADER_LEN + SECTION_HEADER_LEN + 40] ^= 0x10;
+        match lz_error(decode(&stream).unwrap_err()) {
+            LzError::ChecksumMismatch { section: 0, .. } => {}
+            e => panic!("expected a checksum mismatch, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn flipped_checksum_bit_fails_checksum() {
+        let input = noise(100);
+        let mut stream = encode(&input);
+        stream[HEADER_LEN + 8] ^= 0x01;
+        assert_eq!(
+            lz_error(decode(&stream).unwrap_err()),
+            LzError::ChecksumMismatch {
+                section: 0,
+                expected: crc32(&input) ^ 0x0100_0000,
+                found: crc32(&input),
+            }
+        );
+    }
+
+    #[test]
+    fn truncated_stream() {
+        let stream = encode(&noise(100));
+        let err = decode(&stream[..stream.len() - 20]).unwrap_err();
+        assert_eq!(lz_error(err), LzError::Truncated(0));
+    }
+
+    #[test]
+    fn bad_header() {
+        assert_eq!(
+            lz_error(decode(b"LITX\x01").unwrap_err()),
+            LzError::BadMagic
+        );
+        assert_eq!(
+            lz_error(decode(b"LITZ\x09").unwrap_err()),
+            LzError::UnsupportedVersion(9)
+        );
+    }
+}
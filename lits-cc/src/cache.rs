@@ -0,0 +1,341 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// cache.rs - Cache of compiled fragments, so that unchanged files aren't compiled again.
+
+use crate::{Fragment, LitsCcError, Relocation, Symbol, SymbolKind};
+use std::{
+    fs::{self, File},
+    io::{self, prelude::*, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+// a cache entry looks like:
+//
+//  magic (4 bytes), version (u8)
+//  content hash (u64 BE), dependency fingerprint (u64 BE)
+//  symbol count (u32 BE), then for each: kind (u8), name length (u16 BE), name
+//  relocation count (u32 BE), then for each: offset (u32 BE), symbol (u32 BE)
+//  code length (u32 BE), code
+//  line count (u32 BE), then for each: offset (u32 BE), line (u32 BE)
+const MAGIC: [u8; 4] = *b"LITC";
+const VERSION: u8 = 2;
+
+/// The directory used for the cache when none is given.
+pub const DEFAULT_CACHE_DIR: &str = ".lits-cache";
+
+/// 64-bit FNV-1a hasher. This is used instead of the standard library's hasher since its output
+/// must stay the same between runs.
+pub struct Fnv64(u64);
+
+impl Fnv64 {
+    pub fn new() -> Self {
+        Fnv64(0xCBF2_9CE4_8422_2325)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01B3);
+        }
+    }
+
+    #[inline]
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[inline]
+pub fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = Fnv64::new();
+    hasher.update(bytes);
+    hasher.finish()
+}
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<Self, LitsCcError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    // entries are named after a hash of the source path
+    fn entry_path(&self, source: &Path) -> PathBuf {
+        let source = fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf());
+        self.dir.join(format!(
+            "{:016x}.litc",
+            hash(source.to_string_lossy().as_bytes())
+        ))
+    }
+
+    /// Load the fragment for a source file, if the cached copy is still up to date. `fingerprint`
+    /// computes the current dependency fingerprint of the cached fragment.
+    pub fn load<F: Fn(&Fragment) -> u64>(
+        &self,
+        source: &Path,
+        content_hash: u64,
+        fingerprint: F,
+    ) -> Option<Fragment> {
+        let mut file = BufReader::new(File::open(self.entry_path(source)).ok()?);
+
+        // any entry that can't be read is treated as missing
+        let (stored_hash, stored_deps, fragment) = read_entry(&mut file).ok()?;
+        if stored_hash == content_hash && stored_deps == fingerprint(&fragment) {
+            Some(fragment)
+        } else {
+            None
+        }
+    }
+
+    pub fn store(
+        &self,
+        source: &Path,
+        content_hash: u64,
+        fingerprint: u64,
+        fragment: &Fragment,
+    ) -> Result<(), LitsCcError> {
+        // write to a temporary file first, so that an interrupted write never leaves a broken entry
+        let path = self.entry_path(source);
+        let tmp_path = path.with_extension("tmp");
+
+        let mut file = BufWriter::new(File::create(&tmp_path)?);
+        write_entry(&mut file, content_hash, fingerprint, fragment)?;
+        file.flush()?;
+        drop(file);
+
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[inline]
+fn read_u32<T: Read>(stream: &mut T) -> io::Result<u32> {
+    let mut buffer = [0; 4];
+    stream.read_exact(&mut buffer)?;
+    Ok(u32::from_be_bytes(buffer))
+}
+
+#[inline]
+fn read_u64<T: Read>(stream: &mut T) -> io::Result<u64> {
+    let mut buffer = [0; 8];
+    stream.read_exact(&mut buffer)?;
+    Ok(u64::from_be_bytes(buffer))
+}
+
+fn invalid_entry() -> LitsCcError {
+    LitsCcError::StaticMsg("Cache entry is invalid")
+}
+
+fn read_entry<T: Read>(stream: &mut T) -> Result<(u64, u64, Fragment), LitsCcError> {
+    let mut header = [0; 5];
+    stream.read_exact(&mut header)?;
+    if header[..4] != MAGIC || header[4] != VERSION {
+        return Err(invalid_entry());
+    }
+
+    let content_hash = read_u64(stream)?;
+    let fingerprint = read_u64(stream)?;
+
+    let mut fragment = Fragment::new();
+
+    for _ in 0..read_u32(stream)? {
+        let mut buffer = [0; 3];
+        stream.read_exact(&mut buffer)?;
+        let kind = match buffer[0] {
+            0 => SymbolKind::Definition,
+            1 => SymbolKind::Import,
+            _ => return Err(invalid_entry()),
+        };
+
+        let mut name = vec![0; u16::from_be_bytes([buffer[1], buffer[2]]) as usize];
+        stream.read_exact(&mut name)?;
+        let name = String::from_utf8(name).map_err(|_| invalid_entry())?;
+
+        fragment.symbols.push(Symbol { name, kind });
+    }
+
+    for _ in 0..read_u32(stream)? {
+        let offset = read_u32(stream)?;
+        let symbol = read_u32(stream)?;
+        fragment.relocations.push(Relocation { offset, symbol });
+    }
+
+    let mut code = vec![0; read_u32(stream)? as usize];
+    stream.read_exact(&mut code)?;
+    fragment.code = code;
+
+    for _ in 0..read_u32(stream)? {
+        let offset = read_u32(stream)?;
+        let line = read_u32(stream)?;
+        fragment.lines.push((offset, line));
+    }
+
+    // make sure the relocations and lines make sense before this is handed to the linker
+    if fragment.relocations.iter().any(|r| {
+        r.offset as usize + 4 > fragment.code.len() || r.symbol as usize >= fragment.symbols.len()
+    }) {
+        return Err(invalid_entry());
+    }
+    if fragment
+        .lines
+        .iter()
+        .any(|(offset, _)| *offset >= fragment.len())
+    {
+        return Err(invalid_entry());
+    }
+
+    Ok((content_hash, fingerprint, fragment))
+}
+
+fn write_entry<T: Write>(
+    stream: &mut T,
+    content_hash: u64,
+    fingerprint: u64,
+    fragment: &Fragment,
+) -> Result<(), LitsCcError> {
+    stream.write_all(&MAGIC)?;
+    stream.write_all(&[VERSION])?;
+    stream.write_all(&content_hash.to_be_bytes())?;
+    stream.write_all(&fingerprint.to_be_bytes())?;
+
+    stream.write_all(&(fragment.symbols.len() as u32).to_be_bytes())?;
+    for symbol in &fragment.symbols {
+        stream.write_all(&[symbol.kind as u8])?;
+        stream.write_all(&(symbol.name.len() as u16).to_be_bytes())?;
+        stream.write_all(symbol.name.as_bytes())?;
+    }
+
+    stream.write_all(&(fragment.relocations.len() as u32).to_be_bytes())?;
+    for reloc in &fragment.relocations {
+        stream.write_all(&reloc.offset.to_be_bytes())?;
+        stream.write_all(&reloc.symbol.to_be_bytes())?;
+    }
+
+    stream.write_all(&(fragment.code.len() as u32).to_be_bytes())?;
+    stream.write_all(&fragment.code)?;
+
+    stream.write_all(&(fragment.lines.len() as u32).to_be_bytes())?;
+    for (offset, line) in &fragment.lines {
+        stream.write_all(&offset.to_be_bytes())?;
+        stream.write_all(&line.to_be_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile::compile_file, dependency_fingerprint};
+    use std::{env, process};
+
+    // a cache in a directory of its own, since tests run at the same time
+    fn cache(name: &str) -> (Cache, PathBuf) {
+        let dir = env::temp_dir().join(format!("lits-cc-cache-{}-{}", name, process::id()));
+        (Cache::new(&dir).unwrap(), dir)
+    }
+
+    fn compile(source: &str) -> Fragment {
+        compile_file(source.as_bytes()).unwrap()
+    }
+
+    fn same(a: &Fragment, b: &Fragment) -> bool {
+        a.code == b.code
+            && a.relocations == b.relocations
+            && a.symbols == b.symbols
+            && a.lines == b.lines
+    }
+
+    #[test]
+    fn unchanged_files_are_loaded() {
+        let (cache, dir) = cache("hit");
+        let source = "def X 1\nlog \"{}\" (@X)\n";
+        let fragment = compile(source);
+        let path = dir.join("a.lits");
+        cache
+            .store(&path, hash(source.as_bytes()), 7, &fragment)
+            .unwrap();
+
+        let loaded = cache.load(&path, hash(source.as_bytes()), |_| 7).unwrap();
+        assert!(same(&loaded, &fragment));
+        // other files have entries of their own
+        assert!(cache
+            .load(&dir.join("b.lits"), hash(source.as_bytes()), |_| 7)
+            .is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn changed_files_are_compiled_again() {
+        let (cache, dir) = cache("changed");
+        let path = dir.join("a.lits");
+        cache
+            .store(&path, hash(b"def X 1\n"), 0, &compile("def X 1\n"))
+            .unwrap();
+        assert!(cache.load(&path, hash(b"def X 2\n"), |_| 0).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_are_compiled_again_when_their_imports_change() {
+        let (cache, dir) = cache("imports");
+        let provider = "def X 1\n";
+        let compiled = vec![(hash(provider.as_bytes()), compile(provider))];
+        let source = "log \"{}\" (@X)\n";
+        let fragment = compile(source);
+        let path = dir.join("b.lits");
+        let fingerprint = dependency_fingerprint(&fragment, &compiled);
+        cache
+            .store(&path, hash(source.as_bytes()), fingerprint, &fragment)
+            .unwrap();
+
+        let load = |compiled: &[(u64, Fragment)]| {
+            cache.load(&path, hash(source.as_bytes()), |f| {
+                dependency_fingerprint(f, compiled)
+            })
+        };
+        assert!(load(&compiled).is_some());
+
+        // the file that defines X changed
+        let provider = "def X 2\n";
+        let changed = vec![(hash(provider.as_bytes()), compile(provider))];
+        assert!(load(&changed).is_none());
+
+        // a later file defines X instead
+        let mut shadowed = compiled.clone();
+        shadowed.push(changed[0].clone());
+        assert!(load(&shadowed).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn entries_of_other_versions_are_ignored() {
+        let (cache, dir) = cache("version");
+        let path = dir.join("a.lits");
+        cache
+            .store(&path, hash(b"def X 1\n"), 0, &compile("def X 1\n"))
+            .unwrap();
+
+        let entry = cache.entry_path(&path);
+        let mut bytes = fs::read(&entry).unwrap();
+        bytes[4] = VERSION - 1;
+        fs::write(&entry, &bytes).unwrap();
+        assert!(cache.load(&path, hash(b"def X 1\n"), |_| 0).is_none());
+        assert!(read_entry(&mut &bytes[..]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn entries_that_point_outside_their_code_are_invalid() {
+        let mut fragment = compile("def X 1\n");
+        fragment.relocations.push(Relocation {
+            offset: fragment.len(),
+            symbol: 0,
+        });
+        let mut bytes = vec![];
+        write_entry(&mut bytes, 0, 0, &fragment).unwrap();
+        assert!(read_entry(&mut &bytes[..]).is_err());
+    }
+}
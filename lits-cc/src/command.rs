@@ -1,7 +1,10 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // command.rs - Process a command in the LitScript
 
-use crate::{CompilerState, LitsCcError};
+use crate::{
+    format::check_log, process_literals, script::begin_script, CompilerState, Fragment,
+    LitsCcError,
+};
 use proc_macro2::{Ident, TokenTree};
 use std::io::prelude::*;
 
@@ -12,45 +15,83 @@ fn write_word<T: Write>(stream: &mut T, word: u16) -> Result<(), LitsCcError> {
     Ok(())
 }
 
-pub fn read_ident<TStream: Write, TIter: Iterator<Item = TokenTree>>(
+// write out a name as a string object, whose length has to fit in a byte
+pub fn write_name<T: Write>(stream: &mut T, name: &str) -> Result<(), LitsCcError> {
+    if name.len() > u8::MAX as usize {
+        return Err(LitsCcError::NameTooLong(name.to_string()));
+    }
+
+    stream.write_all(&[4, name.len() as u8])?;
+    stream.write_all(name.as_bytes())?;
+    Ok(())
+}
+
+// read in an identifier and register it as a new variable
+//
+// the variable is named in a separate statement before the command itself, so that data files
+// loaded later on can override it by name
+pub fn declare_ident<TIter: Iterator<Item = TokenTree>>(
     iter: &mut TIter,
-    stream: &mut TStream,
+    stream: &mut Fragment,
     state: &mut CompilerState,
+    command: u16,
 ) -> Result<(), LitsCcError> {
-    // read in an ident
     match iter.next() {
         Some(TokenTree::Ident(i)) => {
             let var_name = format!("{}", i);
             let id = state.register_variable(&var_name);
-            stream.write_all(&id.to_be_bytes())?;
+
+            write_word(stream, 8)?;
+            stream.write_symbol(id)?;
+            write_name(stream, &var_name)?;
+
+            write_word(stream, command)?;
+            stream.write_symbol(id)?;
             Ok(())
         }
         _ => Err(LitsCcError::ExpectedIdent),
     }
 }
 
-pub fn process_command<TStream: Write, TIter: Iterator<Item = TokenTree>>(
+pub fn process_command<TIter: Iterator<Item = TokenTree>>(
     ident: &Ident,
     iter: &mut TIter,
-    stream: &mut TStream,
+    stream: &mut Fragment,
     state: &mut CompilerState,
 ) -> Result<(), LitsCcError> {
     let name = format!("{}", ident);
 
     match name.as_ref() {
         "gamedef" => write_word(stream, 1),
-        "def" => {
-            write_word(stream, 2)?;
-            read_ident(iter, stream, state)
-        }
-        "log" => write_word(stream, 3),
-        "create_tex" => {
-            write_word(stream, 4)?;
-            read_ident(iter, stream, state)
+        "def" => declare_ident(iter, stream, state, 2),
+        "log" | "log_warn" | "log_error" => {
+            // check the format string before writing anything
+            let args: Vec<TokenTree> = iter.collect();
+            check_log(args.first(), args.get(1))?;
+            write_word(
+                stream,
+                match name.as_ref() {
+                    "log" => 3,
+                    "log_warn" => 10,
+                    _ => 11,
+                },
+            )?;
+            process_literals(&mut args.into_iter(), stream, state).map(|_| ())
         }
+        "create_tex" => declare_ident(iter, stream, state, 4),
+        "create_indexed_tex" => declare_ident(iter, stream, state, 18),
+        "create_palette" => declare_ident(iter, stream, state, 19),
+        "cycle_palette" => write_word(stream, 20),
         "color_id" => write_word(stream, 5),
         "draw_pixel" => write_word(stream, 6),
         "draw_rect" => write_word(stream, 7),
+        "blit" => write_word(stream, 12),
+        "overlay" => write_word(stream, 13),
+        "tint" => write_word(stream, 14),
+        "flip" => write_word(stream, 15),
+        "flip_v" => write_word(stream, 16),
+        "rotate90" => write_word(stream, 17),
+        "on" => begin_script(iter, state),
         _ => Err(LitsCcError::UnknownCommand(name)),
     }
 }
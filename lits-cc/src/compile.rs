@@ -1,19 +1,45 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // compile.rs - Take a line of LitS and compile it.
 
-use crate::{process_command, process_literals, CompilerState, LitsCcError};
+use crate::{
+    process_command, process_literals, script::compile_script_line, CompilerState, Fragment,
+    LitsCcError,
+};
 use proc_macro2::{TokenStream, TokenTree};
 use std::io::prelude::*;
 
-pub fn compile_line<T: Write>(
+// remove a comment from the end of a line. a '#' inside of a string, like in "{:#x}", doesn't
+// start a comment
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' if in_string => escaped = !escaped,
+            '"' if !escaped => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => escaped = false,
+        }
+    }
+
+    line
+}
+
+pub fn compile_line(
     line: &str,
-    stream: &mut T,
+    stream: &mut Fragment,
     state: &mut CompilerState,
 ) -> Result<(), LitsCcError> {
     // parse the line into tokens
     let tokens: TokenStream = line.parse()?;
     let mut iter = tokens.into_iter();
 
+    // lines inside of an entry point are compiled into VM code
+    if state.script.is_some() {
+        return compile_script_line(&mut iter, stream, state);
+    }
+
     // get the first token in the stream, which must be an identifier
     match iter.next() {
         Some(TokenTree::Ident(ref i)) => {
@@ -26,3 +52,45 @@ pub fn compile_line<T: Write>(
     let _ = process_literals(&mut iter, stream, state)?;
     Ok(())
 }
+
+/// Compile an entire LitS file into a fragment. On failure, the line number the error occurred on
+/// is returned along with the error.
+pub fn compile_file<T: BufRead>(file: T) -> Result<Fragment, (usize, LitsCcError)> {
+    let mut fragment = Fragment::new();
+    let mut state = CompilerState::new();
+
+    let mut line_count = 0;
+    // entry points are written out at their end, but belong to the line they start on
+    let mut script_line = 0;
+    for (index, line) in file.lines().enumerate() {
+        let line_ref = &line.map_err(|e| (index, e.into()))?;
+        let processed_line = strip_comment(line_ref);
+
+        let start = fragment.len();
+        let in_script = state.script.is_some();
+        compile_line(processed_line, &mut fragment, &mut state).map_err(|e| (index, e))?;
+        line_count = index + 1;
+
+        if !in_script && state.script.is_some() {
+            script_line = line_count as u32;
+        }
+        if fragment.len() > start {
+            let line = if in_script {
+                script_line
+            } else {
+                line_count as u32
+            };
+            fragment.lines.push((start, line));
+        }
+    }
+
+    if let Some(ref script) = state.script {
+        return Err((
+            line_count,
+            LitsCcError::UnterminatedScript(script.name.clone()),
+        ));
+    }
+
+    fragment.symbols = state.into_symbols();
+    Ok(fragment)
+}
@@ -0,0 +1,51 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// debug.rs - Write debug symbols, which tell the game's debugger where each statement came from.
+
+use crate::{Fragment, LitsCcError};
+use std::io::prelude::*;
+
+// a debug symbols file looks like:
+//
+//  magic (4 bytes), version (u8)
+//  file count (u16 BE), then for each: name length (u16 BE), name
+//  line count (u32 BE), then for each: offset (u32 BE), file (u16 BE), line (u32 BE)
+//
+// offsets are into the linked data file before it is compressed, and lines count from 1
+const MAGIC: [u8; 4] = *b"LDBG";
+const VERSION: u8 = 1;
+
+/// The extension of debug symbols files, which are written next to the data file.
+pub const DEBUG_EXTENSION: &str = "litdbg";
+
+/// Write the lines of every statement in a set of linked fragments, which were compiled from the
+/// input files with the same index.
+pub fn write_symbols<T: Write>(
+    stream: &mut T,
+    inputs: &[String],
+    fragments: &[Fragment],
+) -> Result<(), LitsCcError> {
+    stream.write_all(&MAGIC)?;
+    stream.write_all(&[VERSION])?;
+
+    stream.write_all(&(inputs.len() as u16).to_be_bytes())?;
+    for input in inputs {
+        stream.write_all(&(input.len() as u16).to_be_bytes())?;
+        stream.write_all(input.as_bytes())?;
+    }
+
+    let count: usize = fragments.iter().map(|f| f.lines.len()).sum();
+    stream.write_all(&(count as u32).to_be_bytes())?;
+
+    // fragments are linked one after another, without changing their length
+    let mut base = 0;
+    for (file, fragment) in fragments.iter().enumerate() {
+        for (offset, line) in &fragment.lines {
+            stream.write_all(&(base + offset).to_be_bytes())?;
+            stream.write_all(&(file as u16).to_be_bytes())?;
+            stream.write_all(&line.to_be_bytes())?;
+        }
+        base += fragment.len();
+    }
+
+    Ok(())
+}
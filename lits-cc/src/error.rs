@@ -1,6 +1,7 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // error.rs - Error handling for the compilation process
 
+use lit_fmt::FormatError;
 use proc_macro2::{LexError, TokenTree};
 use std::io::Error as IoError;
 use thiserror::Error;
@@ -23,6 +24,14 @@ pub enum LitsCcError {
     ExpectedIdent,
     #[error("Unknown command: {0}")]
     UnknownCommand(String),
+    #[error("Unknown operator: {0}")]
+    UnknownOperator(String),
+    #[error("Name {0} is longer than 255 bytes")]
+    NameTooLong(String),
+    #[error("Entry point {0} is missing an end")]
+    UnterminatedScript(String),
+    #[error("{0}")]
+    Format(#[from] FormatError),
 }
 
 impl From<LexError> for LitsCcError {
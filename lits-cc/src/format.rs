@@ -0,0 +1,61 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// format.rs - Check the format strings of log commands
+
+use crate::LitsCcError;
+use proc_macro2::{Delimiter, TokenTree};
+
+/// Check a log command's format string against its tuple, when they are written out as literals.
+/// Anything that comes from a variable is checked when the log command runs instead.
+pub fn check_log(format: Option<&TokenTree>, tuple: Option<&TokenTree>) -> Result<(), LitsCcError> {
+    let format = match format {
+        Some(TokenTree::Literal(l)) => format!("{}", l),
+        _ => return Ok(()),
+    };
+    if !(format.starts_with('"') && format.ends_with('"')) {
+        return Ok(());
+    }
+
+    // read the string the same way process_literal does
+    let inner = format.split('"').nth(1).unwrap_or("");
+    let pieces = lit_fmt::parse(inner)?;
+
+    if let Some(TokenTree::Group(g)) = tuple {
+        if g.delimiter() == Delimiter::Parenthesis {
+            // "@NAME" and "-1" are made up of two tokens, but are single elements, so only the
+            // token after the punctuation is counted
+            let count = g
+                .stream()
+                .into_iter()
+                .filter(|t| !matches!(t, TokenTree::Punct(_)))
+                .count();
+            lit_fmt::check_args(&pieces, count)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lit_fmt::FormatError;
+    use proc_macro2::TokenStream;
+
+    fn check(source: &str) -> Result<(), LitsCcError> {
+        let tokens: Vec<_> = source.parse::<TokenStream>().unwrap().into_iter().collect();
+        check_log(tokens.first(), tokens.get(1))
+    }
+
+    #[test]
+    fn prefixed_elements_are_counted_once() {
+        assert!(check(r#""{} {} {}" (-5 @X 7)"#).is_ok());
+        assert!(matches!(
+            check(r#""{} {}" (-5 @X 7)"#),
+            Err(LitsCcError::Format(FormatError::UnusedArgument(2)))
+        ));
+        assert!(matches!(
+            check(r#""{} {}" (-5)"#),
+            Err(LitsCcError::Format(FormatError::MissingArgument(1, 1)))
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// fragment.rs - Compiled output of a single LitS file, before it is linked together with others.
+
+use std::io::{self, prelude::*};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SymbolKind {
+    // the variable is defined by this file
+    Definition = 0,
+    // the variable is defined by an earlier file
+    Import = 1,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+}
+
+// a spot in the code where a variable id needs to be filled in by the linker
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub offset: u32,
+    pub symbol: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Fragment {
+    pub code: Vec<u8>,
+    pub relocations: Vec<Relocation>,
+    pub symbols: Vec<Symbol>,
+    // where each statement starts, along with the line of the source file it was compiled from
+    pub lines: Vec<(u32, u32)>,
+}
+
+impl Fragment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write a placeholder for a variable id, to be filled in by the linker.
+    pub fn write_symbol(&mut self, symbol: u32) -> io::Result<()> {
+        self.relocations.push(Relocation {
+            offset: self.code.len() as u32,
+            symbol,
+        });
+        self.code.write_all(&symbol.to_be_bytes())
+    }
+
+    /// Overwrite a 32-bit value that was written earlier, such as a jump target.
+    pub fn patch_u32(&mut self, offset: u32, value: u32) {
+        let offset = offset as usize;
+        self.code[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    #[inline]
+    pub fn len(&self) -> u32 {
+        self.code.len() as u32
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    /// Append the code of another fragment to this one.
+    pub fn append(&mut self, other: Fragment) {
+        let base = self.code.len() as u32;
+        self.relocations
+            .extend(other.relocations.into_iter().map(|r| Relocation {
+                offset: r.offset + base,
+                symbol: r.symbol,
+            }));
+        self.code.extend(other.code);
+    }
+
+    /// Iterate over the names of the variables that this fragment takes from other files.
+    pub fn imports(&self) -> impl Iterator<Item = &str> {
+        self.symbols
+            .iter()
+            .filter(|s| s.kind == SymbolKind::Import)
+            .map(|s| s.name.as_str())
+    }
+
+    #[inline]
+    pub fn defines(&self, name: &str) -> bool {
+        self.symbols
+            .iter()
+            .any(|s| s.kind == SymbolKind::Definition && s.name == name)
+    }
+}
+
+impl Write for Fragment {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.code.write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
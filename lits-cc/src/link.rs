@@ -0,0 +1,119 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// link.rs - Link compiled fragments together into one data file.
+
+use crate::{Fragment, LitsCcError, SymbolKind};
+use std::{collections::HashMap, io::prelude::*};
+
+pub fn link<T: Write>(fragments: &[Fragment], stream: &mut T) -> Result<(), LitsCcError> {
+    let mut names: HashMap<&str, u32> = HashMap::new();
+    let mut next_id = 1;
+
+    for fragment in fragments {
+        // assign a global id to every symbol in the fragment
+        let mut ids = Vec::with_capacity(fragment.symbols.len());
+        for symbol in &fragment.symbols {
+            let id = match symbol.kind {
+                SymbolKind::Definition => {
+                    // a name that is defined again refers to the new variable from then on, like
+                    // it does within a single file
+                    let id = next_id;
+                    next_id += 1;
+                    names.insert(&symbol.name, id);
+                    id
+                }
+                SymbolKind::Import => *names
+                    .get(symbol.name.as_str())
+                    .ok_or_else(|| LitsCcError::VariableNotFound(symbol.name.clone()))?,
+            };
+            ids.push(id);
+        }
+
+        // fill in the ids
+        let mut code = fragment.code.clone();
+        for reloc in &fragment.relocations {
+            let offset = reloc.offset as usize;
+            let id = ids
+                .get(reloc.symbol as usize)
+                .ok_or(LitsCcError::StaticMsg(
+                    "Relocation refers to an unknown symbol",
+                ))?;
+            code.get_mut(offset..offset + 4)
+                .ok_or(LitsCcError::StaticMsg("Relocation is out of bounds"))?
+                .copy_from_slice(&id.to_be_bytes());
+        }
+
+        stream.write_all(&code)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Relocation, Symbol};
+
+    // a fragment that is nothing but a reference to one variable
+    fn fragment(name: &str, kind: SymbolKind) -> Fragment {
+        let mut fragment = Fragment::new();
+        fragment.symbols.push(Symbol {
+            name: String::from(name),
+            kind,
+        });
+        fragment.write_symbol(0).unwrap();
+        fragment
+    }
+
+    fn linked(fragments: &[Fragment]) -> Result<Vec<u8>, LitsCcError> {
+        let mut out = vec![];
+        link(fragments, &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn imports_resolve_to_earlier_definitions() {
+        let out = linked(&[
+            fragment("X", SymbolKind::Definition),
+            fragment("Y", SymbolKind::Definition),
+            fragment("X", SymbolKind::Import),
+        ])
+        .unwrap();
+        assert_eq!(out, [0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn unresolved_imports_fail() {
+        let import = || fragment("X", SymbolKind::Import);
+        assert!(matches!(
+            linked(&[import()]),
+            Err(LitsCcError::VariableNotFound(ref name)) if name == "X"
+        ));
+        // definitions only count for the files after them
+        assert!(matches!(
+            linked(&[import(), fragment("X", SymbolKind::Definition)]),
+            Err(LitsCcError::VariableNotFound(ref name)) if name == "X"
+        ));
+    }
+
+    #[test]
+    fn defining_a_name_again_shadows_it() {
+        let out = linked(&[
+            fragment("X", SymbolKind::Definition),
+            fragment("X", SymbolKind::Definition),
+            fragment("X", SymbolKind::Import),
+        ])
+        .unwrap();
+        assert_eq!(out, [0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn bad_relocations_fail() {
+        let relocated = |offset, symbol| {
+            let mut fragment = fragment("X", SymbolKind::Definition);
+            fragment.relocations.push(Relocation { offset, symbol });
+            fragment
+        };
+        assert!(linked(&[relocated(2, 0)]).is_err());
+        assert!(linked(&[relocated(0, 1)]).is_err());
+    }
+}
@@ -1,82 +1,136 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // literals.rs - Process literals
 
-use crate::{CompilerState, LitsCcError};
+use crate::{CompilerState, Fragment, LitsCcError};
 use proc_macro2::{Delimiter, TokenTree};
-use std::io::{self, prelude::*, Cursor, SeekFrom};
+use std::{convert::TryFrom, io::prelude::*};
 
-pub fn process_literals<TStream: Write, TIter: Iterator<Item = TokenTree>>(
+// write a number in the smallest type that can hold it
+fn write_number(stream: &mut Fragment, i: i32) -> Result<(), LitsCcError> {
+    if i >= u8::MIN as i32 && i <= u8::MAX as i32 {
+        stream.write_all(&[1])?;
+        stream.write_all(&[i as u8])?;
+    } else if i >= i16::MIN as i32 && i <= i16::MAX as i32 {
+        stream.write_all(&[2])?;
+        stream.write_all(&(i as i16).to_be_bytes())?;
+    } else {
+        stream.write_all(&[3])?;
+        stream.write_all(&i.to_be_bytes())?;
+    }
+    Ok(())
+}
+
+// process a single literal, which may consume more tokens from the iterator
+pub fn process_literal<TIter: Iterator<Item = TokenTree>>(
+    token: TokenTree,
     iter: &mut TIter,
-    stream: &mut TStream,
+    stream: &mut Fragment,
     state: &mut CompilerState,
-) -> Result<usize, LitsCcError> {
-    let mut elements_processed = 0;
-
-    while let Some(token) = iter.next() {
-        match token {
-            TokenTree::Literal(l) => {
-                let l = format!("{}", l);
+) -> Result<(), LitsCcError> {
+    match token {
+        TokenTree::Literal(l) => {
+            let l = format!("{}", l);
 
-                if l.starts_with('"') && l.ends_with('"') {
-                    // this is a string!
-                    let inner = l.split('\"').nth(1).unwrap();
-                    stream.write_all(&[4, inner.len() as u8])?;
-                    stream.write_all(inner.as_bytes())?;
-                } else if let Ok(i) = l.parse::<i32>() {
-                    if i >= std::u8::MIN as i32 && i <= std::u8::MAX as i32 {
-                        stream.write_all(&[1])?;
-                        stream.write_all(&[i as u8])?;
-                    } else if i >= std::i16::MIN as i32 && i <= std::i16::MAX as i32 {
-                        stream.write_all(&[2])?;
-                        stream.write_all(&(i as i16).to_be_bytes())?;
-                    } else {
-                        stream.write_all(&[3])?;
-                        stream.write_all(&i.to_be_bytes())?;
-                    }
-                } else {
-                    return Err(LitsCcError::Msg(format!("Unexpected literal: {}", l)));
-                }
+            if l.starts_with('"') && l.ends_with('"') {
+                // this is a string!
+                let inner = l.split('\"').nth(1).unwrap();
+                stream.write_all(&[4, inner.len() as u8])?;
+                stream.write_all(inner.as_bytes())?;
+            } else if let Ok(i) = l.parse::<i32>() {
+                write_number(stream, i)?;
+            } else {
+                return Err(LitsCcError::Msg(format!("Unexpected literal: {}", l)));
             }
-            TokenTree::Punct(p) => {
-                if p.as_char() == '@' {
-                    // next should be an identifier
-                    match iter.next() {
-                        Some(TokenTree::Ident(i)) => {
-                            let name = format!("{}", i);
-                            stream.write_all(&[6])?;
-                            stream.write_all(&state.get_variable_id(&name)?.to_be_bytes())?;
-                        }
-                        _ => {
-                            return Err(LitsCcError::ExpectedIdent);
-                        }
+        }
+        TokenTree::Punct(p) => {
+            if p.as_char() == '@' {
+                // next should be an identifier
+                match iter.next() {
+                    Some(TokenTree::Ident(i)) => {
+                        let name = format!("{}", i);
+                        stream.write_all(&[6])?;
+                        stream.write_symbol(state.get_variable_id(&name))?;
+                    }
+                    _ => {
+                        return Err(LitsCcError::ExpectedIdent);
                     }
-                } else {
-                    return Err(LitsCcError::Msg(format!("Unexpected punctuation: {}", p)));
                 }
+            } else if p.as_char() == '-' {
+                // a negative number, which is only in range once it has been negated
+                let l = match iter.next() {
+                    Some(TokenTree::Literal(l)) => format!("{}", l),
+                    _ => return Err(LitsCcError::StaticMsg("Expected a number after '-'")),
+                };
+                let i = l
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|i| i32::try_from(-i).ok())
+                    .ok_or_else(|| LitsCcError::Msg(format!("Unexpected literal: -{}", l)))?;
+                write_number(stream, i)?;
+            } else {
+                return Err(LitsCcError::Msg(format!("Unexpected punctuation: {}", p)));
+            }
+        }
+        TokenTree::Group(g) => {
+            if g.delimiter() != Delimiter::Parenthesis {
+                return Err(LitsCcError::StaticMsg(
+                    "The only group supported at the moment are parenthesis.",
+                ));
             }
-            TokenTree::Group(g) => {
-                if g.delimiter() != Delimiter::Parenthesis {
-                    return Err(LitsCcError::StaticMsg(
-                        "The only group supported at the moment are parenthesis.",
-                    ));
-                }
 
-                let mut cursor = Cursor::new(Vec::new());
+            let mut inner = Fragment::new();
 
-                let mut group_iter = g.stream().into_iter();
-                let length = process_literals(&mut group_iter, &mut cursor, state)?;
+            let mut group_iter = g.stream().into_iter();
+            let length = process_literals(&mut group_iter, &mut inner, state)?;
 
-                cursor.seek(SeekFrom::Start(0))?;
-                stream.write_all(&[5, length as u8])?;
+            stream.write_all(&[5, length as u8])?;
 
-                // copy bytes from the cursor into the file
-                io::copy(&mut cursor, stream)?;
-            }
-            _ => return Err(LitsCcError::Msg(format!("Unexpected token: {}", token))),
+            // copy the inner fragment into the file
+            stream.append(inner);
         }
+        _ => return Err(LitsCcError::Msg(format!("Unexpected token: {}", token))),
+    }
+
+    Ok(())
+}
+
+pub fn process_literals<TIter: Iterator<Item = TokenTree>>(
+    iter: &mut TIter,
+    stream: &mut Fragment,
+    state: &mut CompilerState,
+) -> Result<usize, LitsCcError> {
+    let mut elements_processed = 0;
 
+    while let Some(token) = iter.next() {
+        process_literal(token, iter, stream, state)?;
         elements_processed += 1;
     }
 
     Ok(elements_processed)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proc_macro2::TokenStream;
+
+    #[test]
+    fn negative_numbers_in_tuples() {
+        let source: TokenStream = "(-5 -300 -2147483648)".parse().unwrap();
+        let mut stream = Fragment::new();
+        let count = process_literals(
+            &mut source.into_iter(),
+            &mut stream,
+            &mut CompilerState::new(),
+        );
+
+        assert_eq!(count.unwrap(), 1);
+        let mut expected = vec![5, 3, 2];
+        expected.extend_from_slice(&(-5i16).to_be_bytes());
+        expected.push(2);
+        expected.extend_from_slice(&(-300i16).to_be_bytes());
+        expected.push(3);
+        expected.extend_from_slice(&i32::MIN.to_be_bytes());
+        assert_eq!(stream.code, expected);
+    }
+}
@@ -3,49 +3,165 @@
 
 #![allow(clippy::new_without_default)]
 
+mod cache;
+pub use cache::Cache;
+
 mod command;
 pub use command::process_command;
 
 mod compile;
 
+mod debug;
+
 mod error;
 pub use error::LitsCcError;
 
+mod format;
+
+mod fragment;
+pub use fragment::{Fragment, Relocation, Symbol, SymbolKind};
+
+mod link;
+
 mod literals;
-pub use literals::process_literals;
+pub use literals::{process_literal, process_literals};
+
+mod script;
 
 mod state;
 pub use state::CompilerState;
 
 use std::{
     env,
-    fs::File,
-    io::{prelude::*, BufReader, BufWriter},
+    fs::{self, File},
+    io::{prelude::*, BufWriter},
+    path::Path,
     process,
 };
 
+// the compiler doesn't link the engine, and its errors are its output rather than a log, so they
+// are written to standard error directly
+
+// fingerprint the files that a fragment's imports come from, so that it is recompiled if they change
+fn dependency_fingerprint(fragment: &Fragment, previous: &[(u64, Fragment)]) -> u64 {
+    let mut hasher = cache::Fnv64::new();
+    for name in fragment.imports() {
+        let provider = previous
+            .iter()
+            .rev()
+            .find(|(_, f)| f.defines(name))
+            .map_or(0, |(h, _)| *h);
+
+        hasher.update(name.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(&provider.to_be_bytes());
+    }
+    hasher.finish()
+}
+
+fn compile_files(inputs: &[String], cache: Option<&Cache>) -> Vec<Fragment> {
+    let mut compiled: Vec<(u64, Fragment)> = Vec::with_capacity(inputs.len());
+
+    for input in inputs {
+        let path = Path::new(input);
+        let source = fs::read(path).unwrap_or_else(|e| {
+            eprintln!("Unable to read {}: {}", input, e);
+            process::exit(1);
+        });
+        let content_hash = cache::hash(&source);
+
+        let cached = cache.and_then(|c| {
+            c.load(path, content_hash, |f| dependency_fingerprint(f, &compiled))
+        });
+
+        let fragment = match cached {
+            Some(fragment) => fragment,
+            None => {
+                let fragment = compile::compile_file(&source[..]).unwrap_or_else(|(index, e)| {
+                    eprintln!("Error occurred in {} on line {}: {}", input, index, e);
+                    process::exit(1);
+                });
+
+                if let Some(c) = cache {
+                    let fingerprint = dependency_fingerprint(&fragment, &compiled);
+                    if let Err(e) = c.store(path, content_hash, fingerprint, &fragment) {
+                        eprintln!("Unable to cache {}: {}", input, e);
+                    }
+                }
+
+                fragment
+            }
+        };
+
+        compiled.push((content_hash, fragment));
+    }
+
+    compiled.into_iter().map(|(_, f)| f).collect()
+}
+
 fn main() {
-    // get input and output file
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
+    // get input and output files, as well as any flags
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|a| a.starts_with("--"));
+    if args.len() < 2 {
         eprintln!("lits-cc expects at least two arguments.");
         process::exit(1);
     }
 
-    let in_file = &args[1];
-    let out_file = &args[2];
+    let compress = flags.iter().any(|f| f == "--compress");
+    let debug = flags.iter().any(|f| f == "--debug");
 
-    // open each file as a bufferred reader/writer
-    let in_file = BufReader::new(File::open(in_file).unwrap());
-    let mut out_file = BufWriter::new(File::create(out_file).unwrap());
+    // the cache is enabled with either --cache or --cache-dir=<dir>
+    let cache_dir = flags.iter().find_map(|f| match f.as_ref() {
+        "--cache" => Some(cache::DEFAULT_CACHE_DIR),
+        f if f.starts_with("--cache-dir=") => Some(&f["--cache-dir=".len()..]),
+        _ => None,
+    });
+    let cache = cache_dir.map(|dir| {
+        Cache::new(dir).unwrap_or_else(|e| {
+            eprintln!("Unable to create cache directory {}: {}", dir, e);
+            process::exit(1);
+        })
+    });
+
+    // every argument but the last is an input file
+    let (in_files, out_file) = args.split_at(args.len() - 1);
+    let fragments = compile_files(in_files, cache.as_ref());
+
+    let out_path = &out_file[0];
+    let out_file = BufWriter::new(File::create(out_path).unwrap());
+
+    let res = if compress {
+        lit_lz::Encoder::new(out_file)
+            .map_err(LitsCcError::from)
+            .and_then(|mut encoder| {
+                link::link(&fragments, &mut encoder)?;
+                encoder.finish()?;
+                Ok(())
+            })
+    } else {
+        let mut out_file = out_file;
+        link::link(&fragments, &mut out_file).and_then(|()| Ok(out_file.flush()?))
+    };
+
+    if let Err(e) = res {
+        eprintln!("Error occurred while linking: {}", e);
+        process::exit(1);
+    }
 
-    let mut state = CompilerState::new();
+    // debug symbols are written next to the data file
+    if debug {
+        let path = Path::new(&out_path).with_extension(debug::DEBUG_EXTENSION);
+        let res = File::create(&path)
+            .map_err(LitsCcError::from)
+            .and_then(|file| {
+                let mut file = BufWriter::new(file);
+                debug::write_symbols(&mut file, in_files, &fragments)?;
+                Ok(file.flush()?)
+            });
 
-    for (index, line) in in_file.lines().enumerate() {
-        let line_ref = &line.unwrap();
-        let processed_line = line_ref.split('#').next().unwrap_or_else(|| line_ref);
-        if let Err(e) = compile::compile_line(processed_line, &mut out_file, &mut state) {
-            eprintln!("Error occurred on line {}: {}", index, e);
+        if let Err(e) = res {
+            eprintln!("Unable to write debug symbols to {}: {}", path.display(), e);
             process::exit(1);
         }
     }
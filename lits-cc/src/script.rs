@@ -0,0 +1,358 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// script.rs - Compile entry points into code for the VM.
+
+use crate::{
+    command::write_name, format::check_log, process_literal, state::ControlBlock,
+    state::ScriptBlock, CompilerState, Fragment, LitsCcError,
+};
+use proc_macro2::{Spacing, TokenTree};
+use std::{convert::TryFrom, io::prelude::*, iter::Peekable};
+
+const OP_RETURN: u8 = 0x00;
+const OP_PUSH: u8 = 0x01;
+const OP_LOAD: u8 = 0x02;
+const OP_STORE: u8 = 0x03;
+const OP_ADD: u8 = 0x10;
+const OP_SUB: u8 = 0x11;
+const OP_MUL: u8 = 0x12;
+const OP_DIV: u8 = 0x13;
+const OP_MOD: u8 = 0x14;
+const OP_EQ: u8 = 0x20;
+const OP_NE: u8 = 0x21;
+const OP_LT: u8 = 0x22;
+const OP_LE: u8 = 0x23;
+const OP_GT: u8 = 0x24;
+const OP_GE: u8 = 0x25;
+const OP_NOT: u8 = 0x26;
+const OP_JUMP: u8 = 0x30;
+const OP_JUMP_IF_ZERO: u8 = 0x31;
+const OP_LOG: u8 = 0x40;
+const OP_LOG_WARN: u8 = 0x41;
+const OP_LOG_ERROR: u8 = 0x42;
+const OP_REWIND: u8 = 0x50;
+const OP_REWIND_STOP: u8 = 0x51;
+const OP_TIMELINE_FORK: u8 = 0x60;
+const OP_TIMELINE_SWITCH: u8 = 0x61;
+const OP_TIMELINE_MERGE: u8 = 0x62;
+const OP_TIMELINE_GET: u8 = 0x63;
+const OP_TIMELINE_DIFF: u8 = 0x64;
+const OP_MERGE_RULE: u8 = 0x65;
+const OP_TIMELINE_DELETE: u8 = 0x66;
+const OP_TIMELINE_CURRENT: u8 = 0x67;
+const OP_RANDOM: u8 = 0x70;
+const OP_KEY_HELD: u8 = 0x71;
+
+#[inline]
+fn read_name<TIter: Iterator<Item = TokenTree>>(iter: &mut TIter) -> Result<String, LitsCcError> {
+    match iter.next() {
+        Some(TokenTree::Ident(i)) => Ok(format!("{}", i)),
+        _ => Err(LitsCcError::ExpectedIdent),
+    }
+}
+
+// write a jump with a target that is filled in later, and return where the target is
+#[inline]
+fn write_jump(code: &mut Fragment, op: u8) -> Result<u32, LitsCcError> {
+    code.write_all(&[op])?;
+    let patch = code.len();
+    code.write_all(&[0; 4])?;
+    Ok(patch)
+}
+
+/// Begin compiling an entry point, e.g. "on on_update".
+pub fn begin_script<TIter: Iterator<Item = TokenTree>>(
+    iter: &mut TIter,
+    state: &mut CompilerState,
+) -> Result<(), LitsCcError> {
+    let name = read_name(iter)?;
+    state.script = Some(ScriptBlock {
+        name,
+        code: Fragment::new(),
+        blocks: vec![],
+    });
+    Ok(())
+}
+
+// compile a single value: a variable, a literal, a negative number or a value with a "!" in
+// front of it, which is 1 if the value is 0 and 0 otherwise
+fn compile_operand<TIter: Iterator<Item = TokenTree>>(
+    iter: &mut Peekable<TIter>,
+    code: &mut Fragment,
+    state: &mut CompilerState,
+) -> Result<(), LitsCcError> {
+    match iter.next() {
+        Some(TokenTree::Punct(ref p)) if p.as_char() == '@' => {
+            let name = read_name(iter)?;
+            code.write_all(&[OP_LOAD])?;
+            code.write_symbol(state.get_variable_id(&name))?;
+            Ok(())
+        }
+        Some(TokenTree::Punct(ref p)) if p.as_char() == '-' => match iter.next() {
+            Some(TokenTree::Literal(l)) => {
+                // the number is parsed before it is negated, and -2147483648 is only in range
+                // once it has been
+                let val = format!("{}", l)
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|val| i32::try_from(-val).ok())
+                    .ok_or_else(|| LitsCcError::Msg(format!("Unexpected literal: -{}", l)))?;
+                code.write_all(&[OP_PUSH, 3])?;
+                code.write_all(&val.to_be_bytes())?;
+                Ok(())
+            }
+            _ => Err(LitsCcError::StaticMsg("Expected a number after '-'")),
+        },
+        Some(TokenTree::Punct(ref p)) if p.as_char() == '!' => {
+            compile_operand(iter, code, state)?;
+            code.write_all(&[OP_NOT])?;
+            Ok(())
+        }
+        Some(token) => {
+            code.write_all(&[OP_PUSH])?;
+            process_literal(token, iter, code, state)
+        }
+        None => Err(LitsCcError::StaticMsg("Expected a value")),
+    }
+}
+
+// read an operator such as "+" or "<="
+fn read_operator<TIter: Iterator<Item = TokenTree>>(
+    iter: &mut Peekable<TIter>,
+) -> Result<Option<u8>, LitsCcError> {
+    let first = match iter.next() {
+        Some(TokenTree::Punct(p)) => p,
+        Some(token) => return Err(LitsCcError::UnexpectedToken(token)),
+        None => return Ok(None),
+    };
+
+    let mut op = first.as_char().to_string();
+    if first.spacing() == Spacing::Joint {
+        if let Some(TokenTree::Punct(ref p)) = iter.peek() {
+            if p.as_char() == '=' {
+                op.push('=');
+                iter.next();
+            }
+        }
+    }
+
+    match op.as_ref() {
+        "+" => Ok(Some(OP_ADD)),
+        "-" => Ok(Some(OP_SUB)),
+        "*" => Ok(Some(OP_MUL)),
+        "/" => Ok(Some(OP_DIV)),
+        "%" => Ok(Some(OP_MOD)),
+        "==" => Ok(Some(OP_EQ)),
+        "!=" => Ok(Some(OP_NE)),
+        "<" => Ok(Some(OP_LT)),
+        "<=" => Ok(Some(OP_LE)),
+        ">" => Ok(Some(OP_GT)),
+        ">=" => Ok(Some(OP_GE)),
+        _ => Err(LitsCcError::UnknownOperator(op)),
+    }
+}
+
+// compile an expression, which is a series of values separated by operators. operators are
+// evaluated from left to right, without any precedence
+fn compile_expression<TIter: Iterator<Item = TokenTree>>(
+    iter: &mut Peekable<TIter>,
+    code: &mut Fragment,
+    state: &mut CompilerState,
+) -> Result<(), LitsCcError> {
+    compile_operand(iter, code, state)?;
+
+    while let Some(op) = read_operator(iter)? {
+        compile_operand(iter, code, state)?;
+        code.write_all(&[op])?;
+    }
+
+    Ok(())
+}
+
+fn compile_statement<TIter: Iterator<Item = TokenTree>>(
+    iter: &mut Peekable<TIter>,
+    script: &mut ScriptBlock,
+    state: &mut CompilerState,
+) -> Result<bool, LitsCcError> {
+    let command = match iter.next() {
+        Some(TokenTree::Ident(i)) => format!("{}", i),
+        None => return Ok(false), // empty line, write nothing
+        _ => return Err(LitsCcError::ExpectedIdent),
+    };
+    let code = &mut script.code;
+
+    match command.as_ref() {
+        "set" => {
+            let id = state.get_variable_id(&read_name(iter)?);
+            compile_expression(iter, code, state)?;
+            code.write_all(&[OP_STORE])?;
+            code.write_symbol(id)?;
+        }
+        "add" | "sub" | "mul" | "div" | "mod" => {
+            let id = state.get_variable_id(&read_name(iter)?);
+            code.write_all(&[OP_LOAD])?;
+            code.write_symbol(id)?;
+            compile_expression(iter, code, state)?;
+            code.write_all(&[match command.as_ref() {
+                "add" => OP_ADD,
+                "sub" => OP_SUB,
+                "mul" => OP_MUL,
+                "div" => OP_DIV,
+                _ => OP_MOD,
+            }])?;
+            code.write_all(&[OP_STORE])?;
+            code.write_symbol(id)?;
+        }
+        "log" | "log_warn" | "log_error" => {
+            // push the format string and the tuple
+            let missing = || LitsCcError::StaticMsg("log expects a format string and a tuple");
+            let format = iter.next().ok_or_else(missing)?;
+            check_log(Some(&format), iter.peek())?;
+            code.write_all(&[OP_PUSH])?;
+            process_literal(format, iter, code, state)?;
+
+            let tuple = iter.next().ok_or_else(missing)?;
+            code.write_all(&[OP_PUSH])?;
+            process_literal(tuple, iter, code, state)?;
+            code.write_all(&[match command.as_ref() {
+                "log" => OP_LOG,
+                "log_warn" => OP_LOG_WARN,
+                _ => OP_LOG_ERROR,
+            }])?;
+        }
+        "rewind" => {
+            // the number of frames to go back every frame
+            compile_expression(iter, code, state)?;
+            code.write_all(&[OP_REWIND])?;
+        }
+        "rewind_stop" => code.write_all(&[OP_REWIND_STOP])?,
+        "timeline_fork" | "timeline_switch" | "timeline_merge" | "timeline_delete" => {
+            // the name of the timeline
+            compile_expression(iter, code, state)?;
+            code.write_all(&[match command.as_ref() {
+                "timeline_fork" => OP_TIMELINE_FORK,
+                "timeline_switch" => OP_TIMELINE_SWITCH,
+                "timeline_merge" => OP_TIMELINE_MERGE,
+                _ => OP_TIMELINE_DELETE,
+            }])?;
+        }
+        "timeline_get" => {
+            // e.g. "timeline_get RESULT "past" DOOR_OPEN"
+            let id = state.get_variable_id(&read_name(iter)?);
+            compile_operand(iter, code, state)?;
+            let var = state.get_variable_id(&read_name(iter)?);
+            code.write_all(&[OP_TIMELINE_GET])?;
+            code.write_symbol(var)?;
+            code.write_all(&[OP_STORE])?;
+            code.write_symbol(id)?;
+        }
+        "timeline_diff" => {
+            let id = state.get_variable_id(&read_name(iter)?);
+            compile_expression(iter, code, state)?;
+            code.write_all(&[OP_TIMELINE_DIFF, OP_STORE])?;
+            code.write_symbol(id)?;
+        }
+        "timeline_current" => {
+            let id = state.get_variable_id(&read_name(iter)?);
+            code.write_all(&[OP_TIMELINE_CURRENT, OP_STORE])?;
+            code.write_symbol(id)?;
+        }
+        "random" | "key_held" => {
+            // e.g. "random ROLL 6" or "key_held JUMPING 32"
+            let id = state.get_variable_id(&read_name(iter)?);
+            compile_expression(iter, code, state)?;
+            code.write_all(&[
+                if command == "random" {
+                    OP_RANDOM
+                } else {
+                    OP_KEY_HELD
+                },
+                OP_STORE,
+            ])?;
+            code.write_symbol(id)?;
+        }
+        "merge_rule" => {
+            let var = state.get_variable_id(&read_name(iter)?);
+            let rule = match read_name(iter)?.as_ref() {
+                "keep" => 0,
+                "take" => 1,
+                "max" => 2,
+                "min" => 3,
+                _ => {
+                    return Err(LitsCcError::StaticMsg(
+                        "merge_rule expects keep, take, max or min",
+                    ))
+                }
+            };
+            code.write_all(&[OP_MERGE_RULE])?;
+            code.write_symbol(var)?;
+            code.write_all(&[rule])?;
+        }
+        "if" => {
+            compile_expression(iter, code, state)?;
+            let patch = write_jump(code, OP_JUMP_IF_ZERO)?;
+            script.blocks.push(ControlBlock::If { patch });
+        }
+        "else" => match script.blocks.pop() {
+            Some(ControlBlock::If { patch }) => {
+                // the true branch skips over the else branch
+                let end_patch = write_jump(code, OP_JUMP)?;
+                code.patch_u32(patch, code.len());
+                script.blocks.push(ControlBlock::Else { patch: end_patch });
+            }
+            _ => return Err(LitsCcError::StaticMsg("else without a matching if")),
+        },
+        "while" => {
+            let start = code.len();
+            compile_expression(iter, code, state)?;
+            let patch = write_jump(code, OP_JUMP_IF_ZERO)?;
+            script.blocks.push(ControlBlock::While { start, patch });
+        }
+        "return" => code.write_all(&[OP_RETURN])?,
+        "end" => match script.blocks.pop() {
+            Some(ControlBlock::If { patch }) | Some(ControlBlock::Else { patch }) => {
+                code.patch_u32(patch, code.len());
+            }
+            Some(ControlBlock::While { start, patch }) => {
+                code.write_all(&[OP_JUMP])?;
+                code.write_all(&start.to_be_bytes())?;
+                code.patch_u32(patch, code.len());
+            }
+            // this is the end of the entry point itself
+            None => return Ok(true),
+        },
+        _ => return Err(LitsCcError::UnknownCommand(command)),
+    }
+
+    match iter.next() {
+        Some(token) => Err(LitsCcError::UnexpectedToken(token)),
+        None => Ok(false),
+    }
+}
+
+/// Compile a line that is inside of an entry point.
+pub fn compile_script_line<TIter: Iterator<Item = TokenTree>>(
+    iter: &mut TIter,
+    stream: &mut Fragment,
+    state: &mut CompilerState,
+) -> Result<(), LitsCcError> {
+    let mut script = match state.script.take() {
+        Some(s) => s,
+        None => return Err(LitsCcError::StaticMsg("Not inside of an entry point")),
+    };
+
+    let finished = compile_statement(&mut iter.peekable(), &mut script, state);
+    match finished {
+        Ok(true) => {
+            // write out the entry point: its name, then the length of its code and the code itself
+            stream.write_all(&9u16.to_be_bytes())?;
+            write_name(stream, &script.name)?;
+            stream.write_all(&script.code.len().to_be_bytes())?;
+            stream.append(script.code);
+            Ok(())
+        }
+        res => {
+            state.script = Some(script);
+            res.map(|_| ())
+        }
+    }
+}
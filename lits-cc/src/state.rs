@@ -1,33 +1,65 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // state.rs - The current state of the application.
 
-use crate::LitsCcError;
+use crate::{Fragment, Symbol, SymbolKind};
 use std::collections::HashMap;
 
+// control flow blocks inside of a script, along with the spots that need jump targets filled in
+#[derive(Debug)]
+pub enum ControlBlock {
+    If { patch: u32 },
+    Else { patch: u32 },
+    While { start: u32, patch: u32 },
+}
+
+// a script entry point that is currently being compiled
+#[derive(Debug)]
+pub struct ScriptBlock {
+    pub name: String,
+    pub code: Fragment,
+    pub blocks: Vec<ControlBlock>,
+}
+
 pub struct CompilerState {
     variables: HashMap<String, u32>,
-    current_id: u32,
+    symbols: Vec<Symbol>,
+    pub script: Option<ScriptBlock>,
 }
 
 impl CompilerState {
     pub fn new() -> Self {
         Self {
             variables: HashMap::new(),
-            current_id: 1,
+            symbols: vec![],
+            script: None,
         }
     }
 
-    pub fn register_variable(&mut self, name: &str) -> u32 {
-        let id = self.current_id;
+    fn add_symbol(&mut self, name: &str, kind: SymbolKind) -> u32 {
+        let id = self.symbols.len() as u32;
+        self.symbols.push(Symbol {
+            name: String::from(name),
+            kind,
+        });
         self.variables.insert(String::from(name), id);
-        self.current_id += 1;
         id
     }
 
-    pub fn get_variable_id(&self, name: &str) -> Result<u32, LitsCcError> {
+    pub fn register_variable(&mut self, name: &str) -> u32 {
+        self.add_symbol(name, SymbolKind::Definition)
+    }
+
+    // variables not defined in this file are assumed to come from another file, which is checked
+    // when linking
+    pub fn get_variable_id(&mut self, name: &str) -> u32 {
         match self.variables.get(name) {
-            Some(u) => Ok(*u),
-            None => Err(LitsCcError::VariableNotFound(String::from(name))),
+            Some(u) => *u,
+            None => self.add_symbol(name, SymbolKind::Import),
         }
     }
+
+    #[inline]
+    pub fn into_symbols(self) -> Vec<Symbol> {
+        self.symbols
+    }
 }
@@ -0,0 +1,109 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lits-pack/src/entry.rs - Entries in the pack index
+
+use crate::PackError;
+use std::{fmt, io::prelude::*, path::Path};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EntryKind {
+    Raw = 0,
+    Script = 1,
+    Image = 2,
+    Sound = 3,
+}
+
+impl EntryKind {
+    pub fn from_u8(val: u8) -> Result<Self, PackError> {
+        match val {
+            0 => Ok(EntryKind::Raw),
+            1 => Ok(EntryKind::Script),
+            2 => Ok(EntryKind::Image),
+            3 => Ok(EntryKind::Sound),
+            _ => Err(PackError::UnknownKind(val)),
+        }
+    }
+
+    /// Guess the kind of an entry from its file extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("dat") => EntryKind::Script,
+            Some("png") | Some("bmp") | Some("tga") => EntryKind::Image,
+            Some("wav") | Some("ogg") => EntryKind::Sound,
+            _ => EntryKind::Raw,
+        }
+    }
+}
+
+impl fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            EntryKind::Raw => "raw",
+            EntryKind::Script => "script",
+            EntryKind::Image => "image",
+            EntryKind::Sound => "sound",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PackEntry {
+    pub name: String,
+    pub kind: EntryKind,
+    pub offset: u64,
+    pub length: u64,
+    pub checksum: u32,
+}
+
+impl PackEntry {
+    pub fn read<T: Read>(stream: &mut T) -> Result<Self, PackError> {
+        // name is prefixed by its 16-bit length
+        let mut buffer = [0; 2];
+        stream.read_exact(&mut buffer)?;
+        let mut name = vec![0; u16::from_be_bytes(buffer) as usize];
+        stream.read_exact(&mut name)?;
+        let name = String::from_utf8(name)?;
+
+        let mut buffer = [0; 1];
+        stream.read_exact(&mut buffer)?;
+        let kind = EntryKind::from_u8(buffer[0])?;
+
+        let mut buffer = [0; 8];
+        stream.read_exact(&mut buffer)?;
+        let offset = u64::from_be_bytes(buffer);
+        stream.read_exact(&mut buffer)?;
+        let length = u64::from_be_bytes(buffer);
+
+        let mut buffer = [0; 4];
+        stream.read_exact(&mut buffer)?;
+        let checksum = u32::from_be_bytes(buffer);
+
+        Ok(Self {
+            name,
+            kind,
+            offset,
+            length,
+            checksum,
+        })
+    }
+
+    pub fn write<T: Write>(&self, stream: &mut T) -> Result<(), PackError> {
+        if self.name.len() > u16::MAX as usize {
+            return Err(PackError::NameTooLong(self.name.clone()));
+        }
+
+        stream.write_all(&(self.name.len() as u16).to_be_bytes())?;
+        stream.write_all(self.name.as_bytes())?;
+        stream.write_all(&[self.kind as u8])?;
+        stream.write_all(&self.offset.to_be_bytes())?;
+        stream.write_all(&self.length.to_be_bytes())?;
+        stream.write_all(&self.checksum.to_be_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lits-pack/src/error.rs - Errors that occur while reading or writing packs
+
+use std::{io::Error as IoError, string::FromUtf8Error};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PackError {
+    #[error("An IO error occurred: {0}")]
+    Io(#[from] IoError),
+    #[error("Error converting from UTF-8: {0}")]
+    FromUtf8(#[from] FromUtf8Error),
+    #[error("File is not a pack")]
+    BadMagic,
+    #[error("Unsupported pack version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Unknown entry kind {0}")]
+    UnknownKind(u8),
+    #[error("Pack does not contain an entry named {0}")]
+    EntryNotFound(String),
+    #[error("Pack already contains an entry named {0}")]
+    DuplicateEntry(String),
+    #[error("Entry name {0} is too long")]
+    NameTooLong(String),
+    #[error("Entry {0} failed its integrity check")]
+    ChecksumMismatch(String),
+}
@@ -0,0 +1,32 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lits-pack/src/lib.rs - Archive format bundling compiled scripts and assets
+
+mod entry;
+pub use entry::{EntryKind, PackEntry};
+
+mod error;
+pub use error::PackError;
+
+mod reader;
+pub use reader::PackReader;
+
+mod writer;
+pub use writer::PackWriter;
+
+// a pack consists of a header, the data of every entry and then the index:
+//
+//  magic (8 bytes)
+//  version (u16 BE)
+//  entry count (u32 BE)
+//  index offset (u64 BE)
+//  entry data...
+//  index entries...
+
+/// Magic bytes at the start of every pack.
+pub const MAGIC: [u8; 8] = *b"LITPACK\0";
+
+/// The version of the pack format written by `PackWriter`.
+pub const VERSION: u16 = 1;
+
+/// The length of the pack header, in bytes.
+pub const HEADER_LEN: u64 = 22;
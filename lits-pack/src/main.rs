@@ -0,0 +1,84 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lits-pack/src/main.rs - Tool for building and listing packs
+
+use lits_pack::{EntryKind, PackError, PackReader, PackWriter};
+use std::{
+    env,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    process,
+};
+
+// lits-pack doesn't link the engine, and what it prints is its output rather than a log, so it
+// writes to standard output and standard error directly
+
+fn build(out_file: &str, inputs: &[String]) -> Result<(), PackError> {
+    let mut pack = PackWriter::new(BufWriter::new(File::create(out_file)?))?;
+
+    for input in inputs {
+        // entries are either given as "name=path" or just a path, which is named after its stem
+        let (name, path) = match input.find('=') {
+            Some(i) => (String::from(&input[..i]), &input[i + 1..]),
+            None => (
+                Path::new(input)
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or(input)
+                    .to_string(),
+                &input[..],
+            ),
+        };
+
+        let kind = EntryKind::from_path(path);
+        let mut data = BufReader::new(File::open(path)?);
+        pack.add_entry(&name, kind, &mut data)?;
+        println!("Added {} ({}) from {}", name, kind, path);
+    }
+
+    pack.finish()?;
+    Ok(())
+}
+
+fn list(in_file: &str) -> Result<(), PackError> {
+    let mut pack = PackReader::new(BufReader::new(File::open(in_file)?))?;
+    let names: Vec<String> = pack.entries().iter().map(|e| e.name.clone()).collect();
+
+    for name in names {
+        let status = match pack.read_entry(&name) {
+            Ok(_) => "ok",
+            Err(PackError::ChecksumMismatch(_)) => "CORRUPT",
+            Err(e) => return Err(e),
+        };
+        let entry = pack.entry(&name).unwrap();
+        println!(
+            "{:<24} {:<6} {:>10} bytes  {:08X}  {}",
+            entry.name, entry.kind, entry.length, entry.checksum, status
+        );
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        eprintln!("Usage: lits-pack build <out.litpack> <[name=]file>...");
+        eprintln!("       lits-pack list <in.litpack>");
+        process::exit(1);
+    }
+
+    let res = match args[1].as_ref() {
+        "build" => build(&args[2], &args[3..]),
+        "list" => list(&args[2]),
+        cmd => {
+            eprintln!("Unknown command: {}", cmd);
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = res {
+        eprintln!("A fatal error occurred: {}", e);
+        process::exit(1);
+    }
+}
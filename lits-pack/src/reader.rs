@@ -0,0 +1,175 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lits-pack/src/reader.rs - Random access to the entries of a pack
+
+use crate::{PackEntry, PackError, MAGIC, VERSION};
+use lit_lz::crc32;
+use std::{
+    collections::HashMap,
+    io::{prelude::*, SeekFrom, Take},
+};
+
+pub struct PackReader<R: Read + Seek> {
+    inner: R,
+    entries: Vec<PackEntry>,
+    names: HashMap<String, usize>,
+}
+
+impl<R: Read + Seek> PackReader<R> {
+    pub fn new(mut inner: R) -> Result<Self, PackError> {
+        inner.seek(SeekFrom::Start(0))?;
+
+        let mut magic = [0; 8];
+        inner.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(PackError::BadMagic);
+        }
+
+        let mut buffer = [0; 2];
+        inner.read_exact(&mut buffer)?;
+        let version = u16::from_be_bytes(buffer);
+        if version != VERSION {
+            return Err(PackError::UnsupportedVersion(version));
+        }
+
+        let mut buffer = [0; 4];
+        inner.read_exact(&mut buffer)?;
+        let entry_count = u32::from_be_bytes(buffer);
+
+        let mut buffer = [0; 8];
+        inner.read_exact(&mut buffer)?;
+        let index_offset = u64::from_be_bytes(buffer);
+
+        // read in the index
+        inner.seek(SeekFrom::Start(index_offset))?;
+        let mut entries = vec![];
+        let mut names = HashMap::new();
+        for i in 0..entry_count as usize {
+            let entry = PackEntry::read(&mut inner)?;
+            // a name can only lead to one entry
+            if names.insert(entry.name.clone(), i).is_some() {
+                return Err(PackError::DuplicateEntry(entry.name));
+            }
+            entries.push(entry);
+        }
+
+        Ok(Self {
+            inner,
+            entries,
+            names,
+        })
+    }
+
+    #[inline]
+    pub fn entries(&self) -> &[PackEntry] {
+        &self.entries
+    }
+
+    #[inline]
+    pub fn entry(&self, name: &str) -> Option<&PackEntry> {
+        self.names.get(name).map(|i| &self.entries[*i])
+    }
+
+    /// Get a stream over the contents of an entry. This does not check the entry's checksum.
+    pub fn open(&mut self, name: &str) -> Result<Take<&mut R>, PackError> {
+        let (offset, length) = self
+            .entry(name)
+            .map(|e| (e.offset, e.length))
+            .ok_or_else(|| PackError::EntryNotFound(String::from(name)))?;
+
+        self.inner.seek(SeekFrom::Start(offset))?;
+        Ok((&mut self.inner).take(length))
+    }
+
+    /// Read the entire contents of an entry, and verify its checksum.
+    pub fn read_entry(&mut self, name: &str) -> Result<Vec<u8>, PackError> {
+        let mut data = vec![];
+        self.open(name)?.read_to_end(&mut data)?;
+
+        match self.entry(name) {
+            Some(e) if e.length == data.len() as u64 && e.checksum == crc32(&data) => Ok(data),
+            _ => Err(PackError::ChecksumMismatch(String::from(name))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntryKind, PackWriter, HEADER_LEN};
+    use std::io::Cursor;
+
+    fn build(entries: &[(&str, EntryKind, &[u8])]) -> Vec<u8> {
+        let mut pack = PackWriter::new(Cursor::new(vec![])).unwrap();
+        for (name, kind, mut data) in entries.iter().copied() {
+            pack.add_entry(name, kind, &mut data).unwrap();
+        }
+        pack.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = build(&[
+            ("game", EntryKind::Script, b"\x00\x01bytecode"),
+            ("empty", EntryKind::Raw, b""),
+            ("tiles", EntryKind::Image, &[0xFF; 10_000]),
+        ]);
+        let mut pack = PackReader::new(Cursor::new(bytes)).unwrap();
+
+        let names: Vec<&str> = pack.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["game", "empty", "tiles"]);
+        assert_eq!(pack.entry("game").unwrap().offset, HEADER_LEN);
+        assert_eq!(pack.entry("tiles").unwrap().kind, EntryKind::Image);
+
+        assert_eq!(pack.read_entry("tiles").unwrap(), vec![0xFF; 10_000]);
+        assert_eq!(pack.read_entry("game").unwrap(), b"\x00\x01bytecode");
+        assert_eq!(pack.read_entry("empty").unwrap(), b"");
+        assert!(matches!(
+            pack.read_entry("music"),
+            Err(PackError::EntryNotFound(ref name)) if name == "music"
+        ));
+    }
+
+    #[test]
+    fn corrupt_entry_fails_checksum() {
+        let mut bytes = build(&[("game", EntryKind::Script, b"bytecode")]);
+        bytes[HEADER_LEN as usize + 3] ^= 0x20;
+        let mut pack = PackReader::new(Cursor::new(bytes)).unwrap();
+        assert!(matches!(
+            pack.read_entry("game"),
+            Err(PackError::ChecksumMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        // the writer refuses duplicates, so write the index by hand
+        let mut bytes = build(&[
+            ("game", EntryKind::Script, b"one"),
+            ("gamf", EntryKind::Script, b"two"),
+        ]);
+        let second = bytes.len() - 4 - 8 - 8 - 1 - 4;
+        bytes[second..second + 4].copy_from_slice(b"game");
+
+        assert!(matches!(
+            PackReader::new(Cursor::new(bytes)),
+            Err(PackError::DuplicateEntry(ref name)) if name == "game"
+        ));
+    }
+
+    #[test]
+    fn not_a_pack() {
+        let mut bytes = build(&[]);
+        bytes[0] = b'X';
+        assert!(matches!(
+            PackReader::new(Cursor::new(bytes)),
+            Err(PackError::BadMagic)
+        ));
+
+        let mut bytes = build(&[]);
+        bytes[9] = 2;
+        assert!(matches!(
+            PackReader::new(Cursor::new(bytes)),
+            Err(PackError::UnsupportedVersion(2))
+        ));
+    }
+}
@@ -0,0 +1,82 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lits-pack/src/writer.rs - Build a pack from a set of entries
+
+use crate::{EntryKind, PackEntry, PackError, HEADER_LEN, MAGIC, VERSION};
+use lit_lz::Crc32;
+use std::io::{prelude::*, SeekFrom};
+
+pub struct PackWriter<W: Write + Seek> {
+    inner: W,
+    entries: Vec<PackEntry>,
+    position: u64,
+}
+
+impl<W: Write + Seek> PackWriter<W> {
+    pub fn new(mut inner: W) -> Result<Self, PackError> {
+        // the entry count and index offset are filled in once the pack is finished
+        inner.seek(SeekFrom::Start(0))?;
+        inner.write_all(&MAGIC)?;
+        inner.write_all(&VERSION.to_be_bytes())?;
+        inner.write_all(&[0; 12])?;
+
+        Ok(Self {
+            inner,
+            entries: vec![],
+            position: HEADER_LEN,
+        })
+    }
+
+    /// Copy the contents of a stream into the pack as a new entry.
+    pub fn add_entry<R: Read>(
+        &mut self,
+        name: &str,
+        kind: EntryKind,
+        data: &mut R,
+    ) -> Result<(), PackError> {
+        if self.entries.iter().any(|e| e.name == name) {
+            return Err(PackError::DuplicateEntry(String::from(name)));
+        }
+
+        let mut crc = Crc32::new();
+        let mut length = 0;
+        let mut buffer = [0; 8192];
+
+        loop {
+            let len = data.read(&mut buffer)?;
+            if len == 0 {
+                break;
+            }
+
+            crc.update(&buffer[..len]);
+            self.inner.write_all(&buffer[..len])?;
+            length += len as u64;
+        }
+
+        self.entries.push(PackEntry {
+            name: String::from(name),
+            kind,
+            offset: self.position,
+            length,
+            checksum: crc.finish(),
+        });
+        self.position += length;
+
+        Ok(())
+    }
+
+    /// Write out the index and return the inner writer.
+    pub fn finish(mut self) -> Result<W, PackError> {
+        let index_offset = self.position;
+        for entry in &self.entries {
+            entry.write(&mut self.inner)?;
+        }
+
+        self.inner.seek(SeekFrom::Start(10))?;
+        self.inner
+            .write_all(&(self.entries.len() as u32).to_be_bytes())?;
+        self.inner.write_all(&index_offset.to_be_bytes())?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
@@ -0,0 +1,16 @@
+#version 330 core
+
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// gl_utils/shaders/indexed.frag - Render a palette-indexed sprite to the screen.
+
+in vec2 tex_coords;
+out vec4 color;
+
+uniform sampler2D image;
+uniform sampler1D palette;
+
+void main() {
+    // the index is stored as a normalized byte
+    int index = int(texture(image, tex_coords).r * 255.0 + 0.5);
+    color = texelFetch(palette, index, 0);
+}
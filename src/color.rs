@@ -29,14 +29,14 @@ impl Color {
             3 => Ok(Self::new(
                 arr[0].try_into()?,
                 arr[1].try_into()?,
-                arr[3].try_into()?,
+                arr[2].try_into()?,
             )),
             4 => match arr[3] {
                 0 => Ok(Self::transparent()),
                 _ => Ok(Self::new(
                     arr[0].try_into()?,
                     arr[1].try_into()?,
-                    arr[3].try_into()?,
+                    arr[2].try_into()?,
                 )),
             },
             _ => Err(LitError::StaticMsg("Colors can only have 3 or 4 members")),
@@ -53,6 +53,16 @@ impl Color {
         }
     }
 
+    #[inline]
+    pub fn as_rgba(self) -> [u8; 4] {
+        [
+            self.r,
+            self.g,
+            self.b,
+            if self.is_transparent { 0 } else { 255 },
+        ]
+    }
+
     #[inline]
     pub fn as_gl_color(self) -> [f32; 4] {
         [
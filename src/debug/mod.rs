@@ -0,0 +1,696 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// debug/mod.rs - Stop while the data files are being evaluated, to look at their statements.
+
+mod symbols;
+mod view;
+
+pub use symbols::*;
+pub use view::{ascii_art, indexed_pixels};
+
+use crate::{BytecodeObject, DataType, LitError, ParserState, PixelBuffer};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, prelude::*, BufReader},
+};
+
+/// Shows the pixels of a texture, e.g. in the game's window.
+pub type TextureViewer = Box<dyn FnMut(&str, &PixelBuffer) -> Result<(), LitError>>;
+
+const HELP: &str = "\
+step, s                 run the next statement and stop again
+continue, c             run until a breakpoint is reached
+break, b <where>        stop at FILE:LINE, LINE of the current file, @OFFSET of the current layer
+                        or LAYER@OFFSET
+breakpoints, bl         list the breakpoints
+delete, d <n>           remove a breakpoint
+where, w                show where loading has stopped
+bytes, x                show the bytes of the statement
+vars, v [name]          show every variable, or one of them
+colors <name>           show the color ids of a texture
+draw <name>             draw a texture as text
+show <name>             show a texture in the window
+detach                  stop debugging and let loading finish
+quit, q                 stop loading
+
+Only the statements at the top level of the data files can be stopped at. The scripts of on
+blocks run later, in the script VM, and can't be debugged.";
+
+/// The name of the command that a statement was compiled from.
+pub fn statement_name(opcode: u16) -> &'static str {
+    match opcode {
+        1 => "gamedef",
+        2 => "def",
+        3 => "log",
+        4 => "create_tex",
+        5 => "color_id",
+        6 => "draw_pixel",
+        7 => "draw_rect",
+        8 => "(name)",
+        9 => "on",
+        10 => "log_warn",
+        11 => "log_error",
+        12 => "blit",
+        13 => "overlay",
+        14 => "tint",
+        15 => "flip",
+        16 => "flip_v",
+        17 => "rotate90",
+        18 => "create_indexed_tex",
+        19 => "create_palette",
+        20 => "cycle_palette",
+        _ => "(unknown)",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Stop at the first statement of a line of a source file.
+    Line { file: String, line: u32 },
+    /// Stop at the statement at an offset into a data file.
+    Offset { layer: usize, offset: usize },
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Breakpoint::Line { ref file, line } => write!(f, "{}:{}", file, line),
+            Breakpoint::Offset { layer, offset } => {
+                write!(f, "layer {}, statement at {:#X}", layer, offset)
+            }
+        }
+    }
+}
+
+// the statement that loading stopped at
+struct Stop<'a> {
+    layer: usize,
+    offset: usize,
+    statement: &'a [u8],
+}
+
+/// Stops before statements of the data files are evaluated, and takes commands from a prompt.
+/// Loading starts out stopped at the first statement.
+///
+/// While the debugger waits for a command, the window's events aren't handled, so the window can
+/// seem to hang until loading carries on.
+pub struct Debugger {
+    // the debug symbols of each layer, if it has any
+    symbols: Vec<Option<DebugSymbols>>,
+    breakpoints: Vec<Breakpoint>,
+    // stop at the next statement, whether or not it has a breakpoint
+    stepping: bool,
+    // never stop again
+    detached: bool,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    viewer: Option<TextureViewer>,
+}
+
+impl Debugger {
+    pub fn new(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        Self {
+            symbols: vec![],
+            breakpoints: vec![],
+            stepping: true,
+            detached: false,
+            input,
+            output,
+            viewer: None,
+        }
+    }
+
+    /// A debugger that is driven from the terminal.
+    pub fn stdio() -> Self {
+        Self::new(
+            Box::new(BufReader::new(io::stdin())),
+            Box::new(io::stdout()),
+        )
+    }
+
+    /// Use the debug symbols of a layer to find the source lines of its statements.
+    pub fn set_symbols(&mut self, layer: usize, symbols: DebugSymbols) {
+        if self.symbols.len() <= layer {
+            self.symbols.resize_with(layer + 1, || None);
+        }
+        self.symbols[layer] = Some(symbols);
+    }
+
+    #[inline]
+    fn symbols(&self, layer: usize) -> Option<&DebugSymbols> {
+        self.symbols.get(layer).and_then(|s| s.as_ref())
+    }
+
+    /// Set what the `show` command shows textures with. Without a viewer, textures can only be
+    /// drawn as text.
+    #[inline]
+    pub fn set_viewer(&mut self, viewer: TextureViewer) {
+        self.viewer = Some(viewer);
+    }
+
+    #[inline]
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    #[inline]
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Called before a statement is evaluated. If the debugger stops at the statement, this only
+    /// returns once it is told to carry on.
+    pub fn before_statement(
+        &mut self,
+        layer: usize,
+        offset: usize,
+        statement: &[u8],
+        state: &ParserState,
+    ) -> Result<(), LitError> {
+        if self.detached {
+            return Ok(());
+        }
+
+        let hit = self
+            .breakpoints
+            .iter()
+            .position(|b| self.hits(b, layer, offset));
+        if hit.is_none() && !self.stepping {
+            return Ok(());
+        }
+
+        let stop = Stop {
+            layer,
+            offset,
+            statement,
+        };
+        let location = self.describe(&stop);
+        match hit {
+            Some(i) => writeln!(self.output, "Breakpoint {}, {}", i + 1, location)?,
+            None => writeln!(self.output, "{}", location)?,
+        }
+        self.prompt(&stop, state)
+    }
+
+    /// Called when a statement fails, so that the state it failed in can be looked at.
+    pub fn on_error(
+        &mut self,
+        layer: usize,
+        offset: usize,
+        statement: &[u8],
+        error: &LitError,
+        state: &ParserState,
+    ) -> Result<(), LitError> {
+        if self.detached {
+            return Ok(());
+        }
+
+        let stop = Stop {
+            layer,
+            offset,
+            statement,
+        };
+        let location = self.describe(&stop);
+        writeln!(self.output, "{} failed: {}", location, error)?;
+        self.prompt(&stop, state)
+    }
+
+    fn hits(&self, breakpoint: &Breakpoint, layer: usize, offset: usize) -> bool {
+        match *breakpoint {
+            Breakpoint::Offset {
+                layer: l,
+                offset: o,
+            } => l == layer && o == offset,
+            Breakpoint::Line { ref file, line } => self
+                .symbols(layer)
+                .and_then(|s| s.line_starting_at(offset))
+                .is_some_and(|s| s.line == line && matches_file(s.file, file)),
+        }
+    }
+
+    fn describe(&self, stop: &Stop) -> String {
+        let opcode = match *stop.statement {
+            [high, low, ..] => u16::from_be_bytes([high, low]),
+            _ => 0,
+        };
+        let symbols = self.symbols(stop.layer);
+        let line = match symbols.and_then(|s| s.line_at(stop.offset)) {
+            Some(line) => format!(" ({})", line),
+            None => String::new(),
+        };
+
+        format!(
+            "Layer {}, statement at {:#X}{}: {}",
+            stop.layer,
+            stop.offset,
+            line,
+            statement_name(opcode)
+        )
+    }
+
+    fn prompt(&mut self, stop: &Stop, state: &ParserState) -> Result<(), LitError> {
+        loop {
+            write!(self.output, "(litdbg) ")?;
+            self.output.flush()?;
+
+            // once there is nothing more to read, loading carries on without the debugger
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                self.detached = true;
+                return Ok(());
+            }
+
+            let mut words = line.split_whitespace();
+            let command = match words.next() {
+                Some(command) => command,
+                None => continue,
+            };
+            let arg = words.next();
+
+            match command {
+                "step" | "s" => {
+                    self.stepping = true;
+                    return Ok(());
+                }
+                "continue" | "c" => {
+                    self.stepping = false;
+                    return Ok(());
+                }
+                "detach" => {
+                    self.detached = true;
+                    return Ok(());
+                }
+                "quit" | "q" => return Err(LitError::DebuggerQuit),
+                _ => {}
+            }
+
+            // every other command only looks around, so a mistake in one is just reported
+            if let Err(e) = self.command(command, arg, stop, state) {
+                writeln!(self.output, "{}", e)?;
+            }
+        }
+    }
+
+    fn command(
+        &mut self,
+        command: &str,
+        arg: Option<&str>,
+        stop: &Stop,
+        state: &ParserState,
+    ) -> Result<(), LitError> {
+        match command {
+            "help" | "h" | "?" => writeln!(self.output, "{}", HELP)?,
+            "where" | "w" => {
+                let location = self.describe(stop);
+                writeln!(self.output, "{}", location)?;
+            }
+            "bytes" | "x" => {
+                for row in stop.statement.chunks(16) {
+                    let hex: Vec<String> = row.iter().map(|b| format!("{:02X}", b)).collect();
+                    writeln!(self.output, "{}", hex.join(" "))?;
+                }
+            }
+            "break" | "b" => {
+                let breakpoint = self.parse_breakpoint(need(arg, "a place")?, stop)?;
+                if let Breakpoint::Line { ref file, line } = breakpoint {
+                    if !self
+                        .symbols
+                        .iter()
+                        .flatten()
+                        .any(|s| !s.offsets_of(file, line).is_empty())
+                    {
+                        writeln!(self.output, "No statement starts at {}:{} yet", file, line)?;
+                    }
+                }
+                writeln!(
+                    self.output,
+                    "Breakpoint {} at {}",
+                    self.breakpoints.len() + 1,
+                    breakpoint
+                )?;
+                self.breakpoints.push(breakpoint);
+            }
+            "breakpoints" | "bl" => {
+                for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+                    writeln!(self.output, "{}: {}", i + 1, breakpoint)?;
+                }
+            }
+            "delete" | "d" => {
+                let n = parse_number(need(arg, "a breakpoint number")?)?;
+                if n == 0 || n > self.breakpoints.len() {
+                    return Err(LitError::Msg(format!("There is no breakpoint {}", n)));
+                }
+                self.breakpoints.remove(n - 1);
+            }
+            "vars" | "v" => self.print_vars(arg, state)?,
+            "colors" => {
+                let (name, id) = lookup(need(arg, "a texture")?, state)?;
+                let mut colors: Vec<_> = state
+                    .color_map(id)
+                    .map(|c| c.iter().collect())
+                    .unwrap_or_default();
+                colors.sort_unstable_by_key(|(index, _)| **index);
+
+                writeln!(self.output, "{} has {} color ids", name, colors.len())?;
+                for (index, color) in colors {
+                    if color.is_transparent {
+                        writeln!(self.output, "{:>3}: transparent", index)?;
+                    } else {
+                        writeln!(
+                            self.output,
+                            "{:>3}: ({} {} {})",
+                            index, color.r, color.g, color.b
+                        )?;
+                    }
+                }
+            }
+            "draw" => {
+                let (name, id) = lookup(need(arg, "a texture")?, state)?;
+                match *state.get_variable(id)? {
+                    BytecodeObject::DrawBufferImg(ref buffer) => {
+                        write!(self.output, "{}", ascii_art(&buffer.rasterize()))?;
+                        let composites = buffer.composites().count();
+                        if composites > 0 {
+                            writeln!(
+                                self.output,
+                                "{} blits and other changes to {} are made once loading finishes",
+                                composites, name
+                            )?;
+                        }
+                    }
+                    BytecodeObject::IndexedImg(ref buffer) => writeln!(self.output, "{}", buffer)?,
+                    ref value => {
+                        return Err(LitError::IncorrectDataType(
+                            value.data_type(state),
+                            DataType::DrawBufferImg,
+                        ))
+                    }
+                }
+            }
+            "show" => {
+                let (name, id) = lookup(need(arg, "a texture")?, state)?;
+                let pixels = match *state.get_variable(id)? {
+                    BytecodeObject::DrawBufferImg(ref buffer) => buffer.rasterize(),
+                    BytecodeObject::IndexedImg(ref buffer) => {
+                        indexed_pixels(buffer, state.color_map(id))
+                    }
+                    ref value => {
+                        return Err(LitError::IncorrectDataType(
+                            value.data_type(state),
+                            DataType::DrawBufferImg,
+                        ))
+                    }
+                };
+
+                let viewer = self.viewer.as_mut().ok_or(LitError::StaticMsg(
+                    "There is no window to show textures in",
+                ))?;
+                viewer(name, &pixels)?;
+            }
+            _ => {
+                return Err(LitError::Msg(format!(
+                    "Unknown command {}, try help",
+                    command
+                )))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_breakpoint(&self, arg: &str, stop: &Stop) -> Result<Breakpoint, LitError> {
+        if let Some((layer, offset)) = arg.split_once('@') {
+            let layer = if layer.is_empty() {
+                stop.layer
+            } else {
+                parse_number(layer)?
+            };
+            return Ok(Breakpoint::Offset {
+                layer,
+                offset: parse_number(offset)?,
+            });
+        }
+
+        if let Some((file, line)) = arg.rsplit_once(':') {
+            return Ok(Breakpoint::Line {
+                file: file.to_string(),
+                line: parse_number(line)? as u32,
+            });
+        }
+
+        // a line on its own is in the file that loading stopped in
+        let file = self
+            .symbols(stop.layer)
+            .and_then(|s| s.line_at(stop.offset))
+            .map(|l| l.file.to_string())
+            .ok_or(LitError::StaticMsg(
+                "This layer has no debug symbols, so a file has to be given",
+            ))?;
+        Ok(Breakpoint::Line {
+            file,
+            line: parse_number(arg)? as u32,
+        })
+    }
+
+    fn print_vars(&mut self, name: Option<&str>, state: &ParserState) -> Result<(), LitError> {
+        let names: HashMap<u32, &str> = state
+            .names
+            .iter()
+            .map(|(name, id)| (*id, name.as_str()))
+            .collect();
+        let ids = match name {
+            Some(name) => vec![lookup(name, state)?.1],
+            None => {
+                let mut ids: Vec<u32> = state.variables.keys().copied().collect();
+                ids.sort_unstable();
+                ids
+            }
+        };
+
+        for id in ids {
+            let value = state.get_variable(id)?;
+            let shown = match *value {
+                BytecodeObject::DrawBufferImg(ref buffer) => {
+                    format!("{}x{}", buffer.width(), buffer.height())
+                }
+                BytecodeObject::IndexedImg(ref buffer) => {
+                    format!("{}x{}", buffer.width(), buffer.height())
+                }
+                BytecodeObject::Palette => String::new(),
+                _ => value
+                    .stringify(state)
+                    .unwrap_or_else(|e| format!("<{}>", e)),
+            };
+            writeln!(
+                self.output,
+                "{:>5} {} ({:?}) {}",
+                id,
+                names.get(&id).unwrap_or(&"-"),
+                value.data_type(state),
+                shown
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// commands that need an argument say what it should have been
+#[inline]
+fn need<'a>(arg: Option<&'a str>, what: &str) -> Result<&'a str, LitError> {
+    arg.ok_or_else(|| LitError::Msg(format!("Expected {}", what)))
+}
+
+// numbers can be given in hex, like the offsets the debugger shows
+fn parse_number(s: &str) -> Result<usize, LitError> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| LitError::Msg(format!("{} is not a number", s)))
+}
+
+// variables are looked up by name, or by id like "#12"
+fn lookup<'a>(name: &'a str, state: &ParserState) -> Result<(&'a str, u32), LitError> {
+    let id = match name.strip_prefix('#') {
+        Some(id) => parse_number(id)? as u32,
+        None => *state
+            .names
+            .get(name)
+            .ok_or_else(|| LitError::UnknownVariableName(name.to_string()))?,
+    };
+    state.get_variable(id)?;
+    Ok((name, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{symbols::tests::write_symbols, *};
+    use crate::{GameData, Limits, LoadPolicy};
+    use std::{cell::RefCell, io::Cursor, rc::Rc};
+
+    // what `lits-cc --debug` writes for this test.lits:
+    //   1 gamedef "Test"
+    //   2 def MY_VAR 1
+    //   4 create_tex SPRITE 5 5 (255 0 0 1)
+    //   5 draw_pixel @SPRITE 1 1 0 (0 0 0 1)
+    //   6 draw_rect @SPRITE 2 2 4 4 0 (0 0 0 1)
+    fn data() -> Vec<u8> {
+        let statements: [&[u8]; 6] = [
+            // 0x00 gamedef
+            &[0, 1, 4, 4, b'T', b'e', b's', b't'],
+            // 0x08 the name of MY_VAR
+            &[0, 8, 0, 0, 0, 1, 4, 6, b'M', b'Y', b'_', b'V', b'A', b'R'],
+            // 0x16 def
+            &[0, 2, 0, 0, 0, 1, 1, 1],
+            // 0x1E create_tex
+            &[0, 4, 0, 0, 0, 2, 1, 5, 1, 5, 5, 4, 1, 255, 1, 0, 1, 0, 1, 1],
+            // 0x32 draw_pixel
+            &[
+                0, 6, 6, 0, 0, 0, 2, 1, 1, 1, 1, 1, 0, 5, 4, 1, 0, 1, 0, 1, 0, 1, 1,
+            ],
+            // 0x49 draw_rect
+            &[
+                0, 7, 6, 0, 0, 0, 2, 1, 2, 1, 2, 1, 4, 1, 4, 1, 0, 5, 4, 1, 0, 1, 0, 1, 0, 1, 1,
+            ],
+        ];
+        statements.concat()
+    }
+
+    const LINES: &[(u32, u16, u32)] = &[
+        (0, 0, 1),
+        (0x08, 0, 2),
+        (0x1E, 0, 4),
+        (0x32, 0, 5),
+        (0x49, 0, 6),
+    ];
+
+    // what the debugger writes, kept after the debugger is done with it
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn debugger(commands: &str) -> (Debugger, Output) {
+        let output = Output::default();
+        let mut debugger = Debugger::new(
+            Box::new(Cursor::new(commands.to_string())),
+            Box::new(output.clone()),
+        );
+        let symbols = write_symbols(&["test.lits"], LINES);
+        debugger.set_symbols(0, DebugSymbols::read(&mut &symbols[..]).unwrap());
+        (debugger, output)
+    }
+
+    // load the data file, taking commands from a script
+    fn session(commands: &str) -> (Result<GameData, LitError>, String) {
+        let (mut debugger, output) = debugger(commands);
+        let data = GameData::read_layers_debug(
+            &mut [&data()[..]],
+            LoadPolicy::ReportOnly,
+            Limits::new(),
+            Some(&mut debugger),
+        );
+        let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+        (data, output)
+    }
+
+    fn stop(layer: usize, offset: usize) -> Stop<'static> {
+        Stop {
+            layer,
+            offset,
+            statement: &[],
+        }
+    }
+
+    #[test]
+    fn parses_breakpoints() {
+        let (debugger, _) = debugger("");
+        let at = |arg| debugger.parse_breakpoint(arg, &stop(0, 0x10));
+
+        assert_eq!(
+            at("@0x10").unwrap(),
+            Breakpoint::Offset {
+                layer: 0,
+                offset: 16
+            }
+        );
+        assert_eq!(
+            at("1@16").unwrap(),
+            Breakpoint::Offset {
+                layer: 1,
+                offset: 16
+            }
+        );
+        assert_eq!(
+            at("levels/game.lits:4").unwrap(),
+            Breakpoint::Line {
+                file: String::from("levels/game.lits"),
+                line: 4
+            }
+        );
+        // a line on its own is in the file that loading stopped in
+        assert_eq!(
+            at("9").unwrap(),
+            Breakpoint::Line {
+                file: String::from("test.lits"),
+                line: 9
+            }
+        );
+        assert!(at("game.lits:nine").is_err());
+        assert!(debugger.parse_breakpoint("9", &stop(1, 0)).is_err());
+    }
+
+    #[test]
+    fn steps_and_stops_at_breakpoints() {
+        let (data, output) = session(
+            "s\n\
+             w\n\
+             b 5\n\
+             b @0x49\n\
+             bl\n\
+             d 2\n\
+             c\n\
+             v MY_VAR\n\
+             c\n",
+        );
+        assert!(data.is_ok());
+
+        let expected = [
+            "Layer 0, statement at 0x0 (test.lits:1): gamedef",
+            "Layer 0, statement at 0x8 (test.lits:2): (name)",
+            "Breakpoint 1 at test.lits:5",
+            "Breakpoint 2 at layer 0, statement at 0x49",
+            "1: test.lits:5\n2: layer 0, statement at 0x49",
+            "Breakpoint 1, Layer 0, statement at 0x32 (test.lits:5): draw_pixel",
+            "MY_VAR (Numeric8) 1",
+        ];
+        let mut rest = &output[..];
+        for line in &expected {
+            let at = rest
+                .find(line)
+                .unwrap_or_else(|| panic!("{:?} is missing from {}", line, output));
+            rest = &rest[at + line.len()..];
+        }
+        // the deleted breakpoint is never reached
+        assert!(!output.contains("Breakpoint 2,"));
+    }
+
+    #[test]
+    fn quitting_stops_loading() {
+        let (data, _) = session("s\nq\n");
+        assert!(matches!(data, Err(LitError::DebuggerQuit)));
+
+        // once the commands run out, loading finishes by itself
+        let (data, output) = session("");
+        assert!(data.is_ok());
+        assert_eq!(output.matches("(litdbg)").count(), 1);
+    }
+}
@@ -0,0 +1,226 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// debug/symbols.rs - The source lines that the statements of a data file were compiled from.
+
+use crate::LitError;
+use std::{fmt, io::prelude::*};
+
+/// Magic bytes at the start of every debug symbols file.
+pub const DEBUG_MAGIC: [u8; 4] = *b"LDBG";
+
+/// The version of the debug symbols format that can be read.
+pub const DEBUG_VERSION: u8 = 1;
+
+/// The extension of debug symbols files, which lits-cc writes next to the data file when it is
+/// given `--debug`.
+pub const DEBUG_EXTENSION: &str = "litdbg";
+
+/// A line of a source file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SourceLine<'a> {
+    pub file: &'a str,
+    /// The number of the line, counting from 1.
+    pub line: u32,
+}
+
+impl<'a> fmt::Display for SourceLine<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Where the statements of a data file came from. Only the first statement of every line is
+/// listed, and the statements after it belong to the same line.
+#[derive(Debug, Clone)]
+pub struct DebugSymbols {
+    files: Vec<String>,
+    // the offset of a statement, the index of its file and its line, sorted by offset
+    lines: Vec<(usize, usize, u32)>,
+}
+
+impl DebugSymbols {
+    pub fn read<T: Read>(stream: &mut T) -> Result<Self, LitError> {
+        let mut header = [0; 5];
+        stream.read_exact(&mut header)?;
+        if header[..4] != DEBUG_MAGIC || header[4] != DEBUG_VERSION {
+            return Err(LitError::NotDebugSymbols);
+        }
+
+        let mut buffer = [0; 2];
+        stream.read_exact(&mut buffer)?;
+        let mut files = vec![];
+        for _ in 0..u16::from_be_bytes(buffer) {
+            stream.read_exact(&mut buffer)?;
+            let mut name = vec![0; u16::from_be_bytes(buffer) as usize];
+            stream.read_exact(&mut name)?;
+            files.push(String::from_utf8(name)?);
+        }
+
+        let mut buffer = [0; 4];
+        stream.read_exact(&mut buffer)?;
+        let count = u32::from_be_bytes(buffer);
+
+        // the count comes from the file, so the lines are only stored as they are read
+        let mut lines = vec![];
+        let mut entry = [0; 10];
+        for _ in 0..count {
+            stream.read_exact(&mut entry)?;
+            let offset = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]) as usize;
+            let file = u16::from_be_bytes([entry[4], entry[5]]) as usize;
+            let line = u32::from_be_bytes([entry[6], entry[7], entry[8], entry[9]]);
+            if file >= files.len() {
+                return Err(LitError::NotDebugSymbols);
+            }
+            lines.push((offset, file, line));
+        }
+        lines.sort_unstable_by_key(|(offset, _, _)| *offset);
+
+        Ok(Self { files, lines })
+    }
+
+    /// The source files, in the order they were compiled.
+    #[inline]
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// The line that the statement at an offset was compiled from.
+    pub fn line_at(&self, offset: usize) -> Option<SourceLine<'_>> {
+        let i = match self.lines.binary_search_by_key(&offset, |(o, _, _)| *o) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let (_, file, line) = self.lines[i];
+        Some(SourceLine {
+            file: &self.files[file],
+            line,
+        })
+    }
+
+    /// The line that starts with the statement at an offset, if it is the first one of its line.
+    pub fn line_starting_at(&self, offset: usize) -> Option<SourceLine<'_>> {
+        let i = self
+            .lines
+            .binary_search_by_key(&offset, |(o, _, _)| *o)
+            .ok()?;
+        let (_, file, line) = self.lines[i];
+        Some(SourceLine {
+            file: &self.files[file],
+            line,
+        })
+    }
+
+    /// The offsets of the first statement of a line. The file is matched by the end of its path,
+    /// so "game.lits" finds "levels/game.lits".
+    pub fn offsets_of(&self, file: &str, line: u32) -> Vec<usize> {
+        self.lines
+            .iter()
+            .filter(|(_, f, l)| *l == line && matches_file(&self.files[*f], file))
+            .map(|(offset, _, _)| *offset)
+            .collect()
+    }
+}
+
+/// Whether a path ends with a file name, or the path of a file.
+pub fn matches_file(path: &str, file: &str) -> bool {
+    path == file
+        || path
+            .strip_suffix(file)
+            .is_some_and(|rest| rest.ends_with('/') || rest.ends_with('\\'))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Write a debug symbols file the way lits-cc does, from the offset, file and line of the
+    /// first statement of every line.
+    pub(crate) fn write_symbols(files: &[&str], lines: &[(u32, u16, u32)]) -> Vec<u8> {
+        let mut bytes = DEBUG_MAGIC.to_vec();
+        bytes.push(DEBUG_VERSION);
+        bytes.extend(&(files.len() as u16).to_be_bytes());
+        for file in files {
+            bytes.extend(&(file.len() as u16).to_be_bytes());
+            bytes.extend(file.as_bytes());
+        }
+        bytes.extend(&(lines.len() as u32).to_be_bytes());
+        for &(offset, file, line) in lines {
+            bytes.extend(&offset.to_be_bytes());
+            bytes.extend(&file.to_be_bytes());
+            bytes.extend(&line.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn symbols() -> DebugSymbols {
+        let bytes = write_symbols(
+            &["game.lits", "levels/intro.lits"],
+            &[(0x20, 1, 3), (0, 0, 1), (0x10, 0, 2), (0x30, 0, 13)],
+        );
+        DebugSymbols::read(&mut &bytes[..]).unwrap()
+    }
+
+    fn line(file: &str, line: u32) -> Option<SourceLine<'_>> {
+        Some(SourceLine { file, line })
+    }
+
+    #[test]
+    fn reads_what_lits_cc_writes() {
+        let symbols = symbols();
+        assert_eq!(symbols.files(), ["game.lits", "levels/intro.lits"]);
+        assert_eq!(symbols.offsets_of("game.lits", 2), vec![0x10]);
+        assert_eq!(symbols.offsets_of("intro.lits", 3), vec![0x20]);
+        // lines without statements of their own, and lines of other files, aren't found
+        assert!(symbols.offsets_of("game.lits", 3).is_empty());
+        assert!(symbols.offsets_of("intro.lits", 2).is_empty());
+    }
+
+    #[test]
+    fn refuses_other_files() {
+        let mut bytes = write_symbols(&["game.lits"], &[(0, 0, 1)]);
+        bytes[4] = DEBUG_VERSION + 1;
+        assert!(matches!(
+            DebugSymbols::read(&mut &bytes[..]),
+            Err(LitError::NotDebugSymbols)
+        ));
+
+        let mut bytes = write_symbols(&["game.lits"], &[(0, 0, 1)]);
+        bytes[0] = b'X';
+        assert!(matches!(
+            DebugSymbols::read(&mut &bytes[..]),
+            Err(LitError::NotDebugSymbols)
+        ));
+
+        // a line in a file that isn't listed
+        let bytes = write_symbols(&["game.lits"], &[(0, 1, 1)]);
+        assert!(matches!(
+            DebugSymbols::read(&mut &bytes[..]),
+            Err(LitError::NotDebugSymbols)
+        ));
+    }
+
+    #[test]
+    fn finds_lines() {
+        let symbols = symbols();
+        assert_eq!(symbols.line_at(0), line("game.lits", 1));
+        assert_eq!(symbols.line_at(0x10), line("game.lits", 2));
+        // the statements after the first one of a line belong to it
+        assert_eq!(symbols.line_at(0x18), line("game.lits", 2));
+        assert_eq!(symbols.line_at(0x28), line("levels/intro.lits", 3));
+        assert_eq!(symbols.line_at(0x1000), line("game.lits", 13));
+
+        assert_eq!(symbols.line_starting_at(0x20), line("levels/intro.lits", 3));
+        assert_eq!(symbols.line_starting_at(0x18), None);
+        assert_eq!(line("game.lits", 10).unwrap().to_string(), "game.lits:10");
+    }
+
+    #[test]
+    fn matches_the_end_of_paths() {
+        assert!(matches_file("game.lits", "game.lits"));
+        assert!(matches_file("levels/game.lits", "game.lits"));
+        assert!(matches_file("levels\\game.lits", "game.lits"));
+        assert!(matches_file("a/levels/game.lits", "levels/game.lits"));
+        assert!(!matches_file("levels/endgame.lits", "game.lits"));
+        assert!(!matches_file("game.lits", "levels/game.lits"));
+    }
+}
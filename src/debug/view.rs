@@ -0,0 +1,84 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// debug/view.rs - Turn textures into something that can be looked at while debugging.
+
+use crate::{draw::IndexedBuffer, Color, PixelBuffer};
+use std::collections::HashMap;
+
+// the characters used for brighter and brighter pixels. transparent pixels are left blank
+const SHADES: &[u8] = b".:-=+*#%@";
+
+// wider textures are shrunk to fit a terminal
+const MAX_COLUMNS: u32 = 80;
+
+/// Draw pixels as text, one character for every pixel.
+pub fn ascii_art(pixels: &PixelBuffer) -> String {
+    let step = pixels.width().div_ceil(MAX_COLUMNS).max(1);
+    let bytes = pixels.as_bytes();
+
+    let mut art = String::new();
+    for y in (0..pixels.height()).step_by(step as usize) {
+        for x in (0..pixels.width()).step_by(step as usize) {
+            let start = (y * pixels.width() + x) as usize * 4;
+            let pixel = &bytes[start..start + 4];
+            if pixel[3] == 0 {
+                art.push(' ');
+                continue;
+            }
+
+            let (r, g, b) = (pixel[0] as usize, pixel[1] as usize, pixel[2] as usize);
+            let luma = (r * 299 + g * 587 + b * 114) / 1000;
+            art.push(SHADES[luma * SHADES.len() / 256] as char);
+        }
+        art.push('\n');
+    }
+    art
+}
+
+/// The pixels of an indexed texture, colored with its color ids. Indices without a color are
+/// transparent.
+pub fn indexed_pixels(buffer: &IndexedBuffer, colors: Option<&HashMap<u8, Color>>) -> PixelBuffer {
+    let mut pixels = PixelBuffer::new(buffer.width(), buffer.height(), Color::transparent());
+    for y in 0..buffer.height() {
+        for x in 0..buffer.width() {
+            let color = buffer
+                .get(x, y)
+                .and_then(|index| colors.and_then(|c| c.get(&index)));
+            if let Some(color) = color {
+                pixels.fill(x as i32, y as i32, 1, 1, *color);
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shades_pixels_by_brightness() {
+        let mut pixels = PixelBuffer::new(3, 2, Color::transparent());
+        pixels.fill(0, 0, 1, 1, Color::new(255, 255, 255));
+        pixels.fill(1, 0, 1, 1, Color::new(0, 0, 0));
+        pixels.fill(2, 1, 1, 1, Color::new(128, 128, 128));
+        assert_eq!(ascii_art(&pixels), "@. \n  +\n");
+    }
+
+    #[test]
+    fn shrinks_wide_textures() {
+        let pixels = PixelBuffer::new(MAX_COLUMNS * 2 + 1, 4, Color::new(255, 255, 255));
+        let art = ascii_art(&pixels);
+        let rows: Vec<&str> = art.lines().collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|row| row.len() as u32 <= MAX_COLUMNS));
+    }
+
+    #[test]
+    fn indexed_pixels_without_a_color_are_transparent() {
+        let mut buffer = IndexedBuffer::new(2, 1, 2);
+        buffer.draw_pixel(0, 0, 1);
+        let mut colors = HashMap::new();
+        colors.insert(1, Color::new(255, 255, 255));
+        assert_eq!(ascii_art(&indexed_pixels(&buffer, Some(&colors))), "@ \n");
+    }
+}
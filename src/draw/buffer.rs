@@ -1,27 +1,30 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // draw/buffer.rs - Draw buffer implementation
 
-use super::{DrawHandle, DrawInstruction};
-use crate::{Color, ImgTexture, LitError};
-use lit_gl_wrapper::gl::types::GLfloat;
-use std::convert::TryFrom;
+use super::{Composite, DrawHandle, DrawInstruction, PixelBuffer};
+use crate::{Color, Limits, LitError};
+use std::{collections::HashMap, convert::TryFrom, fmt, mem};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DrawBuffer {
     instructions: Vec<DrawInstruction>,
     background_color: Color,
     width: u32,
     height: u32,
+
+    // operations on other textures, along with the number of instructions drawn before them.
+    // they're replaced with plain instructions once the other textures are known
+    composites: Vec<(usize, Composite)>,
 }
 
 impl DrawBuffer {
     #[inline]
-    fn width(&self) -> u32 {
+    pub fn width(&self) -> u32 {
         self.width
     }
 
     #[inline]
-    fn height(&self) -> u32 {
+    pub fn height(&self) -> u32 {
         self.height
     }
 
@@ -34,21 +37,124 @@ impl DrawBuffer {
     fn instructions(&self) -> &[DrawInstruction] {
         &self.instructions
     }
+
+    /// Add an operation that uses other textures. It is applied on top of everything drawn so
+    /// far, once `resolve` is called.
+    #[inline]
+    pub fn composite(&mut self, op: Composite) {
+        self.composites.push((self.instructions.len(), op));
+    }
+
+    #[inline]
+    pub fn composites(&self) -> impl Iterator<Item = &Composite> {
+        self.composites.iter().map(|(_, op)| op)
+    }
+
+    /// Replace every composite operation with plain instructions. Every texture that is copied
+    /// from has to be resolved already.
+    pub fn resolve(
+        &mut self,
+        sources: &HashMap<u32, DrawBuffer>,
+        limits: &Limits,
+    ) -> Result<(), LitError> {
+        if self.composites.is_empty() {
+            return Ok(());
+        }
+
+        let composites = mem::take(&mut self.composites);
+        let drawn = mem::take(&mut self.instructions);
+        let mut start = 0;
+
+        for (end, op) in composites {
+            self.instructions.extend_from_slice(&drawn[start..end]);
+            start = end;
+            self.apply(op, sources)?;
+            limits.check_elements(self.instructions.len())?;
+        }
+
+        self.instructions.extend_from_slice(&drawn[start..]);
+        limits.check_elements(self.instructions.len())
+    }
+
+    fn apply(&mut self, op: Composite, sources: &HashMap<u32, DrawBuffer>) -> Result<(), LitError> {
+        // textures are far smaller than this, thanks to the texture size limit
+        let width = i16::try_from(self.width).unwrap_or(i16::MAX);
+        let height = i16::try_from(self.height).unwrap_or(i16::MAX);
+
+        match op {
+            Composite::Blit { source, x, y } | Composite::Overlay { source, x, y } => {
+                let src = sources
+                    .get(&source)
+                    .ok_or_else(|| LitError::VariableNotFound(source))?;
+                let overlay = matches!(op, Composite::Overlay { .. });
+
+                // the background of the source covers all of it
+                let background = DrawInstruction::Rectangle {
+                    x: 0,
+                    y: 0,
+                    w: i16::try_from(src.width).unwrap_or(i16::MAX),
+                    h: i16::try_from(src.height).unwrap_or(i16::MAX),
+                    color: src.background_color,
+                };
+
+                // an overlay skips transparent instructions, which also means that transparent
+                // pixels drawn over the source's own pixels show those pixels instead
+                self.instructions.extend(
+                    Some(background)
+                        .into_iter()
+                        .chain(src.instructions.iter().copied())
+                        .filter(|i| !(overlay && i.color().is_transparent))
+                        .map(|i| i.translate(x, y)),
+                );
+            }
+            Composite::Tint(color) => {
+                self.background_color = self.background_color.tint(color);
+                for instr in self.instructions.iter_mut() {
+                    *instr = instr.with_color(instr.color().tint(color));
+                }
+            }
+            Composite::FlipHorizontal => {
+                for instr in self.instructions.iter_mut() {
+                    *instr = instr.flip_horizontal(width);
+                }
+            }
+            Composite::FlipVertical => {
+                for instr in self.instructions.iter_mut() {
+                    *instr = instr.flip_vertical(height);
+                }
+            }
+            Composite::Rotate90 => {
+                for instr in self.instructions.iter_mut() {
+                    *instr = instr.rotate90(height);
+                }
+                mem::swap(&mut self.width, &mut self.height);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl DrawHandle for DrawBuffer {
-    fn new(width: u32, height: u32, background_color: Color) -> Self {
+    fn new(width: u32, height: u32, background_color: Color) -> Self
+    where
+        Self: Sized,
+    {
         Self {
             instructions: vec![],
             width,
             height,
             background_color,
+            composites: vec![],
         }
     }
 
     fn draw_pixel(&mut self, x: u32, y: u32, color: Color) -> Result<(), LitError> {
-        self.instructions
-            .push(DrawInstruction::Pixel { x, y, color });
+        self.instructions.push(DrawInstruction::Pixel {
+            x: i16::try_from(x)?,
+            y: i16::try_from(y)?,
+            color,
+        });
         Ok(())
     }
 
@@ -61,77 +167,45 @@ impl DrawHandle for DrawBuffer {
         color: Color,
     ) -> Result<(), LitError> {
         self.instructions.push(DrawInstruction::Rectangle {
-            x,
-            y,
-            width,
-            height,
+            x: i16::try_from(x)?,
+            y: i16::try_from(y)?,
+            w: i16::try_from(width)?,
+            h: i16::try_from(height)?,
             color,
         });
         Ok(())
     }
 }
 
-impl<T: DrawHandle> TryFrom<DrawBuffer> for T {
-    type Error = LitError;
-
-    fn try_from(db: DrawBuffer) -> Result<T, LitError> {
-        let mut res = T::new(db.width(), db.height(), db.background_color());
-        res.instructions().iter().try_for_each(|i| match *i {
-            DrawInstruction::Pixel { x, y, color } => res.draw_pixel(x, y, color),
-            DrawInstruction::Rectangle { x, y, w, h, color } => {
-                res.draw_rectangle(x, y, w, h, color)
-            }
-            DrawInstruction::Square { x, y, l, color } => res.draw_square(x, y, l, color),
-        })?;
-
-        Ok(res)
+impl fmt::Display for DrawBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Texture of {}x{} with {} instructions",
+            self.width,
+            self.height,
+            self.instructions.len()
+        )
     }
 }
 
 impl DrawBuffer {
-    fn create_texture(&self) -> Result<ImgTexture, LitError> {
-        // create a map of the pixels we are using
-        let mut pixel_map = [[self.background_color(); self.width()]; self.height()];
-
-        // for each x and y coordinate, determine the pixel's color
-        (0..self.width()).into_iter().for_each(|i| {
-            (0..self.height()).into_iter().for_each(|j| {
-                // iterate backward until we find something corresponding to our pixel
-                if let Err(()) = self.instructions().iter().rev().try_for_each(|i| match *i {
-                    DrawInstruction::Pixel { x, y, color } => {
-                        if i == x && j == y {
-                            pixel_map[j][i] = color;
-                            Err(())
-                        } else {
-                            Ok(())
-                        }
-                    }
-                    DrawInstruction::Rectangle { x, y, w, h, color } => {
-                        if x <= i && i >= x + w - 1 && y <= j && j >= y + h - 1 {
-                            pixel_map[j][i] = color;
-                            Err(())
-                        } else {
-                            Ok(())
-                        }
-                    }
-                    DrawInstruction::Square { x, y, l, color } => {
-                        if x <= i && i >= x + l - 1 && y <= j && j >= y + l - 1 {
-                            pixel_map[j][i] = color;
-                            Err(())
-                        } else {
-                            Ok(())
-                        }
-                    }
-                }) {}
-            })
-        });
+    /// Draw every instruction into a buffer of pixels. This doesn't need the GL context, so it
+    /// can be done on any thread.
+    pub fn rasterize(&self) -> PixelBuffer {
+        let mut pixels = PixelBuffer::new(self.width(), self.height(), self.background_color());
+
+        // later instructions are drawn over earlier ones
+        for instr in self.instructions() {
+            pixels.fill(
+                instr.x() as i32,
+                instr.y() as i32,
+                instr.width().unwrap_or(1) as i32,
+                instr.height().unwrap_or(1) as i32,
+                instr.color(),
+            );
+        }
 
-        // convert pixel_map to a list of GL floats
-        let mut floats: Vec<GLfloat> = vec![];
-        pixel_map.into_iter().flatten().for_each(|f| floats.extend(f.as_gl_color()));
-         
-        // create a texture from these floats
-        let tex = ImgTexture::from_raw(&self.gl, &[self.width(), self.height()], floats.as_ptr())?;
-        Ok(tex)
+        pixels
     }
 }
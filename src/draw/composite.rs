@@ -0,0 +1,277 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// draw/composite.rs - Operations that build a texture out of other textures.
+
+use super::DrawInstruction;
+use crate::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Composite {
+    // copy another texture, including its background and transparent pixels
+    Blit { source: u32, x: i16, y: i16 },
+    // draw another texture, leaving the pixels underneath wherever it is transparent
+    Overlay { source: u32, x: i16, y: i16 },
+    // multiply the color of everything drawn so far by a color
+    Tint(Color),
+    // mirror everything drawn so far from left to right
+    FlipHorizontal,
+    // mirror everything drawn so far from top to bottom
+    FlipVertical,
+    // turn everything drawn so far a quarter turn clockwise, swapping the width and height
+    Rotate90,
+}
+
+impl Composite {
+    /// The texture variable this operation copies from, if any.
+    #[inline]
+    pub fn source(&self) -> Option<u32> {
+        match *self {
+            Composite::Blit { source, .. } | Composite::Overlay { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Color {
+    /// Multiply each channel by the channel of another color.
+    #[inline]
+    pub fn tint(self, other: Color) -> Self {
+        if self.is_transparent {
+            return self;
+        }
+
+        let mul = |a: u8, b: u8| ((a as u16 * b as u16) / 255) as u8;
+        Self::new(
+            mul(self.r, other.r),
+            mul(self.g, other.g),
+            mul(self.b, other.b),
+        )
+    }
+}
+
+impl DrawInstruction {
+    #[inline]
+    pub fn with_color(self, color: Color) -> Self {
+        match self {
+            DrawInstruction::Pixel { x, y, .. } => DrawInstruction::Pixel { x, y, color },
+            DrawInstruction::Rectangle { x, y, w, h, .. } => DrawInstruction::Rectangle {
+                x,
+                y,
+                w,
+                h,
+                color,
+            },
+            DrawInstruction::Square { x, y, l, .. } => DrawInstruction::Square { x, y, l, color },
+        }
+    }
+
+    #[inline]
+    fn with_position(self, x: i16, y: i16) -> Self {
+        match self {
+            DrawInstruction::Pixel { color, .. } => DrawInstruction::Pixel { x, y, color },
+            DrawInstruction::Rectangle { w, h, color, .. } => DrawInstruction::Rectangle {
+                x,
+                y,
+                w,
+                h,
+                color,
+            },
+            DrawInstruction::Square { l, color, .. } => DrawInstruction::Square { x, y, l, color },
+        }
+    }
+
+    // coordinates past the edge of the texture aren't drawn, so saturating them is harmless
+    #[inline]
+    pub fn translate(self, dx: i16, dy: i16) -> Self {
+        self.with_position(self.x().saturating_add(dx), self.y().saturating_add(dy))
+    }
+
+    /// Mirror the instruction within a texture of the given width.
+    #[inline]
+    pub fn flip_horizontal(self, width: i16) -> Self {
+        let w = self.width().unwrap_or(1);
+        self.with_position(
+            width.saturating_sub(self.x()).saturating_sub(w),
+            self.y(),
+        )
+    }
+
+    /// Mirror the instruction within a texture of the given height.
+    #[inline]
+    pub fn flip_vertical(self, height: i16) -> Self {
+        let h = self.height().unwrap_or(1);
+        self.with_position(
+            self.x(),
+            height.saturating_sub(self.y()).saturating_sub(h),
+        )
+    }
+
+    /// Turn the instruction a quarter turn clockwise within a texture of the given height.
+    #[inline]
+    pub fn rotate90(self, height: i16) -> Self {
+        let x = height
+            .saturating_sub(self.y())
+            .saturating_sub(self.height().unwrap_or(1));
+        let y = self.x();
+        match self {
+            DrawInstruction::Rectangle { w, h, color, .. } => DrawInstruction::Rectangle {
+                x,
+                y,
+                w: h,
+                h: w,
+                color,
+            },
+            instr => instr.with_position(x, y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DrawBuffer, DrawHandle, Limits, LitError};
+    use std::collections::HashMap;
+
+    const RED: Color = Color {
+        r: 255,
+        g: 0,
+        b: 0,
+        is_transparent: false,
+    };
+
+    fn buffer(width: u32, height: u32, background: Color) -> DrawBuffer {
+        DrawBuffer::new(width, height, background)
+    }
+
+    // resolve a texture and draw it as one character per pixel
+    fn resolve(mut buffer: DrawBuffer, sources: &HashMap<u32, DrawBuffer>) -> Vec<String> {
+        buffer.resolve(sources, &Limits::new()).unwrap();
+        assert_eq!(buffer.composites().count(), 0);
+
+        let pixels = buffer.rasterize();
+        pixels
+            .as_bytes()
+            .chunks(pixels.width() as usize * 4)
+            .map(|row| {
+                row.chunks(4)
+                    .map(|p| match *p {
+                        [_, _, _, 0] => ' ',
+                        [0, 0, 0, _] => '.',
+                        [255, 0, 0, _] => 'r',
+                        [0, 255, 0, _] => 'g',
+                        [0, 0, 255, _] => 'b',
+                        [255, 255, 255, _] => 'w',
+                        _ => '?',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // a red pixel in the top left corner and a green line along the bottom right
+    fn corners() -> DrawBuffer {
+        let mut buffer = buffer(3, 2, Color::new(0, 0, 0));
+        buffer.draw_pixel(0, 0, RED).unwrap();
+        buffer
+            .draw_rectangle(1, 1, 2, 1, Color::new(0, 255, 0))
+            .unwrap();
+        buffer
+    }
+
+    fn transformed(op: Composite) -> Vec<String> {
+        let mut buffer = corners();
+        buffer.composite(op);
+        resolve(buffer, &HashMap::new())
+    }
+
+    #[test]
+    fn flips_and_rotations() {
+        assert_eq!(resolve(corners(), &HashMap::new()), ["r..", ".gg"]);
+        assert_eq!(transformed(Composite::FlipHorizontal), ["..r", "gg."]);
+        assert_eq!(transformed(Composite::FlipVertical), [".gg", "r.."]);
+        assert_eq!(transformed(Composite::Rotate90), [".r", "g.", "g."]);
+    }
+
+    #[test]
+    fn operations_apply_to_what_was_drawn_before_them() {
+        let mut buffer = corners();
+        buffer.composite(Composite::FlipHorizontal);
+        buffer.draw_pixel(0, 0, Color::new(0, 0, 255)).unwrap();
+        buffer.composite(Composite::FlipVertical);
+        buffer.draw_pixel(2, 0, Color::new(0, 0, 255)).unwrap();
+        assert_eq!(resolve(buffer, &HashMap::new()), ["ggb", "b.r"]);
+    }
+
+    #[test]
+    fn blits_copy_transparency_and_overlays_skip_it() {
+        // a transparent texture with a green pixel on the right
+        let mut source = buffer(2, 1, Color::transparent());
+        source.draw_pixel(1, 0, Color::new(0, 255, 0)).unwrap();
+        let sources: HashMap<_, _> = vec![(7, source)].into_iter().collect();
+
+        let copied = |op| {
+            let mut buffer = buffer(3, 1, RED);
+            buffer.composite(op);
+            resolve(buffer, &sources)
+        };
+        assert_eq!(
+            copied(Composite::Blit {
+                source: 7,
+                x: 1,
+                y: 0
+            }),
+            ["r g"]
+        );
+        assert_eq!(
+            copied(Composite::Overlay {
+                source: 7,
+                x: 1,
+                y: 0
+            }),
+            ["rrg"]
+        );
+        // parts that land past the edges are left out
+        assert_eq!(
+            copied(Composite::Overlay {
+                source: 7,
+                x: -1,
+                y: 0
+            }),
+            ["grr"]
+        );
+    }
+
+    #[test]
+    fn tints_multiply_colors() {
+        let mut buffer = buffer(3, 1, Color::new(255, 255, 255));
+        buffer.draw_pixel(1, 0, Color::new(0, 255, 0)).unwrap();
+        buffer.draw_pixel(2, 0, Color::transparent()).unwrap();
+        buffer.composite(Composite::Tint(RED));
+        assert_eq!(resolve(buffer, &HashMap::new()), ["r. "]);
+    }
+
+    #[test]
+    fn resolving_fails_without_sources_or_past_the_limits() {
+        let blit = Composite::Blit {
+            source: 7,
+            x: 0,
+            y: 0,
+        };
+        let mut buffer = corners();
+        buffer.composite(blit);
+        assert!(matches!(
+            buffer.resolve(&HashMap::new(), &Limits::new()),
+            Err(LitError::VariableNotFound(7))
+        ));
+
+        // a blit adds an instruction for the background of the source as well as its own
+        let sources: HashMap<_, _> = vec![(7, corners())].into_iter().collect();
+        let mut limits = Limits::new();
+        limits.max_elements = 4;
+        let mut buffer = corners();
+        buffer.composite(blit);
+        assert!(matches!(
+            buffer.resolve(&sources, &limits),
+            Err(LitError::ValueTooLarge(4))
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// draw/indexed.rs - Texture that keeps the color index of every pixel.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedBuffer {
+    width: u32,
+    height: u32,
+    indices: Vec<u8>,
+}
+
+impl IndexedBuffer {
+    /// Create a texture filled with a background index. The size should be checked against the
+    /// texture limits first.
+    pub fn new(width: u32, height: u32, background: u8) -> Self {
+        Self {
+            width,
+            height,
+            indices: vec![background; width as usize * height as usize],
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The index of every pixel, row by row from the top.
+    #[inline]
+    pub fn indices(&self) -> &[u8] {
+        &self.indices
+    }
+
+    #[inline]
+    pub fn get(&self, x: u32, y: u32) -> Option<u8> {
+        if x < self.width && y < self.height {
+            Some(self.indices[(y * self.width + x) as usize])
+        } else {
+            None
+        }
+    }
+
+    // pixels past the edge of the texture aren't drawn, like with draw buffers
+    #[inline]
+    pub fn draw_pixel(&mut self, x: u32, y: u32, index: u8) {
+        if x < self.width && y < self.height {
+            self.indices[(y * self.width + x) as usize] = index;
+        }
+    }
+
+    pub fn draw_rectangle(&mut self, x: u32, y: u32, width: u32, height: u32, index: u8) {
+        let right = x.saturating_add(width).min(self.width);
+        let bottom = y.saturating_add(height).min(self.height);
+
+        for j in y..bottom {
+            let row = (j * self.width) as usize;
+            for i in x..right {
+                self.indices[row + i as usize] = index;
+            }
+        }
+    }
+}
+
+impl fmt::Display for IndexedBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Indexed texture of {}x{}", self.width, self.height)?;
+        for row in self.indices.chunks(self.width.max(1) as usize) {
+            writeln!(f)?;
+            for index in row {
+                write!(f, "{:02X} ", index)?;
+            }
+        }
+        Ok(())
+    }
+}
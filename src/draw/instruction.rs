@@ -3,7 +3,7 @@
 
 use crate::Color;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(u32)]
 pub enum DrawInstruction {
     // draw a single pixel
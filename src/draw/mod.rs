@@ -2,16 +2,24 @@
 // draw/mod.rs - Draw handle for an object that can be drawn on.
 
 mod buffer;
+mod composite;
+mod indexed;
 mod instruction;
+mod pixels;
 
 pub use buffer::*;
+pub use composite::Composite;
+pub use indexed::IndexedBuffer;
 pub use instruction::DrawInstruction;
+pub use pixels::PixelBuffer;
 
-use crate::{Color, ImgTexture, LitError};
+use crate::{Color, LitError};
 use std::fmt;
 
 pub trait DrawHandle: fmt::Display + fmt::Debug {
-    fn new(width: u32, height: u32, background_color: Color) -> Self;
+    fn new(width: u32, height: u32, background_color: Color) -> Self
+    where
+        Self: Sized;
 
     // draw a single pixel
     fn draw_pixel(&mut self, x: u32, y: u32, color: Color) -> Result<(), LitError>;
@@ -0,0 +1,68 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// draw/pixels.rs - RGBA pixels of a texture, ready to be uploaded.
+
+use crate::{Color, ImgTexture, LitError};
+use lit_gl_wrapper::gl;
+
+#[derive(Debug, Clone)]
+pub struct PixelBuffer {
+    width: u32,
+    height: u32,
+    // four bytes per pixel, row by row from the top
+    pixels: Vec<u8>,
+}
+
+impl PixelBuffer {
+    pub fn new(width: u32, height: u32, background: Color) -> Self {
+        let pixel = background.as_rgba();
+        Self {
+            width,
+            height,
+            pixels: pixel
+                .iter()
+                .copied()
+                .cycle()
+                .take(width as usize * height as usize * 4)
+                .collect(),
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Fill a rectangle, leaving out the parts of it that are past the edges.
+    pub fn fill(&mut self, x: i32, y: i32, width: i32, height: i32, color: Color) {
+        let left = x.max(0) as u32;
+        let top = y.max(0) as u32;
+        let right = (x.saturating_add(width).max(0) as u32).min(self.width);
+        let bottom = (y.saturating_add(height).max(0) as u32).min(self.height);
+
+        let pixel = color.as_rgba();
+        for j in top..bottom {
+            let row = (j * self.width) as usize * 4;
+            for i in left..right {
+                let start = row + i as usize * 4;
+                self.pixels[start..start + 4].copy_from_slice(&pixel);
+            }
+        }
+    }
+
+    /// Create a texture from the pixels. This has to happen on the thread that owns the GL
+    /// context.
+    pub fn upload(&self, gl: &gl::Gl) -> Result<ImgTexture, LitError> {
+        let tex = ImgTexture::from_raw(gl, &[self.width, self.height], self.pixels.as_ptr())?;
+        Ok(tex)
+    }
+}
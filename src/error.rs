@@ -2,10 +2,13 @@
 // error.rs - Error handling struct.
 
 use crate::{
-    script::{DataType, ParserState},
-    GlCall, GlErrorType,
+    script::{DataType, VerifyError},
+    ResourceType,
 };
+use lit_fmt::FormatError;
 use lit_gl_wrapper::GlError;
+use lit_lz::LzError;
+use lits_pack::PackError;
 use std::{
     fmt,
     io::Error as IoError,
@@ -30,11 +33,19 @@ pub enum LitError {
     #[error("Unexpected dword while reading bytecode: {0:X?}")]
     BytecodeRead32(u32),
     #[error("An IO error occurred: {0}")]
-    Io(#[from] IoError),
+    Io(IoError),
+    #[error("Data file is damaged: {0}")]
+    Compression(#[from] LzError),
+    #[error("{0}")]
+    Pack(#[from] PackError),
+    #[error("Pack entry {0} is not a compiled script")]
+    NotAScript(String),
     #[error("Error converting from UTF-8: {0}")]
     FromUtf8(#[from] FromUtf8Error),
     #[error("Unable to find variable with reference {0:X?}")]
     VariableNotFound(u32),
+    #[error("Materials depend on each other: {}", .0.join(" -> "))]
+    DependencyCycle(Vec<String>),
     #[error("Color map does not contain ID {0}")]
     ColorIdObjectNotFound(u32),
     #[error("Color map at {0} does not contain color {1}")]
@@ -49,6 +60,114 @@ pub enum LitError {
     PoisonedMutex,
     #[error("Conversion error: {0}")]
     TryFromInt(#[from] TryFromIntError),
+    #[error("Script attempted to pop a value off of an empty stack")]
+    StackUnderflow,
+    #[error("Script attempted to divide by zero")]
+    DivideByZero,
+    #[error("Script asked for a random number below {0}, which is not above zero")]
+    InvalidRandomBound(i32),
+    #[error("Script contains a jump to {0}, which is not the start of an instruction")]
+    InvalidJumpTarget(usize),
+    #[error("Data file failed verification at offset {offset:#X}: {error}")]
+    Verify { offset: usize, error: VerifyError },
+    #[error("Error in layer {layer}, statement at offset {offset:#X}: {error}")]
+    Statement {
+        layer: usize,
+        offset: usize,
+        error: Box<LitError>,
+    },
+    #[error("Unknown load policy \"{0}\", expected strict, lenient or report")]
+    UnknownLoadPolicy(String),
+    #[error("Unknown log level \"{0}\", expected trace, debug, info, warn or error")]
+    UnknownLogLevel(String),
+    #[error("Unknown log target \"{0}\", expected script, gl, resource or loader")]
+    UnknownLogTarget(String),
+    #[error("Unknown option: {0}")]
+    UnknownOption(String),
+    #[error("Invalid value for option {0}")]
+    InvalidOption(String),
+    #[error("Data file has {0} error(s)")]
+    LoadFailed(usize),
+    #[error("Texture of {width}x{height} is larger than the limit of {max}x{max}")]
+    TextureTooLarge { width: u32, height: u32, max: u32 },
+    #[error("Textures take up more than the limit of {0} bytes")]
+    PixelMemoryExceeded(usize),
+    #[error("String of {0} bytes is longer than the limit of {1}")]
+    StringTooLong(usize, usize),
+    #[error("Data files define more than the limit of {0} variables")]
+    TooManyVariables(usize),
+    #[error("Data is nested deeper than the limit of {0} levels")]
+    TooDeep(usize),
+    #[error("Script ran more than the limit of {0} instructions")]
+    InstructionLimit(usize),
+    #[error("Value contains more than the limit of {0} objects")]
+    ValueTooLarge(usize),
+    #[error("Data file takes up more than the limit of {0} bytes once decompressed")]
+    DataTooLarge(usize),
+    #[error("Resources have not been built")]
+    NoResources,
+    #[error("Stale handle to {0:?} {1}, generation {2} was replaced or removed")]
+    StaleHandle(ResourceType, u32, u32),
+    #[error("There is no resource named {0}{}", similar_names(.1))]
+    ResourceNameNotFound(String, Vec<String>),
+    #[error("Resource {0} is {1:?}, not {2:?}")]
+    ResourceTypeMismatch(String, ResourceType, ResourceType),
+    #[error("{0}")]
+    Format(#[from] FormatError),
+    #[error("There is no variable named {0}")]
+    UnknownVariableName(String),
+    #[error("Only numbers, strings and tuples of them can be written")]
+    NotPlainValue,
+    #[error("File is not a save file")]
+    NotASave,
+    #[error("Save file has version {0}, but only versions up to {1} can be loaded")]
+    UnsupportedSaveVersion(u16, u16),
+    #[error("Save file is damaged")]
+    DamagedSave,
+    #[error("There is no timeline named {0}")]
+    UnknownTimeline(String),
+    #[error("There is already a timeline named {0}")]
+    TimelineExists(String),
+    #[error("Timeline {0} is the current timeline")]
+    CurrentTimeline(String),
+    #[error("File is not a replay")]
+    NotAReplay,
+    #[error("Replay has version {0}, but only versions up to {1} can be played")]
+    UnsupportedReplayVersion(u16, u16),
+    #[error("Replay is damaged")]
+    DamagedReplay,
+    #[error("Replay went out of sync at frame {0}, expected state {1:08x} but found {2:08x}")]
+    ReplayDesync(u32, u32, u32),
+    #[error("File is not a debug symbols file")]
+    NotDebugSymbols,
+    #[error("Loading was stopped in the debugger")]
+    DebuggerQuit,
+}
+
+// suggest names that were probably meant instead of a missing one
+fn similar_names(names: &[String]) -> String {
+    if names.is_empty() {
+        String::new()
+    } else {
+        format!(", did you mean {}?", names.join(", "))
+    }
+}
+
+impl From<IoError> for LitError {
+    fn from(e: IoError) -> Self {
+        // the decompressor reports its errors through IO errors, so unwrap them here
+        if !e.get_ref().is_some_and(|inner| inner.is::<LzError>()) {
+            return Self::Io(e);
+        }
+
+        let kind = e.kind();
+        match e.into_inner().map(|inner| inner.downcast::<LzError>()) {
+            Some(Ok(lz)) => Self::Compression(*lz),
+            // put back together whatever wasn't a compression error after all
+            Some(Err(inner)) => Self::Io(IoError::new(kind, inner)),
+            None => Self::Io(kind.into()),
+        }
+    }
 }
 
 impl<'a, T> From<PoisonError<MutexGuard<'a, T>>> for LitError {
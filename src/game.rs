@@ -1,18 +1,268 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // game.rs - Object for holding the game's current state.
 
-use crate::{GameData, LitError, Resource};
+use crate::{
+    logging::RingBuffer, GameData, Handle, Image, IndexedTexture, InputEvent, LitError, Loadable,
+    Loader, Palette, ReloadReport, Renderer, Resource, ResourceStats, ResourceType, RewindBuffer,
+    Rng, SaveSlots, Vm, VmRequest,
+};
+use lit_gl_wrapper::gl;
+use nalgebra::geometry::Point2;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// the number of frames that can be rewound by default, which is a minute at 60 frames per second
+const REWIND_FRAMES: usize = 3600;
 
 pub struct Game {
     data: GameData,
+    vm: Vm,
+    console: Option<RingBuffer>,
+    rewind: RewindBuffer,
 }
 
 impl Game {
     pub fn new(data: GameData) -> Self {
-        Self { data }
+        Self {
+            data,
+            vm: Vm::new(),
+            console: None,
+            rewind: RewindBuffer::new(REWIND_FRAMES),
+        }
+    }
+
+    /// Set the log records shown by the in-game console.
+    #[inline]
+    pub fn set_console(&mut self, console: RingBuffer) {
+        self.console = Some(console);
+    }
+
+    #[inline]
+    pub fn console(&self) -> Option<&RingBuffer> {
+        self.console.as_ref()
+    }
+
+    /// Look up a resource by the name it was given in the data files, e.g. `MY_SPRITE`.
+    #[inline]
+    pub fn handle<T: Resource>(&self, name: &str) -> Result<Handle<T>, LitError> {
+        self.data.handle(name)
+    }
+
+    #[inline]
+    pub fn resource_names(&self) -> Vec<(&str, ResourceType)> {
+        self.data.resource_names()
+    }
+
+    pub fn get_resource<T: Resource>(&mut self, handle: Handle<T>) -> Result<&T, LitError> {
+        self.data.get_resource(handle)
+    }
+
+    /// Get a resource to draw with, loading it onto the GPU if it isn't already.
+    #[inline]
+    pub fn load_resource<T: Loadable>(
+        &mut self,
+        gl: &gl::Gl,
+        handle: Handle<T>,
+    ) -> Result<&mut T, LitError> {
+        self.data.load_resource(gl, handle)
+    }
+
+    /// Draw an image, loading it onto the GPU if it isn't already.
+    pub fn draw_image<R>(
+        &mut self,
+        renderer: &R,
+        gl: &gl::Gl,
+        handle: Handle<Image>,
+        position: Point2<f32>,
+        size: Point2<f32>,
+        rotation: f32,
+    ) -> Result<(), LitError>
+    where
+        R: Renderer,
+        LitError: From<R::Error>,
+    {
+        let texture = self.data.load_resource(gl, handle)?.upload(gl)?;
+        renderer.draw_sprite(texture, position, size, rotation)?;
+        Ok(())
+    }
+
+    /// Draw an indexed texture through its palette, loading it onto the GPU if it isn't already.
+    /// Palettes that were swapped or cycled since it was last drawn are uploaded first.
+    pub fn draw_indexed<R>(
+        &mut self,
+        renderer: &R,
+        gl: &gl::Gl,
+        handle: Handle<IndexedTexture>,
+        position: Point2<f32>,
+        size: Point2<f32>,
+        rotation: f32,
+    ) -> Result<(), LitError>
+    where
+        R: Renderer,
+        LitError: From<R::Error>,
+    {
+        let (indices, palette) = self.data.load_resource(gl, handle)?.upload(gl)?;
+        renderer.draw_indexed_sprite(indices, palette, position, size, rotation)?;
+        Ok(())
+    }
+
+    /// Start drawing the images that are held with `acquire_resource`, e.g. by the scene that is
+    /// about to start, on the loader's threads instead of when they are first used.
+    #[inline]
+    pub fn preload(&self, loader: &mut Loader) -> Result<(), LitError> {
+        self.data.preload(loader)
+    }
+
+    /// Upload up to some number of the images that the loader has drawn. This is called once
+    /// every frame while loading, so that the uploads don't freeze the window.
+    #[inline]
+    pub fn upload_preloaded(
+        &mut self,
+        loader: &mut Loader,
+        gl: &gl::Gl,
+        max: usize,
+    ) -> Result<usize, LitError> {
+        self.data.upload_preloaded(loader, gl, max)
+    }
+
+    /// Hold a reference to a resource, e.g. when a scene that uses it starts. Resources that are
+    /// referenced are never evicted.
+    #[inline]
+    pub fn acquire_resource<T: Resource>(&mut self, handle: Handle<T>) -> Result<(), LitError> {
+        self.data.acquire_resource(handle)
+    }
+
+    #[inline]
+    pub fn release_resource<T: Resource>(&mut self, handle: Handle<T>) -> Result<(), LitError> {
+        self.data.release_resource(handle)
+    }
+
+    /// Set the most GPU memory that resources should use, in bytes.
+    #[inline]
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) -> Result<(), LitError> {
+        self.data.set_memory_budget(budget)
+    }
+
+    #[inline]
+    pub fn resource_stats(&self) -> Result<ResourceStats, LitError> {
+        self.data.resource_stats()
+    }
+
+    /// Swap the palette of an indexed texture for another palette. The new colors are uploaded
+    /// the next time the texture is drawn.
+    #[inline]
+    pub fn set_palette(
+        &mut self,
+        texture: Handle<IndexedTexture>,
+        palette: Handle<Palette>,
+    ) -> Result<(), LitError> {
+        self.data.set_palette(texture, palette)
+    }
+
+    /// Take the data read from data files that changed while the game was running, keeping the
+    /// window, the handles to resources and the game's state.
+    #[inline]
+    pub fn reload(&mut self, data: GameData) -> Result<ReloadReport, LitError> {
+        // variables may have moved, so the recorded frames no longer apply
+        self.rewind.clear();
+        self.data.reload(data)
+    }
+
+    /// Save the game's state to a slot, replacing whatever was saved there.
+    pub fn save(&self, slots: &SaveSlots, slot: u32) -> Result<(), LitError> {
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        slots.save(slot, &self.data.save_data(saved_at))
+    }
+
+    /// Restore the game's state from a slot. Returns false if nothing was saved there.
+    pub fn load(&mut self, slots: &SaveSlots, slot: u32) -> Result<bool, LitError> {
+        match slots.load(slot)? {
+            Some(save) => {
+                self.data.restore(&save);
+                self.rewind.clear();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Start rewinding time, going back some number of frames every frame.
+    #[inline]
+    pub fn rewind(&mut self, speed: u32) {
+        self.rewind.start(speed);
+    }
+
+    #[inline]
+    pub fn rewind_stop(&mut self) {
+        self.rewind.stop();
+    }
+
+    #[inline]
+    pub fn is_rewinding(&self) -> bool {
+        self.rewind.is_rewinding()
+    }
+
+    /// Set the number of frames that can be rewound.
+    #[inline]
+    pub fn set_rewind_capacity(&mut self, frames: usize) {
+        self.rewind.set_capacity(frames);
+    }
+
+    /// Seed the random numbers that scripts get. A game that is started with the same seed and
+    /// given the same input plays out the same way.
+    #[inline]
+    pub fn set_seed(&mut self, seed: u64) {
+        self.data.state_mut().rng = Rng::new(seed);
+    }
+
+    /// Pass on the input of a frame to the scripts. This is called every frame, before `update`.
+    #[inline]
+    pub fn input(&mut self, events: &[InputEvent]) {
+        self.vm.handle_input(events);
+    }
+
+    /// A checksum of the game's state, to tell whether a replay is still doing what was recorded.
+    #[inline]
+    pub fn state_checksum(&self) -> u32 {
+        self.data.state().checksum()
+    }
+
+    /// Run the on_start entry point, if the game data has one.
+    pub fn start(&mut self) -> Result<(), LitError> {
+        self.data.run_script(&mut self.vm, "on_start")?;
+        self.handle_requests();
+        Ok(())
+    }
+
+    /// Evict the resources that have gone unused, run the on_update entry point if the game data
+    /// has one, and advance the palette cycles. This is called once every frame.
+    ///
+    /// While time is being rewound, the state is put back the way it was some frames ago, and
+    /// then the on_rewind entry point is run instead of on_update.
+    pub fn update(&mut self) -> Result<(), LitError> {
+        self.data.collect_resources()?;
+        if self.rewind.is_rewinding() {
+            // whatever on_rewind changes is kept when play resumes
+            self.rewind.rewind(self.data.state_mut());
+            self.data.run_script(&mut self.vm, "on_rewind")?;
+            self.rewind.sync(self.data.state());
+        } else {
+            self.data.run_script(&mut self.vm, "on_update")?;
+            self.rewind.record(self.data.state());
+        }
+        self.handle_requests();
+        self.data.advance_palettes()?;
+        Ok(())
     }
 
-    pub fn get_resource<T: Resource>(&mut self, id: u32) -> Result<&T, LitError> {
-        self.data.get_resource(id)
+    // do what the scripts asked for while they ran
+    fn handle_requests(&mut self) {
+        for request in self.vm.take_requests() {
+            match request {
+                VmRequest::Rewind(speed) => self.rewind.start(speed),
+                VmRequest::RewindStop => self.rewind.stop(),
+            }
+        }
     }
 }
@@ -0,0 +1,104 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// input/mod.rs - Where the game's input comes from, one frame at a time.
+
+mod replay;
+pub use replay::{InputRecorder, InputReplay, REPLAY_MAGIC, REPLAY_VERSION};
+
+use crate::LitError;
+use lit_gl_wrapper::InputEvent;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The number of frames the game runs every second.
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+/// Gives the game its input every frame. The input from the window is passed in, and the source
+/// decides what the game sees, so that recording and replaying go through the same path as
+/// playing.
+pub trait InputSource {
+    /// The frame that is being run, counting from 0.
+    fn frame(&self) -> u32;
+
+    /// The input of the frame that is being run.
+    fn next_frame(&mut self, live: &[InputEvent]) -> Result<Vec<InputEvent>, LitError>;
+
+    /// Whether the game's state should be checked after the frame that is being run.
+    fn checksum_due(&self) -> bool {
+        false
+    }
+
+    /// End the frame that is being run, with the checksum of the game's state if one was due.
+    fn end_frame(&mut self, checksum: Option<u32>) -> Result<(), LitError>;
+
+    /// Whether there is no more input, so the game should stop.
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    /// Called once the game has stopped.
+    fn finish(&mut self) -> Result<(), LitError> {
+        Ok(())
+    }
+}
+
+/// The input from the window, as it happens.
+#[derive(Debug)]
+pub struct LiveInput {
+    frame: u32,
+}
+
+impl LiveInput {
+    pub fn new() -> Self {
+        Self { frame: 0 }
+    }
+}
+
+impl InputSource for LiveInput {
+    #[inline]
+    fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    fn next_frame(&mut self, live: &[InputEvent]) -> Result<Vec<InputEvent>, LitError> {
+        Ok(live.to_vec())
+    }
+
+    fn end_frame(&mut self, _checksum: Option<u32>) -> Result<(), LitError> {
+        self.frame += 1;
+        Ok(())
+    }
+}
+
+/// Keeps frames a fixed time apart, however fast the window is drawn. The game only ever
+/// advances a whole frame at a time, so a frame that runs late is never made up for by running
+/// the next one differently.
+#[derive(Debug)]
+pub struct FrameClock {
+    frame_time: Duration,
+    next: Option<Instant>,
+}
+
+impl FrameClock {
+    pub fn new(frames_per_second: u32) -> Self {
+        Self {
+            frame_time: Duration::from_secs(1) / frames_per_second.max(1),
+            next: None,
+        }
+    }
+
+    /// Sleep until it is time for the next frame.
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        let next = match self.next {
+            Some(next) if next > now => {
+                thread::sleep(next - now);
+                next
+            }
+            // after a slow frame, start counting again instead of rushing to catch up
+            _ => now,
+        };
+        self.next = Some(next + self.frame_time);
+    }
+}
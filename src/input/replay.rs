@@ -0,0 +1,353 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// input/replay.rs - Record the input of a game to a file, and play it back.
+
+use super::InputSource;
+use crate::LitError;
+use lit_gl_wrapper::InputEvent;
+use std::io::prelude::*;
+
+/// Magic bytes at the start of every replay file.
+pub const REPLAY_MAGIC: [u8; 4] = *b"LREC";
+
+/// The version of the replay format written by `InputRecorder`.
+pub const REPLAY_VERSION: u16 = 1;
+
+// every record is the frame it belongs to, its kind and a value
+const RECORD_SIZE: usize = 9;
+const KIND_KEY_DOWN: u8 = 0;
+const KIND_KEY_UP: u8 = 1;
+const KIND_CHECKSUM: u8 = 2;
+// the last record, whose frame is the number of frames that were recorded
+const KIND_END: u8 = 3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Record {
+    Input(InputEvent),
+    Checksum(u32),
+}
+
+/// Passes the input from the window on to the game, and writes it to a file along with a
+/// checksum of the game's state every so many frames.
+#[derive(Debug)]
+pub struct InputRecorder<W: Write> {
+    stream: W,
+    frame: u32,
+    // the number of frames between checksums, or 0 for none
+    interval: u32,
+}
+
+impl<W: Write> InputRecorder<W> {
+    /// Start recording a game that was started with a seed.
+    pub fn new(mut stream: W, seed: u64, interval: u32) -> Result<Self, LitError> {
+        stream.write_all(&REPLAY_MAGIC)?;
+        stream.write_all(&REPLAY_VERSION.to_be_bytes())?;
+        stream.write_all(&seed.to_be_bytes())?;
+        stream.write_all(&interval.to_be_bytes())?;
+        Ok(Self {
+            stream,
+            frame: 0,
+            interval,
+        })
+    }
+
+    fn write_record(&mut self, frame: u32, kind: u8, value: u32) -> Result<(), LitError> {
+        let mut record = [0; RECORD_SIZE];
+        record[..4].copy_from_slice(&frame.to_be_bytes());
+        record[4] = kind;
+        record[5..].copy_from_slice(&value.to_be_bytes());
+        self.stream.write_all(&record)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> InputSource for InputRecorder<W> {
+    #[inline]
+    fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    fn next_frame(&mut self, live: &[InputEvent]) -> Result<Vec<InputEvent>, LitError> {
+        for event in live {
+            let (kind, key) = match *event {
+                InputEvent::KeyDown(key) => (KIND_KEY_DOWN, key),
+                InputEvent::KeyUp(key) => (KIND_KEY_UP, key),
+            };
+            self.write_record(self.frame, kind, key as u32)?;
+        }
+        Ok(live.to_vec())
+    }
+
+    #[inline]
+    fn checksum_due(&self) -> bool {
+        self.interval != 0 && (self.frame + 1).is_multiple_of(self.interval)
+    }
+
+    fn end_frame(&mut self, checksum: Option<u32>) -> Result<(), LitError> {
+        if let Some(checksum) = checksum {
+            self.write_record(self.frame, KIND_CHECKSUM, checksum)?;
+            // a recording that is cut short still replays up to here
+            self.stream.flush()?;
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), LitError> {
+        self.write_record(self.frame, KIND_END, 0)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+}
+
+/// Plays back a recorded game, ignoring the input from the window. The game's state is checked
+/// against the recorded checksums, so a replay that no longer does what was recorded stops with
+/// an error instead of going on to do something else.
+#[derive(Debug)]
+pub struct InputReplay {
+    seed: u64,
+    records: Vec<(u32, Record)>,
+    next: usize,
+    frame: u32,
+    frames: u32,
+}
+
+impl InputReplay {
+    pub fn read<T: Read>(stream: &mut T) -> Result<Self, LitError> {
+        let mut magic = [0; 4];
+        stream.read_exact(&mut magic)?;
+        if magic != REPLAY_MAGIC {
+            return Err(LitError::NotAReplay);
+        }
+
+        let mut buffer = [0; 2];
+        stream.read_exact(&mut buffer)?;
+        let version = u16::from_be_bytes(buffer);
+        if version == 0 || version > REPLAY_VERSION {
+            return Err(LitError::UnsupportedReplayVersion(version, REPLAY_VERSION));
+        }
+
+        let mut buffer = [0; 8];
+        stream.read_exact(&mut buffer)?;
+        let seed = u64::from_be_bytes(buffer);
+        // the interval is only needed while recording
+        let mut buffer = [0; 4];
+        stream.read_exact(&mut buffer)?;
+
+        let mut contents = vec![];
+        stream.read_to_end(&mut contents)?;
+        if contents.len() % RECORD_SIZE != 0 {
+            return Err(LitError::DamagedReplay);
+        }
+
+        let mut records = vec![];
+        let mut frames = None;
+        for chunk in contents.chunks(RECORD_SIZE) {
+            let frame = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            let value = u32::from_be_bytes([chunk[5], chunk[6], chunk[7], chunk[8]]);
+            // records go forwards in time, and nothing comes after the end
+            if frames.is_some() || records.last().is_some_and(|(f, _)| *f > frame) {
+                return Err(LitError::DamagedReplay);
+            }
+
+            let record = match chunk[4] {
+                KIND_KEY_DOWN => Record::Input(InputEvent::KeyDown(value as i32)),
+                KIND_KEY_UP => Record::Input(InputEvent::KeyUp(value as i32)),
+                KIND_CHECKSUM => Record::Checksum(value),
+                KIND_END => {
+                    frames = Some(frame);
+                    continue;
+                }
+                _ => return Err(LitError::DamagedReplay),
+            };
+            records.push((frame, record));
+        }
+
+        // a recording without an end was cut short, and is played up to its last record
+        let frames = frames.unwrap_or_else(|| records.last().map_or(0, |(f, _)| f + 1));
+        Ok(Self {
+            seed,
+            records,
+            next: 0,
+            frame: 0,
+            frames,
+        })
+    }
+
+    /// The seed the recorded game was started with.
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The number of frames that were recorded.
+    #[inline]
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+}
+
+impl InputSource for InputReplay {
+    #[inline]
+    fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    fn next_frame(&mut self, _live: &[InputEvent]) -> Result<Vec<InputEvent>, LitError> {
+        let mut events = vec![];
+        while let Some((frame, Record::Input(event))) = self.records.get(self.next) {
+            if *frame != self.frame {
+                break;
+            }
+            events.push(*event);
+            self.next += 1;
+        }
+        Ok(events)
+    }
+
+    #[inline]
+    fn checksum_due(&self) -> bool {
+        match self.records.get(self.next) {
+            Some((frame, Record::Checksum(_))) => *frame == self.frame,
+            _ => false,
+        }
+    }
+
+    fn end_frame(&mut self, checksum: Option<u32>) -> Result<(), LitError> {
+        if let Some((frame, Record::Checksum(expected))) = self.records.get(self.next) {
+            if *frame == self.frame {
+                match checksum {
+                    Some(found) if found != *expected => {
+                        return Err(LitError::ReplayDesync(self.frame, *expected, found));
+                    }
+                    _ => self.next += 1,
+                }
+            }
+        }
+
+        // whatever is left of this frame, which a file that was written by a recorder never has,
+        // is skipped
+        while self
+            .records
+            .get(self.next)
+            .is_some_and(|(f, _)| *f <= self.frame)
+        {
+            self.next += 1;
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    #[inline]
+    fn is_finished(&self) -> bool {
+        self.frame >= self.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BytecodeObject, ParserState, Rng};
+
+    // the size of the header before the records
+    const HEADER_SIZE: usize = 18;
+
+    // the input of every frame that is recorded
+    fn live() -> Vec<Vec<InputEvent>> {
+        vec![
+            vec![InputEvent::KeyDown(4)],
+            vec![],
+            vec![InputEvent::KeyUp(4), InputEvent::KeyDown(7)],
+            vec![],
+            vec![InputEvent::KeyDown(2)],
+            vec![InputEvent::KeyUp(7)],
+        ]
+    }
+
+    // a tiny game, where every key that is pressed adds to a variable along with a random number.
+    // returns the checksums that were taken
+    fn run(input: &mut dyn InputSource, seed: u64) -> Result<Vec<u32>, LitError> {
+        let mut state = ParserState::new();
+        state.rng = Rng::new(seed);
+        let mut checksums = vec![];
+        for live in live() {
+            if input.is_finished() {
+                break;
+            }
+            for event in input.next_frame(&live)? {
+                if let InputEvent::KeyDown(key) = event {
+                    let total = match state.variables.get(&1) {
+                        Some(BytecodeObject::Numeric32(total)) => *total,
+                        _ => 0,
+                    };
+                    let value = total + key + state.rng.below(10) as i32;
+                    state.variables.insert(1, BytecodeObject::Numeric32(value));
+                }
+            }
+            state.rng.next_u64();
+
+            let checksum = if input.checksum_due() {
+                Some(state.checksum())
+            } else {
+                None
+            };
+            checksums.extend(checksum);
+            input.end_frame(checksum)?;
+        }
+        input.finish()?;
+        Ok(checksums)
+    }
+
+    fn recorded() -> (Vec<u8>, Vec<u32>) {
+        let mut bytes = vec![];
+        let mut recorder = InputRecorder::new(&mut bytes, 42, 2).unwrap();
+        let checksums = run(&mut recorder, 42).unwrap();
+        (bytes, checksums)
+    }
+
+    // the offset of the first record of a kind
+    fn record(bytes: &[u8], kind: u8) -> usize {
+        (HEADER_SIZE..bytes.len())
+            .step_by(RECORD_SIZE)
+            .find(|at| bytes[at + 4] == kind)
+            .unwrap()
+    }
+
+    #[test]
+    fn replays_match_the_recording() {
+        let (bytes, checksums) = recorded();
+        assert_eq!(checksums.len(), 3);
+
+        let mut replay = InputReplay::read(&mut &bytes[..]).unwrap();
+        assert_eq!(replay.seed(), 42);
+        assert_eq!(replay.frames(), live().len() as u32);
+        let seed = replay.seed();
+        assert_eq!(run(&mut replay, seed).unwrap(), checksums);
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn tampered_checksums_desync() {
+        let (mut bytes, checksums) = recorded();
+        let at = record(&bytes, KIND_CHECKSUM);
+        bytes[at + RECORD_SIZE - 1] ^= 1;
+
+        let mut replay = InputReplay::read(&mut &bytes[..]).unwrap();
+        match run(&mut replay, 42) {
+            Err(LitError::ReplayDesync(1, _, found)) => assert_eq!(found, checksums[0]),
+            other => panic!("expected a desync, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tampered_input_desyncs() {
+        let (mut bytes, _) = recorded();
+        let at = record(&bytes, KIND_KEY_DOWN);
+        bytes[at + RECORD_SIZE - 1] ^= 1;
+
+        let mut replay = InputReplay::read(&mut &bytes[..]).unwrap();
+        assert!(matches!(
+            run(&mut replay, 42),
+            Err(LitError::ReplayDesync(1, _, _))
+        ));
+    }
+}
@@ -0,0 +1,34 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// lib.rs - Game engine, shared by the game itself and tooling such as the fuzz targets
+
+#![allow(clippy::new_without_default)]
+
+pub use lit_gl_wrapper::*;
+
+pub mod draw;
+pub mod logging;
+
+mod color;
+mod debug;
+mod error;
+mod game;
+mod input;
+mod palette;
+mod resource;
+mod rewind;
+mod rng;
+mod save;
+mod script;
+
+pub use color::Color;
+pub use debug::*;
+pub use draw::*;
+pub use error::LitError;
+pub use game::Game;
+pub use input::*;
+pub use palette::{Palette, PaletteCycle, PALETTE_SIZE};
+pub use resource::*;
+pub use rewind::RewindBuffer;
+pub use rng::{Rng, DEFAULT_SEED};
+pub use save::*;
+pub use script::*;
@@ -0,0 +1,345 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// logging/mod.rs - Engine-wide logging, with levels, targets and pluggable sinks.
+//
+// records are sent to every sink of the global logger. until a sink is added, records go to
+// standard error, so that nothing is lost before the logger is set up.
+
+mod sink;
+
+pub use sink::{FileSink, RingBuffer, Sink, StderrSink};
+
+use crate::LitError;
+use std::{
+    fmt,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = LitError;
+
+    fn from_str(s: &str) -> Result<Self, LitError> {
+        match s {
+            "trace" => Ok(Level::Trace),
+            "debug" => Ok(Level::Debug),
+            "info" => Ok(Level::Info),
+            "warn" => Ok(Level::Warn),
+            "error" => Ok(Level::Error),
+            _ => Err(LitError::UnknownLogLevel(s.to_string())),
+        }
+    }
+}
+
+/// The part of the engine a record comes from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// Output of the log commands in LitS.
+    Script,
+    /// The renderer.
+    Gl,
+    /// Building and looking up resources.
+    Resource,
+    /// Reading data files.
+    Loader,
+}
+
+impl Target {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::Script => "script",
+            Target::Gl => "gl",
+            Target::Resource => "resource",
+            Target::Loader => "loader",
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = LitError;
+
+    fn from_str(s: &str) -> Result<Self, LitError> {
+        match s {
+            "script" => Ok(Target::Script),
+            "gl" => Ok(Target::Gl),
+            "resource" => Ok(Target::Resource),
+            "loader" => Ok(Target::Loader),
+            _ => Err(LitError::UnknownLogTarget(s.to_string())),
+        }
+    }
+}
+
+/// Which records a sink receives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    /// The lowest level of the records to take.
+    pub level: Level,
+    /// The targets to take records from, or every target if this is empty.
+    pub targets: Vec<Target>,
+}
+
+impl Filter {
+    /// Take every record at or above a level.
+    #[inline]
+    pub fn new(level: Level) -> Self {
+        Self {
+            level,
+            targets: vec![],
+        }
+    }
+
+    #[inline]
+    pub fn accepts(&self, level: Level, target: Target) -> bool {
+        level >= self.level && (self.targets.is_empty() || self.targets.contains(&target))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub level: Level,
+    pub target: Target,
+    /// The time since the logger was first used.
+    pub time: Duration,
+    pub message: String,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{:>5}.{:03} {:<5} {}] {}",
+            self.time.as_secs(),
+            self.time.subsec_millis(),
+            self.level.name(),
+            self.target.name(),
+            self.message
+        )
+    }
+}
+
+struct Logger {
+    sinks: Vec<(Filter, Box<dyn Sink>)>,
+    start: Option<Instant>,
+}
+
+impl Logger {
+    const fn new() -> Self {
+        Self {
+            sinks: Vec::new(),
+            start: None,
+        }
+    }
+
+    fn log(&mut self, level: Level, target: Target, args: fmt::Arguments) {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let record = Record {
+            level,
+            target,
+            time: start.elapsed(),
+            message: fmt::format(args),
+        };
+
+        if self.sinks.is_empty() {
+            StderrSink.write(&record);
+            return;
+        }
+
+        for (filter, sink) in self.sinks.iter_mut() {
+            if filter.accepts(level, target) {
+                sink.write(&record);
+            }
+        }
+    }
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger::new());
+
+// a sink that panics while writing shouldn't take logging down with it
+#[inline]
+fn logger() -> std::sync::MutexGuard<'static, Logger> {
+    LOGGER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Add a sink to the global logger. It receives every record at or above the given level.
+#[inline]
+pub fn add_sink<T: Sink + 'static>(level: Level, sink: T) {
+    add_filtered_sink(Filter::new(level), sink);
+}
+
+/// Add a sink to the global logger that only receives the records a filter accepts.
+pub fn add_filtered_sink<T: Sink + 'static>(filter: Filter, sink: T) {
+    logger().sinks.push((filter, Box::new(sink)));
+}
+
+/// Remove every sink from the global logger, flushing them first.
+pub fn clear_sinks() {
+    let mut logger = logger();
+    for (_, sink) in logger.sinks.iter_mut() {
+        sink.flush();
+    }
+    logger.sinks.clear();
+}
+
+/// Send a record to every sink whose filter accepts it.
+#[inline]
+pub fn log(level: Level, target: Target, args: fmt::Arguments) {
+    logger().log(level, target, args);
+}
+
+/// Flush every sink, e.g. before the program exits.
+pub fn flush() {
+    for (_, sink) in logger().sinks.iter_mut() {
+        sink.flush();
+    }
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    ($target: expr, $($arg: tt)+) => {
+        $crate::logging::log($crate::logging::Level::Trace, $target, format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($target: expr, $($arg: tt)+) => {
+        $crate::logging::log($crate::logging::Level::Debug, $target, format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($target: expr, $($arg: tt)+) => {
+        $crate::logging::log($crate::logging::Level::Info, $target, format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($target: expr, $($arg: tt)+) => {
+        $crate::logging::log($crate::logging::Level::Warn, $target, format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($target: expr, $($arg: tt)+) => {
+        $crate::logging::log($crate::logging::Level::Error, $target, format_args!($($arg)+))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a logger of its own, since other tests log to the global one at the same time
+    fn logger(filters: &[Filter]) -> (Logger, Vec<RingBuffer>) {
+        let mut logger = Logger::new();
+        let buffers: Vec<_> = filters.iter().map(|_| RingBuffer::new(8)).collect();
+        for (filter, buffer) in filters.iter().zip(&buffers) {
+            logger
+                .sinks
+                .push((filter.clone(), Box::new(buffer.clone())));
+        }
+        (logger, buffers)
+    }
+
+    fn messages(buffer: &RingBuffer) -> Vec<String> {
+        buffer.records().into_iter().map(|r| r.message).collect()
+    }
+
+    #[test]
+    fn sinks_take_records_at_or_above_their_level() {
+        let (mut logger, buffers) = logger(&[Filter::new(Level::Trace), Filter::new(Level::Warn)]);
+        logger.log(Level::Debug, Target::Gl, format_args!("debug"));
+        logger.log(Level::Warn, Target::Gl, format_args!("warn"));
+        logger.log(Level::Error, Target::Loader, format_args!("error {}", 1));
+
+        assert_eq!(messages(&buffers[0]), ["debug", "warn", "error 1"]);
+        assert_eq!(messages(&buffers[1]), ["warn", "error 1"]);
+    }
+
+    #[test]
+    fn sinks_take_records_from_their_targets() {
+        let scripts = Filter {
+            level: Level::Info,
+            targets: vec![Target::Script, Target::Loader],
+        };
+        let (mut logger, buffers) = logger(&[scripts]);
+        logger.log(Level::Info, Target::Script, format_args!("script"));
+        logger.log(Level::Error, Target::Gl, format_args!("gl"));
+        logger.log(Level::Debug, Target::Loader, format_args!("quiet loader"));
+        logger.log(Level::Warn, Target::Loader, format_args!("loader"));
+
+        let records = buffers[0].records();
+        assert_eq!(messages(&buffers[0]), ["script", "loader"]);
+        assert_eq!(records[1].level, Level::Warn);
+        assert_eq!(records[1].target, Target::Loader);
+    }
+
+    #[test]
+    fn the_global_logger_writes_to_its_sinks() {
+        let console = RingBuffer::new(8);
+        let filter = Filter {
+            level: Level::Trace,
+            targets: vec![Target::Gl],
+        };
+        add_filtered_sink(filter, console.clone());
+        crate::log_trace!(Target::Gl, "{} {}", "from", "gl");
+        crate::log_error!(Target::Script, "from a script");
+
+        let records = console.records();
+        assert!(records.iter().any(|r| r.message == "from gl"));
+        assert!(records.iter().all(|r| r.target == Target::Gl));
+        let line = records[0].to_string();
+        assert!(line.ends_with(" TRACE gl] from gl"), "{}", line);
+    }
+
+    #[test]
+    fn ring_buffers_keep_the_latest_records() {
+        let (mut logger, buffers) = logger(&[Filter::new(Level::Trace)]);
+        for i in 0..10 {
+            logger.log(Level::Info, Target::Script, format_args!("{}", i));
+        }
+        assert_eq!(
+            messages(&buffers[0]),
+            ["2", "3", "4", "5", "6", "7", "8", "9"]
+        );
+        buffers[0].clear();
+        assert!(buffers[0].records().is_empty());
+    }
+
+    #[test]
+    fn levels_and_targets_are_parsed_from_their_names() {
+        assert_eq!("warn".parse::<Level>().unwrap(), Level::Warn);
+        assert!("loud".parse::<Level>().is_err());
+        assert_eq!("gl".parse::<Target>().unwrap(), Target::Gl);
+        assert!(matches!(
+            "audio".parse::<Target>(),
+            Err(LitError::UnknownLogTarget(_))
+        ));
+    }
+}
@@ -0,0 +1,164 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// logging/sink.rs - Places for log records to go.
+
+use super::Record;
+use crate::LitError;
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+pub trait Sink: Send {
+    fn write(&mut self, record: &Record);
+
+    #[inline]
+    fn flush(&mut self) {}
+}
+
+/// Write records to standard error.
+#[derive(Debug, Copy, Clone)]
+pub struct StderrSink;
+
+impl Sink for StderrSink {
+    #[inline]
+    fn write(&mut self, record: &Record) {
+        // this is where records end up, so it writes to standard error itself
+        eprintln!("{}", record);
+    }
+}
+
+/// Write records to a file. Once the file grows past its maximum size, it is renamed to
+/// "NAME.1", the previous "NAME.1" is renamed to "NAME.2" and so on, and a new file is started.
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+impl FileSink {
+    /// Open a log file, adding on to it if it already exists. Up to `keep` old files are kept
+    /// around when the log is rotated.
+    pub fn new<P: AsRef<Path>>(path: P, max_size: u64, keep: usize) -> Result<Self, LitError> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size,
+            keep,
+        })
+    }
+
+    #[inline]
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep > 0 {
+            // the oldest file may not exist yet, which is fine
+            let _ = fs::remove_file(self.rotated(self.keep));
+            for i in (1..self.keep).rev() {
+                let from = self.rotated(i);
+                if from.exists() {
+                    fs::rename(&from, self.rotated(i + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Sink for FileSink {
+    // sinks are written to while the logger is locked, so their own failures can't be logged and
+    // go straight to standard error instead
+    fn write(&mut self, record: &Record) {
+        let line = format!("{}\n", record);
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            if let Err(e) = self.rotate() {
+                eprintln!("Unable to rotate {}: {}", self.path.display(), e);
+            }
+        }
+
+        // each record is written in one go, so the file is complete up to the last record if the
+        // game crashes
+        match self.file.write_all(line.as_bytes()) {
+            Ok(()) => self.size += line.len() as u64,
+            Err(e) => eprintln!("Unable to write to {}: {}", self.path.display(), e),
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) {
+        let _ = self.file.sync_data();
+    }
+}
+
+/// Keep the latest records in memory, for the in-game console. Clones share the same records, so
+/// one clone can be added to the logger while another is used to read them.
+#[derive(Debug, Clone)]
+pub struct RingBuffer {
+    records: Arc<Mutex<VecDeque<Record>>>,
+    capacity: usize,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Get a copy of the records in the buffer, from oldest to newest.
+    pub fn records(&self) -> Vec<Record> {
+        match self.records.lock() {
+            Ok(r) => r.iter().cloned().collect(),
+            Err(e) => e.into_inner().iter().cloned().collect(),
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut r) = self.records.lock() {
+            r.clear();
+        }
+    }
+}
+
+impl Sink for RingBuffer {
+    fn write(&mut self, record: &Record) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record.clone());
+    }
+}
@@ -1,47 +1,343 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // main.rs - Program entry point
 
-#![allow(clippy::new_without_default)]
+use lost_in_time::{
+    logging::{self, FileSink, Filter, Level, RingBuffer, StderrSink, Target},
+    *,
+};
+use nalgebra::geometry::Point2;
+use std::{
+    env,
+    fs::File,
+    io::{BufReader, BufWriter},
+    process,
+    rc::Rc,
+    thread,
+};
 
-pub use lit_gl_wrapper::*;
-
-pub mod draw;
-
-mod color;
-mod error;
-mod game;
-mod resource;
-mod script;
-
-pub use color::Color;
-pub use draw::*;
-pub use error::LitError;
-pub use game::Game;
-pub use renderer::*;
-pub use resource::*;
-pub use script::*;
-
-use std::{env, fs, io::BufReader, process};
+// the log file is rotated once it reaches this size, keeping this many old files
+const LOG_FILE_SIZE: u64 = 1 << 20;
+const LOG_FILE_KEEP: usize = 3;
+// the number of records kept for the in-game console
+const CONSOLE_RECORDS: usize = 256;
+// the memory budget and the pixel memory limit are given in MiB
+const MIB: usize = 1 << 20;
+// the most images that are uploaded every frame while loading
+const UPLOADS_PER_FRAME: usize = 8;
+// how often the game's state is checked while recording, which is once a second
+const CHECKSUM_FRAMES: u32 = 60;
 
 fn main() {
-    process::exit(match classic_main() {
+    let code = match classic_main() {
         Ok(()) => 0,
         Err(e) => {
-            eprintln!("A fatal error occurred: {}", e);
+            let target = match e {
+                LitError::GlError(_) => Target::Gl,
+                _ => Target::Loader,
+            };
+            log_error!(target, "A fatal error occurred: {}", e);
             1
         }
-    });
+    };
+
+    logging::flush();
+    process::exit(code);
 }
 
 fn classic_main() -> Result<(), LitError> {
-    let mut renderer = GlRenderer::init()?;
-    let mut data_file = BufReader::new(fs::File::open(
-        env::args().nth(1).ok_or_else(|| LitError::NoDataFile)?,
-    )?);
-    let game_data = script::GameData::read(&mut data_file)?;
-    println!("{:?}", &game_data);
+    // options start with "--", every other argument is a data file or pack, with later ones
+    // overriding earlier ones
+    let mut policy = LoadPolicy::Strict;
+    let mut limits = Limits::new();
+    let mut log_level = Level::Info;
+    let mut log_targets = vec![];
+    let mut log_file = None;
+    let mut memory_budget = None;
+    let mut watch = false;
+    let mut seed = DEFAULT_SEED;
+    let mut record = None;
+    let mut replay = None;
+    let mut debug = false;
+    let mut layers: Vec<DataLayer> = vec![];
+    for arg in env::args().skip(1) {
+        if let Some(value) = arg.strip_prefix("--load-policy=") {
+            policy = value.parse()?;
+        } else if let Some(value) = arg.strip_prefix("--log-level=") {
+            log_level = value.parse()?;
+        } else if let Some(value) = arg.strip_prefix("--log-targets=") {
+            // e.g. "--log-targets=script,loader"
+            log_targets = value
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<Vec<Target>, _>>()?;
+        } else if let Some(value) = arg.strip_prefix("--log-file=") {
+            log_file = Some(value.to_string());
+        } else if let Some(value) = arg.strip_prefix("--memory-budget=") {
+            memory_budget = Some(mebibytes(&arg, value)?);
+        } else if arg == "--no-limits" {
+            limits = Limits::unlimited();
+        } else if let Some(value) = arg.strip_prefix("--max-pixel-memory=") {
+            limits.max_pixel_memory = mebibytes(&arg, value)?;
+        } else if let Some(value) = arg.strip_prefix("--max-data-size=") {
+            limits.max_data_size = mebibytes(&arg, value)?;
+        } else if let Some(option) = arg.strip_prefix("--max-") {
+            // every other limit is a plain number, e.g. "--max-texture-size=1024"
+            let (name, value) = match option.split_once('=') {
+                Some(option) => option,
+                None => return Err(LitError::UnknownOption(arg)),
+            };
+            let value = value
+                .parse()
+                .map_err(|_| LitError::InvalidOption(arg.clone()))?;
+            if !limits.set(name, value) {
+                return Err(LitError::UnknownOption(arg));
+            }
+        } else if arg == "--watch" {
+            watch = true;
+        } else if let Some(value) = arg.strip_prefix("--seed=") {
+            seed = value
+                .parse()
+                .map_err(|_| LitError::InvalidOption(arg.clone()))?;
+        } else if let Some(value) = arg.strip_prefix("--record=") {
+            record = Some(value.to_string());
+        } else if let Some(value) = arg.strip_prefix("--replay=") {
+            replay = Some(value.to_string());
+        } else if arg == "--debug" {
+            debug = true;
+        } else if arg.starts_with("--") {
+            return Err(LitError::UnknownOption(arg));
+        } else {
+            layers.push(DataLayer::parse(&arg));
+        }
+    }
+
+    // the log file and the console get everything, so that a bug report has the whole story
+    logging::add_filtered_sink(
+        Filter {
+            level: log_level,
+            targets: log_targets,
+        },
+        StderrSink,
+    );
+    if let Some(path) = log_file {
+        logging::add_sink(
+            Level::Trace,
+            FileSink::new(path, LOG_FILE_SIZE, LOG_FILE_KEEP)?,
+        );
+    }
+    let console = RingBuffer::new(CONSOLE_RECORDS);
+    logging::add_sink(Level::Trace, console.clone());
+
+    if layers.is_empty() {
+        return Err(LitError::NoDataFile);
+    }
+
+    // a window is only needed if the data isn't only being checked
+    let renderer = match policy {
+        LoadPolicy::ReportOnly => None,
+        _ => Some(Rc::new(GlRenderer::init()?)),
+    };
+
+    // the debugger stops before the first statement, and takes commands from the terminal
+    let mut debugger = if debug {
+        Some(start_debugger(&layers, renderer.clone()))
+    } else {
+        None
+    };
+    let game_data = read_layers(&layers, policy, limits, debugger.as_mut())?;
+    // the debugger is only used while loading, and lets go of the window once it is done
+    drop(debugger);
+    for (i, layer) in layers.iter().enumerate() {
+        log_info!(Target::Loader, "Layer {}: {}", i, layer);
+    }
+    for warning in game_data.warnings() {
+        log_warn!(Target::Loader, "{}", warning);
+    }
+
+    let mut renderer = match renderer {
+        Some(r) => r,
+        None if game_data.warnings().is_empty() => return Ok(()),
+        None => return Err(LitError::LoadFailed(game_data.warnings().len())),
+    };
+
+    log_debug!(Target::Loader, "{:?}", &game_data);
     let mut game = Game::new(game_data);
-    println!("{:?}", game.get_resource::<ImgTexture>(0)?);
+    game.set_console(console);
+    game.set_memory_budget(memory_budget)?;
+    for (name, kind) in game.resource_names() {
+        log_debug!(Target::Resource, "{}: {:?}", name, kind);
+    }
+
+    // images are drawn in the background, leaving a thread for the window
+    let threads = thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1));
+    let mut loader = Loader::new(threads);
+    game.preload(&mut loader)?;
+
+    // the data files are reloaded when they change, without restarting the game
+    let mut watcher = if watch {
+        Some(DataWatcher::new(&layers))
+    } else {
+        None
+    };
+
+    // a replay is started the same way as the game it was recorded from, and ignores the window's
+    // input
+    let mut input: Box<dyn InputSource> = match (record, replay) {
+        (Some(_), Some(_)) => {
+            return Err(LitError::StaticMsg(
+                "A game can't be recorded while it is being replayed",
+            ))
+        }
+        (Some(path), None) => {
+            log_info!(
+                Target::Loader,
+                "Recording input to {} with seed {}",
+                path,
+                seed
+            );
+            let stream = BufWriter::new(File::create(path)?);
+            Box::new(InputRecorder::new(stream, seed, CHECKSUM_FRAMES)?)
+        }
+        (None, Some(path)) => {
+            let replay = InputReplay::read(&mut BufReader::new(File::open(&path)?))?;
+            seed = replay.seed();
+            log_info!(
+                Target::Loader,
+                "Replaying {} frames from {}",
+                replay.frames(),
+                path
+            );
+            Box::new(replay)
+        }
+        (None, None) => Box::new(LiveInput::new()),
+    };
+    game.set_seed(seed);
+    let mut clock = FrameClock::new(FRAMES_PER_SECOND);
+
+    game.start()?;
+    let result = renderer.main_loop(|renderer, live| {
+        clock.wait();
+        if watcher.as_mut().map_or(false, |w| w.poll()) {
+            // mistakes in the data files are reported, and the game keeps going with the old data
+            match read_layers(&layers, policy, limits, None) {
+                Ok(data) => {
+                    for warning in data.warnings() {
+                        log_warn!(Target::Loader, "{}", warning);
+                    }
+                    let report = game.reload(data)?;
+                    log_info!(Target::Loader, "Reloaded the data files. {}", report);
+                    game.preload(&mut loader)?;
+                }
+                Err(e) => log_error!(Target::Loader, "Failed to reload the data files: {}", e),
+            }
+        }
+
+        if !loader.is_done() {
+            game.upload_preloaded(&mut loader, renderer.gl(), UPLOADS_PER_FRAME)?;
+            if loader.is_done() {
+                let (uploaded, _) = loader.progress();
+                log_debug!(Target::Resource, "Preloaded {} images", uploaded);
+            }
+        }
+
+        let events = input.next_frame(live)?;
+        game.input(&events);
+        game.update()?;
+        let checksum = if input.checksum_due() {
+            Some(game.state_checksum())
+        } else {
+            None
+        };
+        input.end_frame(checksum)?;
+        Ok(!input.is_finished())
+    });
+    // a recording is ended even when the game stopped with an error, so that it can be replayed
+    // up to where the error happened
+    let finished = input.finish();
+    result?;
+    finished?;
+
+    log_info!(Target::Resource, "{}", game.resource_stats()?);
+    Ok(())
+}
+
+// open every layer and read the game data from them
+fn read_layers(
+    layers: &[DataLayer],
+    policy: LoadPolicy,
+    limits: Limits,
+    debugger: Option<&mut Debugger>,
+) -> Result<GameData, LitError> {
+    let mut streams = layers
+        .iter()
+        .map(|l| l.open())
+        .collect::<Result<Vec<_>, LitError>>()?;
+    GameData::read_layers_debug(&mut streams, policy, limits, debugger)
+}
+
+// sizes of memory are given in MiB
+fn mebibytes(arg: &str, value: &str) -> Result<usize, LitError> {
+    value
+        .parse::<usize>()
+        .ok()
+        .and_then(|mib| mib.checked_mul(MIB))
+        .ok_or_else(|| LitError::InvalidOption(arg.to_string()))
+}
+
+// create a debugger with the debug symbols of every layer that was compiled with them
+fn start_debugger(layers: &[DataLayer], renderer: Option<Rc<GlRenderer>>) -> Debugger {
+    let mut debugger = Debugger::stdio();
+    for (i, layer) in layers.iter().enumerate() {
+        let path = match layer.symbols_path() {
+            Some(path) => path,
+            None => {
+                log_warn!(
+                    Target::Loader,
+                    "Layer {} is in a pack, so it has no debug symbols",
+                    i
+                );
+                continue;
+            }
+        };
+        let symbols = File::open(&path)
+            .map_err(LitError::from)
+            .and_then(|file| DebugSymbols::read(&mut BufReader::new(file)));
+        match symbols {
+            Ok(symbols) => {
+                log_info!(Target::Loader, "Layer {}: {}", i, path.display());
+                debugger.set_symbols(i, symbols);
+            }
+            Err(e) => log_warn!(Target::Loader, "Layer {} has no debug symbols: {}", i, e),
+        }
+    }
+
+    if let Some(renderer) = renderer {
+        debugger.set_viewer(Box::new(move |name: &str, pixels: &PixelBuffer| {
+            show_texture(&renderer, name, pixels)
+        }));
+    }
+    debugger
+}
+
+// show a texture in the window, scaled up by a whole number as far as it fits
+fn show_texture(renderer: &GlRenderer, name: &str, pixels: &PixelBuffer) -> Result<(), LitError> {
+    let texture = pixels.upload(renderer.gl())?;
+    let (width, height) = renderer.window_size();
+    let scale = (width / pixels.width().max(1))
+        .min(height / pixels.height().max(1))
+        .max(1) as f32;
 
-    renderer.main_loop(game)
+    renderer.clear();
+    renderer.draw_sprite(
+        &texture,
+        Point2::new(0.0, 0.0),
+        Point2::new(
+            pixels.width() as f32 * scale,
+            pixels.height() as f32 * scale,
+        ),
+        0.0,
+    )?;
+    renderer.present();
+    log_debug!(Target::Gl, "Showing {} in the window", name);
+    Ok(())
 }
@@ -0,0 +1,178 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// palette.rs - A table of colors that indexed textures look their colors up in.
+
+use crate::Color;
+use std::collections::HashMap;
+
+/// The number of colors in a palette, one for every value of an index.
+pub const PALETTE_SIZE: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<Color>,
+}
+
+impl Palette {
+    /// Create a palette where every color is transparent.
+    pub fn new() -> Self {
+        Self {
+            colors: vec![Color::transparent(); PALETTE_SIZE],
+        }
+    }
+
+    /// Create a palette from the colors given to color ids. Ids without a color are transparent.
+    pub fn from_color_ids(ids: &HashMap<u8, Color>) -> Self {
+        let mut palette = Self::new();
+        for (index, color) in ids {
+            palette.set(*index, *color);
+        }
+        palette
+    }
+
+    #[inline]
+    pub fn get(&self, index: u8) -> Color {
+        self.colors[index as usize]
+    }
+
+    #[inline]
+    pub fn set(&mut self, index: u8, color: Color) {
+        self.colors[index as usize] = color;
+    }
+
+    #[inline]
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    /// Rotate a range of colors by a number of steps. Positive steps move each color to the next
+    /// index, with the last color of the range wrapping around to the first.
+    pub fn rotate(&mut self, start: u8, end: u8, steps: i32) {
+        let range = &mut self.colors[start as usize..=end as usize];
+        let shift = steps.rem_euclid(range.len() as i32) as usize;
+        range.rotate_right(shift);
+    }
+
+    /// Get the colors as RGBA bytes, for a palette texture.
+    pub fn as_rgba(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .flat_map(|c| c.as_rgba().to_vec())
+            .collect()
+    }
+}
+
+impl Default for Palette {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rotates a range of a palette over time, for effects like flowing water or flickering fire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaletteCycle {
+    start: u8,
+    end: u8,
+    // the number of frames between each step. a negative speed rotates the other way
+    speed: i32,
+    frame: u32,
+    offset: i32,
+}
+
+impl PaletteCycle {
+    /// Create a cycle over the colors from start to end. A range that is given backwards rotates
+    /// the other way.
+    pub fn new(start: u8, end: u8, speed: i32) -> Self {
+        let (start, end, speed) = if start <= end {
+            (start, end, speed)
+        } else {
+            (end, start, speed.saturating_neg())
+        };
+
+        Self {
+            start,
+            end,
+            speed,
+            frame: 0,
+            offset: 0,
+        }
+    }
+
+    #[inline]
+    pub fn range(&self) -> (u8, u8) {
+        (self.start, self.end)
+    }
+
+    #[inline]
+    pub fn speed(&self) -> i32 {
+        self.speed
+    }
+
+    /// Advance the cycle by a frame. Returns true if the colors moved.
+    pub fn advance(&mut self) -> bool {
+        if self.speed == 0 || self.start == self.end {
+            return false;
+        }
+
+        self.frame += 1;
+        if self.frame < self.speed.unsigned_abs() {
+            return false;
+        }
+
+        // the offset wraps around with the range, so it never grows without bound
+        let len = (self.end - self.start) as i32 + 1;
+        self.frame = 0;
+        self.offset = (self.offset + self.speed.signum()).rem_euclid(len);
+        true
+    }
+
+    /// Apply the current rotation of the range to a palette.
+    #[inline]
+    pub fn apply(&self, palette: &mut Palette) {
+        palette.rotate(self.start, self.end, self.offset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Color;
+
+    fn numbered() -> Palette {
+        let mut palette = Palette::new();
+        for i in 0..=255 {
+            palette.set(i, Color::new(i, 0, 0));
+        }
+        palette
+    }
+
+    #[test]
+    fn backwards_ranges_rotate_the_other_way() {
+        let mut forward = PaletteCycle::new(250, 255, -1);
+        let mut backward = PaletteCycle::new(255, 250, 1);
+        assert_eq!(backward.range(), (250, 255));
+        assert_eq!(backward.speed(), -1);
+
+        for _ in 0..10 {
+            assert!(forward.advance());
+            assert!(backward.advance());
+
+            let (mut a, mut b) = (numbered(), numbered());
+            forward.apply(&mut a);
+            backward.apply(&mut b);
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn cycles_step_at_their_speed() {
+        let mut cycle = PaletteCycle::new(0, 255, 2);
+        assert!(!cycle.advance());
+        assert!(cycle.advance());
+
+        let mut palette = numbered();
+        cycle.apply(&mut palette);
+        assert_eq!(palette.get(1), Color::new(0, 0, 0));
+        assert_eq!(palette.get(0), Color::new(255, 0, 0));
+    }
+}
@@ -1,48 +1,542 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // resource/mod.rs - Define resource dictionaries.
 
-use super::Resource;
-use crate::{ImgTexture, LitError};
+use super::{
+    residency::{Residency, ResourceKey},
+    Handle, Image, Loadable, ReloadReport, Resource, ResourceStats, ResourceType, SubDictionary,
+};
+use crate::{
+    logging::{self, Level, Target},
+    IndexedTexture, LitError, Palette, PixelBuffer,
+};
+use lit_gl_wrapper::gl;
 use std::collections::HashMap;
 
 #[derive(Debug)]
 pub struct ResourceDictionary {
-    next_id: u32,
-    img_textures: HashMap<u32, ImgTexture>,
+    images: SubDictionary<Image>,
+    indexed_textures: SubDictionary<IndexedTexture>,
+    palettes: SubDictionary<Palette>,
+
+    // the data file layer each resource was defined in, by type and slot
+    origins: HashMap<(ResourceType, u32), usize>,
+    // the name each resource was given in the data files, and the slot it is in. the generation
+    // is taken from the slot, so that names keep working when a resource is replaced
+    names: HashMap<String, (ResourceType, u32)>,
+    // which resources are loaded on the GPU, and which are in use
+    residency: Residency,
+}
+
+// the most names that are suggested when a name can't be found
+const MAX_SUGGESTIONS: usize = 3;
+
+// the number of single character edits that turn one string into another
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { diagonal } else { diagonal + 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = cost.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[b.len()]
 }
 
 impl ResourceDictionary {
     pub fn new() -> Self {
         Self {
-            next_id: 0,
-            loaded_ids: vec![],
-            prev_loaded_ids: vec![],
-            mat_img: HashMap::new(),
+            images: SubDictionary::new(),
+            indexed_textures: SubDictionary::new(),
+            palettes: SubDictionary::new(),
+            origins: HashMap::new(),
+            names: HashMap::new(),
+            residency: Residency::new(),
         }
     }
 
     #[inline]
-    pub fn res_img_subdict(&self) -> &HashMap<u32, ImgTexture> {
-        &self.res_img
+    pub fn res_img_subdict(&self) -> &SubDictionary<Image> {
+        &self.images
     }
     #[inline]
-    pub fn res_img_subdict_mut(&mut self) -> &mut HashMap<u32, ImgTexture> {
-        &mut self.res_img
+    pub fn res_img_subdict_mut(&mut self) -> &mut SubDictionary<Image> {
+        &mut self.images
     }
 
-    pub fn add_res<T: Resource>(&mut self, item: T) -> u32 {
-        // generate id
-        let id = self.next_id;
-        self.next_id += 1;
+    #[inline]
+    pub fn res_indexed_subdict(&self) -> &SubDictionary<IndexedTexture> {
+        &self.indexed_textures
+    }
+    #[inline]
+    pub fn res_indexed_subdict_mut(&mut self) -> &mut SubDictionary<IndexedTexture> {
+        &mut self.indexed_textures
+    }
+
+    #[inline]
+    pub fn res_palette_subdict(&self) -> &SubDictionary<Palette> {
+        &self.palettes
+    }
+    #[inline]
+    pub fn res_palette_subdict_mut(&mut self) -> &mut SubDictionary<Palette> {
+        &mut self.palettes
+    }
 
-        // insert item
-        T::get_subdict_mut(self).insert(id, item);
+    #[inline]
+    pub fn set_origin<T: Resource>(&mut self, handle: Handle<T>, layer: usize) {
+        self.origins
+            .insert((T::resource_type(), handle.index()), layer);
+    }
 
-        id
+    #[inline]
+    pub fn origin<T: Resource>(&self, handle: Handle<T>) -> Option<usize> {
+        self.origins
+            .get(&(T::resource_type(), handle.index()))
+            .copied()
     }
 
+    /// Give a resource the name of the variable it was built from.
     #[inline]
-    pub fn get_res<T: Resource>(&self, id: u32) -> Option<&T> {
-        T::get_subdict(self).get(&id)
+    pub fn set_name<T: Resource>(&mut self, name: String, handle: Handle<T>) {
+        self.names
+            .insert(name, (T::resource_type(), handle.index()));
+    }
+
+    /// Every named resource and its type, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = (&str, ResourceType)> {
+        self.names
+            .iter()
+            .map(|(name, (kind, _))| (name.as_str(), *kind))
+    }
+
+    /// Look up a resource by its name. If there is no resource with the name, the error lists the
+    /// names that are closest to it.
+    pub fn handle<T: Resource>(&self, name: &str) -> Result<Handle<T>, LitError> {
+        match self.names.get(name) {
+            Some((kind, index)) if *kind == T::resource_type() => T::get_subdict(self)
+                .handle(*index)
+                .ok_or_else(|| LitError::ResourceNameNotFound(name.to_string(), vec![])),
+            Some((kind, _)) => Err(LitError::ResourceTypeMismatch(
+                name.to_string(),
+                *kind,
+                T::resource_type(),
+            )),
+            None => Err(LitError::ResourceNameNotFound(
+                name.to_string(),
+                self.similar_names(name),
+            )),
+        }
+    }
+
+    // names that are a few typos away from a name, or that match it apart from case
+    fn similar_names(&self, name: &str) -> Vec<String> {
+        let lower = name.to_lowercase();
+        let max_distance = (name.chars().count() / 3).max(1);
+
+        let mut similar: Vec<(usize, &String)> = self
+            .names
+            .keys()
+            .filter_map(|other| {
+                let other_lower = other.to_lowercase();
+                if other_lower == lower {
+                    return Some((0, other));
+                }
+
+                let distance = edit_distance(&lower, &other_lower);
+                if distance <= max_distance {
+                    Some((distance, other))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        similar.sort();
+        similar
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, other)| other.clone())
+            .collect()
+    }
+
+    #[inline]
+    pub fn add_res<T: Resource>(&mut self, item: T) -> Handle<T> {
+        T::get_subdict_mut(self).insert(item)
+    }
+
+    /// Replace a resource with a new one, e.g. after it has been reloaded. Handles to the old
+    /// resource become stale, so the new handle is returned.
+    pub fn replace_res<T: Resource>(
+        &mut self,
+        handle: Handle<T>,
+        item: T,
+    ) -> Result<(Handle<T>, T), LitError> {
+        let replaced = T::get_subdict_mut(self)
+            .replace(handle, item)
+            .ok_or_else(|| stale(handle))?;
+
+        // the new resource is loaded when it is next used
+        self.residency.replaced(key(handle));
+        Ok(replaced)
+    }
+
+    /// Swap a resource for a new version of itself, e.g. after its data file changed. Unlike
+    /// `replace_res`, handles to it keep working. The new version is loaded when it is next used.
+    pub fn reload_res<T: Resource>(&mut self, handle: Handle<T>, item: T) -> Result<T, LitError> {
+        let old = T::get_subdict_mut(self)
+            .swap(handle, item)
+            .ok_or_else(|| stale(handle))?;
+        self.residency.replaced(key(handle));
+        Ok(old)
+    }
+
+    /// Take the resources of a dictionary that was built from changed data files. Resources are
+    /// matched up by name: the ones that changed are swapped in place, so that their handles keep
+    /// working, while the ones that went away or changed type are removed. Resources without a
+    /// name can't be matched up, so the ones already here are left alone and the new ones are
+    /// left out, with a warning for each.
+    pub fn merge(&mut self, mut other: ResourceDictionary) -> ReloadReport {
+        let mut report = ReloadReport::new();
+
+        let gone: Vec<(String, ResourceKey)> = self
+            .names
+            .iter()
+            .filter(|(name, (kind, _))| other.names.get(*name).map(|(k, _)| k) != Some(kind))
+            .map(|(name, key)| (name.clone(), *key))
+            .collect();
+        for (name, key) in gone {
+            self.remove_slot(key);
+            report.removed.push(name);
+        }
+
+        self.merge_subdict::<Image>(&mut other, &mut report);
+        self.merge_subdict::<IndexedTexture>(&mut other, &mut report);
+        self.merge_subdict::<Palette>(&mut other, &mut report);
+        report
+    }
+
+    // take the named resources of one type from another dictionary
+    fn merge_subdict<T: Resource>(
+        &mut self,
+        other: &mut ResourceDictionary,
+        report: &mut ReloadReport,
+    ) {
+        let names: Vec<(String, u32)> = other
+            .names
+            .iter()
+            .filter(|(_, (kind, _))| *kind == T::resource_type())
+            .map(|(name, (_, index))| (name.clone(), *index))
+            .collect();
+
+        for (name, index) in names {
+            let theirs = match T::get_subdict(other).handle(index) {
+                Some(handle) => handle,
+                None => continue,
+            };
+            let layer = other.origin(theirs);
+            let item = match T::get_subdict_mut(other).remove(theirs) {
+                Some(item) => item,
+                None => continue,
+            };
+
+            let ours = self
+                .names
+                .get(&name)
+                .and_then(|(_, index)| T::get_subdict(self).handle(*index));
+            let handle = match ours {
+                Some(ours) => {
+                    if T::get_subdict(self)
+                        .get(ours)
+                        .is_some_and(|current| current.same_source(&item))
+                    {
+                        continue;
+                    }
+                    // the handle was just looked up, so it can't be stale
+                    let _ = self.reload_res(ours, item);
+                    report.changed.push(name);
+                    ours
+                }
+                None => {
+                    let handle = self.add_res(item);
+                    self.set_name(name.clone(), handle);
+                    report.added.push(name);
+                    handle
+                }
+            };
+
+            if let Some(layer) = layer {
+                self.set_origin(handle, layer);
+            }
+        }
+
+        // whatever is left had no name, so there is nothing to match it up with
+        for (handle, _) in T::get_subdict(other).iter() {
+            let layer = other.origin(handle).map_or_else(
+                || String::from("an unknown layer"),
+                |l| format!("layer {}", l),
+            );
+            logging::log(
+                Level::Warn,
+                Target::Resource,
+                format_args!(
+                    "{:?} {} from {} has no name, so it can't be reloaded",
+                    T::resource_type(),
+                    handle.index(),
+                    layer
+                ),
+            );
+        }
+    }
+
+    // remove a resource by its slot, whatever generation it is at
+    fn remove_slot(&mut self, key: ResourceKey) {
+        match key.0 {
+            ResourceType::Image => self.remove_index::<Image>(key.1),
+            ResourceType::IndexedTexture => self.remove_index::<IndexedTexture>(key.1),
+            ResourceType::Palette => self.remove_index::<Palette>(key.1),
+        }
+    }
+
+    #[inline]
+    fn remove_index<T: Resource>(&mut self, index: u32) {
+        if let Some(handle) = T::get_subdict(self).handle(index) {
+            let _ = self.remove_res(handle);
+        }
+    }
+
+    /// Remove a resource, along with its name.
+    pub fn remove_res<T: Resource>(&mut self, handle: Handle<T>) -> Result<T, LitError> {
+        let item = T::get_subdict_mut(self)
+            .remove(handle)
+            .ok_or_else(|| stale(handle))?;
+
+        let key = key(handle);
+        self.names.retain(|_, v| *v != key);
+        self.origins.remove(&key);
+        self.residency.removed(key);
+        Ok(item)
+    }
+
+    #[inline]
+    pub fn get<T: Resource>(&self, handle: Handle<T>) -> Result<&T, LitError> {
+        T::get_subdict(self)
+            .get(handle)
+            .ok_or_else(|| stale(handle))
+    }
+
+    #[inline]
+    pub fn get_mut<T: Resource>(&mut self, handle: Handle<T>) -> Result<&mut T, LitError> {
+        T::get_subdict_mut(self)
+            .get_mut(handle)
+            .ok_or_else(|| stale(handle))
+    }
+
+    /// Get a resource to draw with, loading it first if needed. If loading it would go over the
+    /// memory budget, the resources that were used the longest ago are evicted to make room.
+    pub fn load<T: Loadable>(
+        &mut self,
+        gl: &gl::Gl,
+        handle: Handle<T>,
+    ) -> Result<&mut T, LitError> {
+        let key = key(handle);
+        let item = self.get(handle)?;
+        if item.is_loaded() {
+            self.residency.touch(key);
+            return self.get_mut(handle);
+        }
+
+        let size = item.memory_size();
+        self.make_room(key, size);
+        self.get_mut(handle)?.load(gl)?;
+        self.residency.loaded(key, size);
+        self.get_mut(handle)
+    }
+
+    /// Create the texture of an image from pixels that were drawn on a loader thread. Nothing
+    /// happens if the image was replaced or removed in the meantime, or has been loaded since.
+    pub fn upload_pixels(
+        &mut self,
+        gl: &gl::Gl,
+        handle: Handle<Image>,
+        pixels: &PixelBuffer,
+    ) -> Result<(), LitError> {
+        let size = match self.images.get(handle) {
+            Some(image) if !image.is_loaded() => image.memory_size(),
+            _ => return Ok(()),
+        };
+
+        let key = key(handle);
+        self.make_room(key, size);
+        self.get_mut(handle)?.upload_pixels(gl, pixels)?;
+        self.residency.loaded(key, size);
+        Ok(())
+    }
+
+    // evict resources until another one fits in the memory budget
+    fn make_room(&mut self, key: ResourceKey, size: usize) {
+        while let Some(victim) = self.residency.victim(size) {
+            self.evict(victim);
+        }
+
+        if self.residency.over_budget(size) {
+            logging::log(
+                Level::Warn,
+                Target::Resource,
+                format_args!(
+                    "Loading {:?} {} goes over the memory budget, since everything else is in use",
+                    key.0, key.1
+                ),
+            );
+        }
+    }
+
+    /// Hold a reference to a resource, e.g. for each scene that uses it. A resource is never
+    /// evicted while somebody holds a reference to it.
+    pub fn acquire<T: Resource>(&mut self, handle: Handle<T>) -> Result<(), LitError> {
+        self.get(handle)?;
+        self.residency.acquire(key(handle));
+        Ok(())
+    }
+
+    /// Whether anybody holds a reference to a resource.
+    #[inline]
+    pub fn is_referenced<T: Resource>(&self, handle: Handle<T>) -> bool {
+        self.residency.is_referenced(key(handle))
+    }
+
+    /// Let go of a reference to a resource. References are kept when a resource is replaced, so
+    /// the old handle can still be used for this.
+    #[inline]
+    pub fn release<T: Resource>(&mut self, handle: Handle<T>) {
+        self.residency.release(key(handle));
+    }
+
+    /// Evict every resource that nobody holds a reference to and that hasn't been used for a
+    /// while. This is called once every frame.
+    pub fn collect(&mut self) {
+        for key in self.residency.idle() {
+            self.evict(key);
+        }
+        self.residency.next_frame();
+    }
+
+    /// Set the most GPU memory that resources should use, in bytes.
+    #[inline]
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        self.residency.set_budget(budget);
+    }
+
+    #[inline]
+    pub fn stats(&self) -> ResourceStats {
+        self.residency.stats()
+    }
+
+    fn evict(&mut self, key: ResourceKey) {
+        match key.0 {
+            ResourceType::Image => unload_slot(&mut self.images, key.1),
+            ResourceType::IndexedTexture => unload_slot(&mut self.indexed_textures, key.1),
+            // palettes are only kept in memory
+            ResourceType::Palette => {}
+        }
+        self.residency.evicted(key);
+    }
+}
+
+// the key a resource's bookkeeping is stored under
+#[inline]
+fn key<T: Resource>(handle: Handle<T>) -> ResourceKey {
+    (T::resource_type(), handle.index())
+}
+
+// unload a resource by its slot, whatever generation it is at
+fn unload_slot<T: Loadable>(subdict: &mut SubDictionary<T>, index: u32) {
+    if let Some(item) = subdict.handle(index).and_then(move |h| subdict.get_mut(h)) {
+        item.unload();
+    }
+}
+
+// the error for a handle whose resource has been replaced or removed
+#[inline]
+fn stale<T: Resource>(handle: Handle<T>) -> LitError {
+    LitError::StaleHandle(T::resource_type(), handle.index(), handle.generation())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{logging::RingBuffer, Color};
+
+    fn palette(index: u8) -> Palette {
+        let mut palette = Palette::new();
+        palette.set(index, Color::new(255, 255, 255));
+        palette
+    }
+
+    fn named(entries: &[(&str, u8)]) -> ResourceDictionary {
+        let mut rd = ResourceDictionary::new();
+        for (name, index) in entries {
+            let handle = rd.add_res(palette(*index));
+            rd.set_name(name.to_string(), handle);
+        }
+        rd
+    }
+
+    #[test]
+    fn reload_res_keeps_the_handle() {
+        let mut rd = ResourceDictionary::new();
+        let handle = rd.add_res(palette(1));
+        rd.acquire(handle).unwrap();
+
+        let old = rd.reload_res(handle, palette(2)).unwrap();
+        assert_eq!(old, palette(1));
+        assert_eq!(rd.get(handle).unwrap(), &palette(2));
+        assert!(rd.is_referenced(handle));
+
+        // unlike reloading, replacing makes the old handle stale
+        let (new, _) = rd.replace_res(handle, palette(3)).unwrap();
+        assert!(rd.get(handle).is_err());
+        assert_eq!(rd.get(new).unwrap(), &palette(3));
+    }
+
+    #[test]
+    fn merge_keeps_the_handles_of_named_resources() {
+        let mut rd = named(&[("SAME", 1), ("CHANGED", 2), ("GONE", 3)]);
+        let same: Handle<Palette> = rd.handle("SAME").unwrap();
+        let changed: Handle<Palette> = rd.handle("CHANGED").unwrap();
+        let gone: Handle<Palette> = rd.handle("GONE").unwrap();
+
+        let report = rd.merge(named(&[("SAME", 1), ("CHANGED", 4), ("ADDED", 5)]));
+        assert_eq!(report.changed, vec!["CHANGED"]);
+        assert_eq!(report.added, vec!["ADDED"]);
+        assert_eq!(report.removed, vec!["GONE"]);
+
+        assert_eq!(rd.get(same).unwrap(), &palette(1));
+        assert_eq!(rd.get(changed).unwrap(), &palette(4));
+        assert!(rd.get(gone).is_err());
+        assert_eq!(rd.handle::<Palette>("CHANGED").unwrap(), changed);
+    }
+
+    #[test]
+    fn merge_warns_about_unnamed_resources() {
+        let console = RingBuffer::new(64);
+        logging::add_sink(Level::Warn, console.clone());
+
+        let mut rd = ResourceDictionary::new();
+        let kept = rd.add_res(palette(1));
+        let mut other = ResourceDictionary::new();
+        let unnamed = other.add_res(palette(2));
+        other.set_origin(unnamed, 7);
+
+        assert!(rd.merge(other).is_empty());
+        assert_eq!(rd.get(kept).unwrap(), &palette(1));
+        assert!(console
+            .records()
+            .iter()
+            .any(|r| r.message == "Palette 0 from layer 7 has no name, so it can't be reloaded"));
     }
 }
@@ -0,0 +1,75 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// resource/image.rs - Texture resource drawn by the data files, uploaded when it is first used.
+
+use super::Loadable;
+use crate::{DrawBuffer, ImgTexture, LitError, PixelBuffer};
+use lit_gl_wrapper::gl;
+
+#[derive(Debug)]
+pub struct Image {
+    buffer: DrawBuffer,
+    // created the first time the image is drawn, and dropped again when it is evicted
+    texture: Option<ImgTexture>,
+}
+
+impl Image {
+    pub fn new(buffer: DrawBuffer) -> Self {
+        Self {
+            buffer,
+            texture: None,
+        }
+    }
+
+    #[inline]
+    pub fn buffer(&self) -> &DrawBuffer {
+        &self.buffer
+    }
+
+    /// Get the texture to draw with, if the image is loaded.
+    #[inline]
+    pub fn texture(&self) -> Option<&ImgTexture> {
+        self.texture.as_ref()
+    }
+
+    /// Create the texture from pixels that were drawn ahead of time, e.g. on a loader thread.
+    #[inline]
+    pub fn upload_pixels(&mut self, gl: &gl::Gl, pixels: &PixelBuffer) -> Result<(), LitError> {
+        self.texture = Some(pixels.upload(gl)?);
+        Ok(())
+    }
+
+    /// Get the texture to draw with, drawing and creating it if needed.
+    pub fn upload(&mut self, gl: &gl::Gl) -> Result<&ImgTexture, LitError> {
+        if self.texture.is_none() {
+            self.texture = Some(self.buffer.rasterize().upload(gl)?);
+        }
+
+        match self.texture {
+            Some(ref texture) => Ok(texture),
+            None => unreachable!(),
+        }
+    }
+}
+
+impl Loadable for Image {
+    #[inline]
+    fn memory_size(&self) -> usize {
+        // RGBA, a byte per channel
+        self.buffer.width() as usize * self.buffer.height() as usize * 4
+    }
+
+    #[inline]
+    fn is_loaded(&self) -> bool {
+        self.texture.is_some()
+    }
+
+    #[inline]
+    fn load(&mut self, gl: &gl::Gl) -> Result<(), LitError> {
+        self.upload(gl).map(|_| ())
+    }
+
+    #[inline]
+    fn unload(&mut self) {
+        self.texture = None;
+    }
+}
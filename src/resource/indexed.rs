@@ -0,0 +1,169 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// resource/indexed.rs - Indexed texture resource, whose palette can be swapped at runtime.
+
+use super::Loadable;
+use crate::{
+    draw::IndexedBuffer, IndexTexture, LitError, Palette, PaletteCycle, PaletteTexture,
+    PALETTE_SIZE,
+};
+use lit_gl_wrapper::gl;
+
+#[derive(Debug)]
+pub struct IndexedTexture {
+    buffer: IndexedBuffer,
+    palette: Palette,
+    // cycles rotate the colors that are shown, not the palette itself, so that they keep going
+    // when the palette is swapped
+    cycles: Vec<PaletteCycle>,
+
+    // the textures are created the first time the texture is drawn. the palette is uploaded
+    // again whenever it changes, while the indices never are
+    textures: Option<(IndexTexture, PaletteTexture)>,
+    palette_changed: bool,
+}
+
+impl IndexedTexture {
+    pub fn new(buffer: IndexedBuffer, palette: Palette) -> Self {
+        Self {
+            buffer,
+            palette,
+            cycles: vec![],
+            textures: None,
+            palette_changed: false,
+        }
+    }
+
+    #[inline]
+    pub fn buffer(&self) -> &IndexedBuffer {
+        &self.buffer
+    }
+
+    #[inline]
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Get the palette to change some of its colors.
+    #[inline]
+    pub fn palette_mut(&mut self) -> &mut Palette {
+        self.palette_changed = true;
+        &mut self.palette
+    }
+
+    /// Replace the whole palette, e.g. to recolor an enemy.
+    pub fn set_palette(&mut self, palette: &Palette) {
+        if self.palette != *palette {
+            self.palette.clone_from(palette);
+            self.palette_changed = true;
+        }
+    }
+
+    #[inline]
+    pub fn add_cycle(&mut self, cycle: PaletteCycle) {
+        self.cycles.push(cycle);
+    }
+
+    #[inline]
+    pub fn cycles(&self) -> &[PaletteCycle] {
+        &self.cycles
+    }
+
+    /// Advance every palette cycle by a frame. This is called once every frame.
+    pub fn advance(&mut self) {
+        for cycle in self.cycles.iter_mut() {
+            if cycle.advance() {
+                self.palette_changed = true;
+            }
+        }
+    }
+
+    /// Get the colors that are shown, with every cycle applied to the palette.
+    pub fn shown_palette(&self) -> Palette {
+        let mut palette = self.palette.clone();
+        for cycle in &self.cycles {
+            cycle.apply(&mut palette);
+        }
+        palette
+    }
+
+    /// Get the textures to draw with, creating them or updating the palette as needed.
+    pub fn upload(&mut self, gl: &gl::Gl) -> Result<(&IndexTexture, &PaletteTexture), LitError> {
+        match self.textures {
+            Some((_, ref palette)) if self.palette_changed => {
+                palette.update(&self.shown_palette().as_rgba())?;
+            }
+            Some(_) => {}
+            None => {
+                let indices = IndexTexture::from_raw(
+                    gl,
+                    &[self.buffer.width(), self.buffer.height()],
+                    self.buffer.indices().as_ptr(),
+                )?;
+                let shown = self.shown_palette();
+                let palette = PaletteTexture::from_raw(
+                    gl,
+                    &[shown.colors().len() as u32],
+                    shown.as_rgba().as_ptr(),
+                )?;
+                self.textures = Some((indices, palette));
+            }
+        }
+        self.palette_changed = false;
+
+        match self.textures {
+            Some((ref indices, ref palette)) => Ok((indices, palette)),
+            None => unreachable!(),
+        }
+    }
+}
+
+impl Loadable for IndexedTexture {
+    #[inline]
+    fn memory_size(&self) -> usize {
+        // a byte per pixel, plus the RGBA palette
+        self.buffer.width() as usize * self.buffer.height() as usize + PALETTE_SIZE * 4
+    }
+
+    #[inline]
+    fn is_loaded(&self) -> bool {
+        self.textures.is_some()
+    }
+
+    #[inline]
+    fn load(&mut self, gl: &gl::Gl) -> Result<(), LitError> {
+        self.upload(gl).map(|_| ())
+    }
+
+    #[inline]
+    fn unload(&mut self) {
+        self.textures = None;
+        self.palette_changed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Color;
+
+    #[test]
+    fn shown_palette_follows_swaps_and_cycles() {
+        let mut texture = IndexedTexture::new(IndexedBuffer::new(2, 2, 0), Palette::new());
+        texture.add_cycle(PaletteCycle::new(1, 2, 1));
+
+        let mut palette = Palette::new();
+        palette.set(1, Color::new(255, 0, 0));
+        palette.set(2, Color::new(0, 0, 255));
+        texture.set_palette(&palette);
+        assert!(texture.palette_changed);
+        assert_eq!(texture.shown_palette(), palette);
+
+        // the cycle moves every color of the range on by one each frame, without changing the
+        // palette that was set
+        texture.advance();
+        let shown = texture.shown_palette();
+        assert_eq!(shown.get(1), palette.get(2));
+        assert_eq!(shown.get(2), palette.get(1));
+        assert_eq!(texture.palette(), &palette);
+    }
+}
@@ -0,0 +1,207 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// resource/loader.rs - Draw textures on worker threads, leaving only the upload to the GL thread.
+
+use super::{Handle, Image, Loadable, ResourceDictionary};
+use crate::{DrawBuffer, LitError, PixelBuffer};
+use lit_gl_wrapper::gl;
+use std::{
+    collections::HashSet,
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+// a texture to draw, and the image it belongs to
+struct Job {
+    handle: Handle<Image>,
+    buffer: DrawBuffer,
+}
+
+// the buffer is handed back too, since the image may have been reloaded while it was being drawn
+struct Drawn {
+    handle: Handle<Image>,
+    buffer: DrawBuffer,
+    pixels: PixelBuffer,
+}
+
+/// Draws images on a pool of threads. The GL context can only be used on the thread that created
+/// it, so the drawn pixels are handed back to be uploaded from there, a few at a time, while the
+/// game keeps running.
+pub struct Loader {
+    jobs: Option<Sender<Job>>,
+    drawn: Receiver<Drawn>,
+    workers: Vec<JoinHandle<()>>,
+
+    // images that have been queued but not uploaded yet
+    pending: HashSet<Handle<Image>>,
+    uploaded: usize,
+}
+
+impl Loader {
+    pub fn new(threads: usize) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (drawn_sender, drawn) = mpsc::channel();
+
+        // the workers take turns waiting for the next job
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let jobs = Arc::clone(&job_receiver);
+                let drawn = drawn_sender.clone();
+                thread::spawn(move || loop {
+                    let job = match jobs.lock() {
+                        Ok(jobs) => jobs.recv(),
+                        Err(_) => return,
+                    };
+
+                    // the loader hangs up on the workers when it is dropped
+                    let job = match job {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+                    let pixels = job.buffer.rasterize();
+                    if drawn
+                        .send(Drawn {
+                            handle: job.handle,
+                            buffer: job.buffer,
+                            pixels,
+                        })
+                        .is_err()
+                    {
+                        return;
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            jobs: Some(jobs),
+            drawn,
+            workers,
+            pending: HashSet::new(),
+            uploaded: 0,
+        }
+    }
+
+    /// Queue an image to be drawn, unless it is loaded or queued already.
+    pub fn queue(
+        &mut self,
+        rd: &ResourceDictionary,
+        handle: Handle<Image>,
+    ) -> Result<(), LitError> {
+        let image = rd.get(handle)?;
+        if image.is_loaded() || self.pending.contains(&handle) {
+            return Ok(());
+        }
+
+        let job = Job {
+            handle,
+            buffer: image.buffer().clone(),
+        };
+        self.jobs
+            .as_ref()
+            .and_then(|jobs| jobs.send(job).ok())
+            .ok_or(LitError::StaticMsg("Every loader thread has stopped"))?;
+        self.pending.insert(handle);
+        Ok(())
+    }
+
+    /// Queue every image that somebody holds a reference to, e.g. the images of the scenes that
+    /// are in use, and that isn't loaded yet. Everything else is loaded when it is first used, so
+    /// that images which aren't needed yet don't take up the memory budget.
+    pub fn queue_referenced(&mut self, rd: &ResourceDictionary) -> Result<(), LitError> {
+        let handles: Vec<Handle<Image>> = rd
+            .res_img_subdict()
+            .iter()
+            .map(|(h, _)| h)
+            .filter(|h| rd.is_referenced(*h))
+            .collect();
+        for handle in handles {
+            self.queue(rd, handle)?;
+        }
+        Ok(())
+    }
+
+    /// Upload some of the images that have been drawn, without waiting for any others. Returns
+    /// the number of images that were uploaded. This has to be called on the GL thread, and is
+    /// meant to be called once every frame, so that the uploads are spread out.
+    pub fn upload(
+        &mut self,
+        rd: &mut ResourceDictionary,
+        gl: &gl::Gl,
+        max: usize,
+    ) -> Result<usize, LitError> {
+        let mut count = 0;
+        while count < max {
+            let drawn = match self.drawn.try_recv() {
+                Ok(drawn) => drawn,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    return Err(LitError::StaticMsg("Every loader thread has stopped"))
+                }
+            };
+
+            self.pending.remove(&drawn.handle);
+            // an image that changed while it was being drawn is drawn again, and one that was
+            // removed is skipped
+            match rd.get(drawn.handle) {
+                Ok(image) if *image.buffer() == drawn.buffer => {}
+                Ok(_) => {
+                    self.queue(rd, drawn.handle)?;
+                    continue;
+                }
+                Err(_) => continue,
+            }
+            rd.upload_pixels(gl, drawn.handle, &drawn.pixels)?;
+            self.uploaded += 1;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// The number of images that have been uploaded, and the number that have been queued in
+    /// total, e.g. for a loading bar.
+    #[inline]
+    pub fn progress(&self) -> (usize, usize) {
+        (self.uploaded, self.uploaded + self.pending.len())
+    }
+
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        // once the jobs are gone, every worker stops after its current job
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Color, DrawHandle};
+
+    #[test]
+    fn only_referenced_images_are_preloaded() {
+        let mut rd = ResourceDictionary::new();
+        let image = || Image::new(DrawBuffer::new(2, 2, Color::transparent()));
+        let unused = rd.res_img_subdict_mut().insert(image());
+        let used = rd.res_img_subdict_mut().insert(image());
+        rd.acquire(used).unwrap();
+
+        let mut loader = Loader::new(1);
+        loader.queue_referenced(&rd).unwrap();
+        assert_eq!(loader.progress(), (0, 1));
+        assert!(loader.pending.contains(&used));
+        assert!(!loader.pending.contains(&unused));
+    }
+}
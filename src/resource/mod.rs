@@ -2,30 +2,121 @@
 // resource/mod.rs - Define resources and resource dictionaries.
 
 mod dictionary;
+mod handle;
+mod image;
+mod indexed;
+mod loader;
+mod reload;
+mod residency;
+mod subdict;
 
 pub use dictionary::ResourceDictionary;
+pub use handle::Handle;
+pub use image::Image;
+pub use indexed::IndexedTexture;
+pub use loader::Loader;
+pub use reload::ReloadReport;
+pub use residency::ResourceStats;
+pub use subdict::SubDictionary;
 
-use crate::{BytecodeObject, ImgTexture, LitError};
-use std::collections::HashMap;
+use crate::{LitError, Palette};
+use lit_gl_wrapper::gl;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum ResourceType {
-    ImgTexture,
+    Image,
+    IndexedTexture,
+    Palette,
 }
 
 pub trait Resource: Sized {
-    fn get_subdict(dict: &ResourceDictionary) -> &HashMap<u32, Self>;
-    fn get_subdict_mut(dict: &mut ResourceDictionary) -> &mut HashMap<u32, Self>;
+    fn resource_type() -> ResourceType;
+    /// Whether two resources were built from the same data, so that reloading one as the other
+    /// wouldn't change anything.
+    fn same_source(&self, other: &Self) -> bool;
+    fn get_subdict(dict: &ResourceDictionary) -> &SubDictionary<Self>;
+    fn get_subdict_mut(dict: &mut ResourceDictionary) -> &mut SubDictionary<Self>;
 }
 
-impl Resource for ImgTexture {
+/// A resource whose GPU data is created the first time it is used, and can be dropped again to
+/// free up memory. The data it is created from is always kept.
+pub trait Loadable: Resource {
+    /// The number of bytes of GPU memory the resource takes up once it is loaded.
+    fn memory_size(&self) -> usize;
+    fn is_loaded(&self) -> bool;
+    fn load(&mut self, gl: &gl::Gl) -> Result<(), LitError>;
+    fn unload(&mut self);
+}
+
+impl Resource for Image {
+    #[inline]
+    fn resource_type() -> ResourceType {
+        ResourceType::Image
+    }
+
     #[inline]
-    fn get_subdict(dict: &ResourceDictionary) -> &HashMap<u32, Self> {
+    fn same_source(&self, other: &Self) -> bool {
+        self.buffer() == other.buffer()
+    }
+
+    #[inline]
+    fn get_subdict(dict: &ResourceDictionary) -> &SubDictionary<Self> {
         dict.res_img_subdict()
     }
 
     #[inline]
-    fn get_subdict_mut(dict: &mut ResourceDictionary) -> &mut HashMap<u32, Self> {
+    fn get_subdict_mut(dict: &mut ResourceDictionary) -> &mut SubDictionary<Self> {
         dict.res_img_subdict_mut()
     }
 }
+
+impl Resource for IndexedTexture {
+    #[inline]
+    fn resource_type() -> ResourceType {
+        ResourceType::IndexedTexture
+    }
+
+    #[inline]
+    fn same_source(&self, other: &Self) -> bool {
+        self.buffer() == other.buffer()
+            && self.palette() == other.palette()
+            && self.cycles().len() == other.cycles().len()
+            && self
+                .cycles()
+                .iter()
+                .zip(other.cycles())
+                .all(|(a, b)| a.range() == b.range() && a.speed() == b.speed())
+    }
+
+    #[inline]
+    fn get_subdict(dict: &ResourceDictionary) -> &SubDictionary<Self> {
+        dict.res_indexed_subdict()
+    }
+
+    #[inline]
+    fn get_subdict_mut(dict: &mut ResourceDictionary) -> &mut SubDictionary<Self> {
+        dict.res_indexed_subdict_mut()
+    }
+}
+
+impl Resource for Palette {
+    #[inline]
+    fn resource_type() -> ResourceType {
+        ResourceType::Palette
+    }
+
+    #[inline]
+    fn same_source(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    #[inline]
+    fn get_subdict(dict: &ResourceDictionary) -> &SubDictionary<Self> {
+        dict.res_palette_subdict()
+    }
+
+    #[inline]
+    fn get_subdict_mut(dict: &mut ResourceDictionary) -> &mut SubDictionary<Self> {
+        dict.res_palette_subdict_mut()
+    }
+}
@@ -0,0 +1,63 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// resource/reload.rs - Summary of what changed when resources were reloaded.
+
+use std::fmt;
+
+/// The names of the resources that changed when the data files were reloaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReloadReport {
+    /// Resources that were swapped in place. Their handles keep working.
+    pub changed: Vec<String>,
+    pub added: Vec<String>,
+    /// Resources that are gone, or that changed type. Their handles are stale.
+    pub removed: Vec<String>,
+}
+
+impl ReloadReport {
+    pub fn new() -> Self {
+        Self {
+            changed: vec![],
+            added: vec![],
+            removed: vec![],
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl Default for ReloadReport {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for ReloadReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No resources changed");
+        }
+
+        let mut first = true;
+        for (what, names) in [
+            ("Changed", &self.changed),
+            ("Added", &self.added),
+            ("Removed", &self.removed),
+        ]
+        .iter()
+        {
+            if names.is_empty() {
+                continue;
+            }
+            if !first {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}: {}", what, names.join(", "))?;
+            first = false;
+        }
+        Ok(())
+    }
+}
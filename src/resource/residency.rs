@@ -0,0 +1,333 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// resource/residency.rs - Track which resources are loaded and how much memory they use.
+
+use super::ResourceType;
+use std::{collections::HashMap, fmt};
+
+// resources nobody holds a reference to are evicted after going unused for this many frames,
+// which is ten seconds at 60 frames per second
+const IDLE_FRAMES: u64 = 600;
+
+// a resource is identified by its type and slot, so that it keeps its references and memory
+// when it is replaced
+pub(super) type ResourceKey = (ResourceType, u32);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResourceStats {
+    /// The number of resources that are loaded.
+    pub resident: usize,
+    /// The bytes of GPU memory used by loaded resources.
+    pub memory: usize,
+    pub peak_memory: usize,
+    pub budget: Option<usize>,
+    /// The number of times a resource had to be loaded.
+    pub loads: u64,
+    /// The number of times a resource was used while it was already loaded.
+    pub hits: u64,
+    pub evictions: u64,
+}
+
+impl fmt::Display for ResourceStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} resources loaded, using {} KiB",
+            self.resident,
+            self.memory / 1024
+        )?;
+        if let Some(budget) = self.budget {
+            write!(f, " of {} KiB", budget / 1024)?;
+        }
+        write!(
+            f,
+            " (peak {} KiB), {} loads, {} hits, {} evictions",
+            self.peak_memory / 1024,
+            self.loads,
+            self.hits,
+            self.evictions
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Resident {
+    size: usize,
+    last_used: u64,
+}
+
+#[derive(Debug)]
+pub(super) struct Residency {
+    refs: HashMap<ResourceKey, u32>,
+    loaded: HashMap<ResourceKey, Resident>,
+    frame: u64,
+    stats: ResourceStats,
+}
+
+impl Residency {
+    pub fn new() -> Self {
+        Self {
+            refs: HashMap::new(),
+            loaded: HashMap::new(),
+            frame: 0,
+            stats: ResourceStats {
+                resident: 0,
+                memory: 0,
+                peak_memory: 0,
+                budget: None,
+                loads: 0,
+                hits: 0,
+                evictions: 0,
+            },
+        }
+    }
+
+    #[inline]
+    pub fn stats(&self) -> ResourceStats {
+        self.stats
+    }
+
+    #[inline]
+    pub fn set_budget(&mut self, budget: Option<usize>) {
+        self.stats.budget = budget;
+    }
+
+    #[inline]
+    pub fn acquire(&mut self, key: ResourceKey) {
+        *self.refs.entry(key).or_insert(0) += 1;
+    }
+
+    pub fn release(&mut self, key: ResourceKey) {
+        if let Some(refs) = self.refs.get_mut(&key) {
+            *refs -= 1;
+            if *refs == 0 {
+                self.refs.remove(&key);
+            }
+        }
+    }
+
+    #[inline]
+    pub fn is_referenced(&self, key: ResourceKey) -> bool {
+        self.refs.contains_key(&key)
+    }
+
+    /// Record that a loaded resource was used.
+    pub fn touch(&mut self, key: ResourceKey) {
+        if let Some(resident) = self.loaded.get_mut(&key) {
+            resident.last_used = self.frame;
+            self.stats.hits += 1;
+        }
+    }
+
+    /// Whether loading a resource of some size would go over the budget.
+    #[inline]
+    pub fn over_budget(&self, size: usize) -> bool {
+        self.stats
+            .budget
+            .is_some_and(|budget| self.stats.memory + size > budget)
+    }
+
+    /// The resource to evict to make room for another one: the one that was used the longest
+    /// ago, out of those that nobody holds a reference to and that weren't used this frame.
+    pub fn victim(&self, size: usize) -> Option<ResourceKey> {
+        if !self.over_budget(size) {
+            return None;
+        }
+
+        self.loaded
+            .iter()
+            .filter(|(key, resident)| {
+                !self.refs.contains_key(key) && resident.last_used < self.frame
+            })
+            .min_by_key(|(_, resident)| resident.last_used)
+            .map(|(key, _)| *key)
+    }
+
+    /// Every resource that nobody holds a reference to and that hasn't been used for a while.
+    pub fn idle(&self) -> Vec<ResourceKey> {
+        self.loaded
+            .iter()
+            .filter(|(key, resident)| {
+                !self.refs.contains_key(key) && self.frame - resident.last_used >= IDLE_FRAMES
+            })
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    pub fn loaded(&mut self, key: ResourceKey, size: usize) {
+        self.loaded.insert(
+            key,
+            Resident {
+                size,
+                last_used: self.frame,
+            },
+        );
+        self.stats.resident += 1;
+        self.stats.memory += size;
+        self.stats.peak_memory = self.stats.peak_memory.max(self.stats.memory);
+        self.stats.loads += 1;
+    }
+
+    pub fn evicted(&mut self, key: ResourceKey) {
+        if self.unloaded(key) {
+            self.stats.evictions += 1;
+        }
+    }
+
+    /// Forget about a resource that was replaced, keeping the references to it.
+    pub fn replaced(&mut self, key: ResourceKey) {
+        self.unloaded(key);
+    }
+
+    /// Forget about a resource that was removed, along with the references to it.
+    pub fn removed(&mut self, key: ResourceKey) {
+        self.unloaded(key);
+        self.refs.remove(&key);
+    }
+
+    // returns false if the resource wasn't loaded
+    fn unloaded(&mut self, key: ResourceKey) -> bool {
+        match self.loaded.remove(&key) {
+            Some(resident) => {
+                self.stats.resident -= 1;
+                self.stats.memory -= resident.size;
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[inline]
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: ResourceKey = (ResourceType::Image, 0);
+    const B: ResourceKey = (ResourceType::Image, 1);
+    const C: ResourceKey = (ResourceType::Palette, 0);
+
+    fn frames(residency: &mut Residency, count: u64) {
+        for _ in 0..count {
+            residency.next_frame();
+        }
+    }
+
+    // A, B and C are loaded on frames 0, 1 and 2, taking up 40 bytes each
+    fn loaded(budget: Option<usize>) -> Residency {
+        let mut residency = Residency::new();
+        residency.set_budget(budget);
+        for key in [A, B, C].iter() {
+            residency.loaded(*key, 40);
+            residency.next_frame();
+        }
+        residency
+    }
+
+    #[test]
+    fn the_least_recently_used_resource_is_evicted() {
+        let mut residency = loaded(Some(150));
+        assert_eq!(residency.victim(30), None);
+        assert_eq!(residency.victim(31), Some(A));
+
+        residency.touch(A);
+        residency.next_frame();
+        assert_eq!(residency.victim(31), Some(B));
+        residency.evicted(B);
+        assert_eq!(residency.victim(31), None);
+
+        // resources used this frame are still being drawn, so they are never evicted
+        residency.touch(A);
+        residency.touch(C);
+        assert!(residency.over_budget(71));
+        assert_eq!(residency.victim(71), None);
+
+        // without a budget nothing is evicted to make room
+        assert_eq!(loaded(None).victim(usize::MAX / 2), None);
+    }
+
+    #[test]
+    fn referenced_resources_are_never_evicted() {
+        let mut residency = loaded(Some(120));
+        residency.acquire(A);
+        residency.acquire(A);
+        assert_eq!(residency.victim(1), Some(B));
+        residency.acquire(B);
+        residency.acquire(C);
+        assert_eq!(residency.victim(1), None);
+
+        frames(&mut residency, IDLE_FRAMES);
+        assert!(residency.idle().is_empty());
+
+        // a resource is free to go once every reference to it is released
+        residency.release(A);
+        assert!(residency.is_referenced(A));
+        residency.release(A);
+        assert!(!residency.is_referenced(A));
+        assert_eq!(residency.victim(1), Some(A));
+        assert_eq!(residency.idle(), [A]);
+    }
+
+    #[test]
+    fn unused_resources_become_idle() {
+        let mut residency = loaded(None);
+        frames(&mut residency, IDLE_FRAMES - 3);
+        assert_eq!(residency.idle(), [A]);
+
+        residency.touch(B);
+        frames(&mut residency, 1);
+        assert_eq!(residency.idle(), [A]);
+        frames(&mut residency, 1);
+        let idle = residency.idle();
+        assert_eq!(idle.len(), 2);
+        assert!(idle.contains(&A) && idle.contains(&C));
+    }
+
+    #[test]
+    fn removing_a_resource_drops_its_references() {
+        let mut residency = loaded(None);
+        residency.acquire(A);
+        residency.acquire(B);
+        residency.replaced(A);
+        residency.removed(B);
+        assert!(residency.is_referenced(A));
+        assert!(!residency.is_referenced(B));
+        assert_eq!(residency.stats().resident, 1);
+        assert_eq!(residency.stats().evictions, 0);
+    }
+
+    #[test]
+    fn stats_count_loads_hits_and_evictions() {
+        let mut residency = loaded(Some(100 * 1024));
+        residency.touch(A);
+        residency.touch(A);
+        // touching a resource that isn't loaded isn't a hit
+        residency.touch((ResourceType::IndexedTexture, 0));
+        residency.evicted(A);
+        residency.evicted(A);
+        residency.loaded(A, 2048);
+
+        let stats = residency.stats();
+        assert_eq!(
+            stats,
+            ResourceStats {
+                resident: 3,
+                memory: 2128,
+                peak_memory: 2128,
+                budget: Some(100 * 1024),
+                loads: 4,
+                hits: 2,
+                evictions: 1,
+            }
+        );
+        residency.evicted(A);
+        assert_eq!(residency.stats().peak_memory, 2128);
+        assert_eq!(
+            stats.to_string(),
+            "3 resources loaded, using 2 KiB of 100 KiB (peak 2 KiB), 4 loads, 2 hits, 1 evictions"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// resource/subdict.rs - Storage for resources of a single type, addressed by generational handles.
+
+use super::Handle;
+
+#[derive(Debug)]
+struct Slot<T> {
+    // bumped every time the slot's resource is replaced or removed, so that old handles to it
+    // stop working
+    generation: u32,
+    value: Option<T>,
+}
+
+#[derive(Debug)]
+pub struct SubDictionary<T> {
+    slots: Vec<Slot<T>>,
+    // slots whose resources have been removed, to be reused
+    free: Vec<u32>,
+}
+
+impl<T> SubDictionary<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![],
+            free: vec![],
+        }
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle<T> {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.value = Some(value);
+                Handle::new(index, slot.generation)
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                Handle::new(self.slots.len() as u32 - 1, 0)
+            }
+        }
+    }
+
+    // the slot a handle points to, if the handle is still current
+    #[inline]
+    fn slot(&self, handle: Handle<T>) -> Option<&Slot<T>> {
+        self.slots
+            .get(handle.index() as usize)
+            .filter(|s| s.generation == handle.generation() && s.value.is_some())
+    }
+
+    #[inline]
+    fn slot_mut(&mut self, handle: Handle<T>) -> Option<&mut Slot<T>> {
+        self.slots
+            .get_mut(handle.index() as usize)
+            .filter(|s| s.generation == handle.generation() && s.value.is_some())
+    }
+
+    #[inline]
+    pub fn contains(&self, handle: Handle<T>) -> bool {
+        self.slot(handle).is_some()
+    }
+
+    #[inline]
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        self.slot(handle).and_then(|s| s.value.as_ref())
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        self.slot_mut(handle).and_then(|s| s.value.as_mut())
+    }
+
+    /// Get the current handle to the resource in a slot.
+    #[inline]
+    pub fn handle(&self, index: u32) -> Option<Handle<T>> {
+        self.slots
+            .get(index as usize)
+            .filter(|s| s.value.is_some())
+            .map(|s| Handle::new(index, s.generation))
+    }
+
+    /// Replace a resource, e.g. when it is reloaded. The old handle stops working, and the new
+    /// handle that is returned should be used instead.
+    pub fn replace(&mut self, handle: Handle<T>, value: T) -> Option<(Handle<T>, T)> {
+        let slot = self.slot_mut(handle)?;
+        slot.generation = slot.generation.wrapping_add(1);
+        let old = slot.value.replace(value)?;
+        Some((Handle::new(handle.index(), slot.generation), old))
+    }
+
+    /// Swap a resource for a new version of itself, e.g. when its data file changed. Handles to
+    /// it keep working.
+    pub fn swap(&mut self, handle: Handle<T>, value: T) -> Option<T> {
+        self.slot_mut(handle)?.value.replace(value)
+    }
+
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        let slot = self.slot_mut(handle)?;
+        slot.generation = slot.generation.wrapping_add(1);
+        let old = slot.value.take();
+        self.free.push(handle.index());
+        old
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
+        self.slots.iter().enumerate().filter_map(|(i, s)| {
+            s.value
+                .as_ref()
+                .map(|v| (Handle::new(i as u32, s.generation), v))
+        })
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().filter_map(|s| s.value.as_mut())
+    }
+}
+
+impl<T> Default for SubDictionary<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaced_resources_get_new_handles() {
+        let mut dict = SubDictionary::new();
+        let old = dict.insert("old");
+        let (new, value) = dict.replace(old, "new").unwrap();
+        assert_eq!(value, "old");
+        assert_eq!(new.index(), old.index());
+        assert_ne!(new, old);
+
+        assert!(!dict.contains(old));
+        assert_eq!(dict.get(old), None);
+        assert!(dict.replace(old, "newer").is_none());
+        assert!(dict.remove(old).is_none());
+        assert_eq!(dict.get(new), Some(&"new"));
+        assert_eq!(dict.handle(new.index()), Some(new));
+    }
+
+    #[test]
+    fn swapped_resources_keep_their_handles() {
+        let mut dict = SubDictionary::new();
+        let handle = dict.insert(1);
+        assert_eq!(dict.swap(handle, 2), Some(1));
+        assert_eq!(dict.get(handle), Some(&2));
+    }
+
+    #[test]
+    fn removed_resources_leave_stale_handles() {
+        let mut dict = SubDictionary::new();
+        let a = dict.insert('a');
+        let b = dict.insert('b');
+        assert_eq!(dict.remove(a), Some('a'));
+        assert_eq!(dict.len(), 1);
+
+        assert!(!dict.contains(a));
+        assert_eq!(dict.get_mut(a), None);
+        assert_eq!(dict.handle(a.index()), None);
+        assert!(dict.remove(a).is_none());
+        assert_eq!(dict.len(), 1);
+        assert_eq!(dict.iter().collect::<Vec<_>>(), [(b, &'b')]);
+    }
+
+    #[test]
+    fn reused_slots_get_a_new_generation() {
+        let mut dict = SubDictionary::new();
+        let a = dict.insert('a');
+        dict.remove(a);
+        let c = dict.insert('c');
+
+        // the slot is reused, but the handle to what was in it before doesn't find the new value
+        assert_eq!(c.index(), a.index());
+        assert!(c.generation() > a.generation());
+        assert_eq!(dict.get(a), None);
+        assert_eq!(dict.get(c), Some(&'c'));
+        assert_eq!(dict.len(), 1);
+    }
+}
@@ -0,0 +1,325 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// rewind.rs - Record the game's state every frame, so that time can be rewound.
+
+use crate::{BytecodeObject, ParserState, Rng, Timelines};
+use std::{
+    collections::{HashMap, VecDeque},
+    mem,
+};
+
+// the values some variables had before a frame, or None for variables that didn't exist yet.
+// sorted by variable, so that undoing a frame always happens in the same order
+type Delta = Vec<(u32, Option<BytecodeObject>)>;
+
+// what the game was like before a frame, as far as the frame changed it
+#[derive(Debug)]
+struct Frame {
+    variables: Delta,
+    rng: u64,
+    // only kept for frames that changed the timelines, which is rare
+    timelines: Option<Timelines>,
+}
+
+// what the game was like as of the newest frame
+#[derive(Debug)]
+struct Snapshot {
+    variables: HashMap<u32, BytecodeObject>,
+    rng: u64,
+    timelines: Timelines,
+}
+
+impl Snapshot {
+    fn new(state: &ParserState) -> Self {
+        Self {
+            variables: plain_variables(state),
+            rng: state.rng.state(),
+            timelines: state.timelines.clone(),
+        }
+    }
+}
+
+/// A bounded history of the script variables, the random number generator and the timelines.
+/// Only the variables that changed are stored for each frame, and the oldest frames are dropped
+/// once the buffer is full. Textures and palettes aren't recorded, since scripts can't change
+/// them.
+#[derive(Debug)]
+pub struct RewindBuffer {
+    capacity: usize,
+    // the newest frame is at the back
+    frames: VecDeque<Frame>,
+    // the state as of the newest frame, or None before the first one is recorded
+    last: Option<Snapshot>,
+    // the number of frames that are undone every frame while rewinding
+    speed: Option<u32>,
+}
+
+impl RewindBuffer {
+    /// Create a buffer that holds up to some number of frames.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            frames: VecDeque::new(),
+            last: None,
+            speed: None,
+        }
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the number of frames that are kept, dropping the oldest ones if there are too many.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.frames.len() > capacity {
+            self.frames.pop_front();
+        }
+    }
+
+    /// The number of frames that can be rewound.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Forget every frame, e.g. after loading a save. The next frame that is recorded is the
+    /// furthest back that time can be rewound.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.last = None;
+        self.speed = None;
+    }
+
+    #[inline]
+    pub fn is_rewinding(&self) -> bool {
+        self.speed.is_some()
+    }
+
+    #[inline]
+    pub fn speed(&self) -> Option<u32> {
+        self.speed
+    }
+
+    /// Start rewinding, going back some number of frames every frame. A speed of 0 holds time
+    /// still at the current frame.
+    #[inline]
+    pub fn start(&mut self, speed: u32) {
+        self.speed = Some(speed);
+    }
+
+    /// Stop rewinding. Play resumes from the frame that was reached, and the frames after it are
+    /// gone.
+    #[inline]
+    pub fn stop(&mut self) {
+        self.speed = None;
+    }
+
+    /// Record the state after a frame has run.
+    pub fn record(&mut self, state: &ParserState) {
+        let last = match self.last {
+            Some(ref mut last) => last,
+            None => {
+                self.last = Some(Snapshot::new(state));
+                return;
+            }
+        };
+
+        let mut delta: Delta = vec![];
+        for (id, value) in state.variables.iter().filter(|(_, v)| v.is_plain()) {
+            if last.variables.get(id) != Some(value) {
+                delta.push((*id, last.variables.insert(*id, value.clone())));
+            }
+        }
+        let removed: Vec<u32> = last
+            .variables
+            .keys()
+            .filter(|id| state.variables.get(id).is_none_or(|v| !v.is_plain()))
+            .copied()
+            .collect();
+        for id in removed {
+            delta.push((id, last.variables.remove(&id)));
+        }
+        delta.sort_unstable_by_key(|(id, _)| *id);
+
+        let timelines = if state.timelines.generation() != last.timelines.generation() {
+            Some(mem::replace(&mut last.timelines, state.timelines.clone()))
+        } else {
+            None
+        };
+
+        // a frame where nothing changed is still a frame, so that rewinding takes as long as
+        // playing did
+        self.frames.push_back(Frame {
+            variables: delta,
+            rng: mem::replace(&mut last.rng, state.rng.state()),
+            timelines,
+        });
+        if self.frames.len() > self.capacity {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Undo the newest frame, putting the state back the way it was before it. Returns false if
+    /// there are no frames left.
+    pub fn step_back(&mut self, state: &mut ParserState) -> bool {
+        let (frame, last) = match (self.frames.pop_back(), self.last.as_mut()) {
+            (Some(frame), Some(last)) => (frame, last),
+            _ => return false,
+        };
+
+        for (id, value) in frame.variables {
+            match value {
+                Some(value) => {
+                    state.variables.insert(id, value.clone());
+                    last.variables.insert(id, value);
+                }
+                None => {
+                    state.variables.remove(&id);
+                    last.variables.remove(&id);
+                }
+            }
+        }
+
+        state.rng = Rng::new(frame.rng);
+        last.rng = frame.rng;
+        if let Some(timelines) = frame.timelines {
+            last.timelines = timelines.clone();
+            state.timelines = timelines;
+        }
+        true
+    }
+
+    /// Take the state as it is now to be the newest frame, e.g. after the on_rewind entry point
+    /// changed it while rewinding. Undoing the frames before it doesn't touch those changes,
+    /// unless the frames changed the same variables.
+    pub fn sync(&mut self, state: &ParserState) {
+        if self.last.is_some() {
+            self.last = Some(Snapshot::new(state));
+        }
+    }
+
+    /// Undo as many frames as the rewind speed asks for. Rewinding stops by itself once there are
+    /// no frames left. Returns the number of frames that were undone.
+    pub fn rewind(&mut self, state: &mut ParserState) -> u32 {
+        let speed = match self.speed {
+            Some(speed) => speed,
+            None => return 0,
+        };
+
+        for i in 0..speed {
+            if !self.step_back(state) {
+                self.stop();
+                return i;
+            }
+        }
+        speed
+    }
+}
+
+fn plain_variables(state: &ParserState) -> HashMap<u32, BytecodeObject> {
+    state
+        .variables
+        .iter()
+        .filter(|(_, v)| v.is_plain())
+        .map(|(id, v)| (*id, v.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAIN_TIMELINE;
+
+    fn number(state: &ParserState, id: u32) -> Option<i32> {
+        match state.variables.get(&id) {
+            Some(BytecodeObject::Numeric32(n)) => Some(*n),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn step_back_undoes_changed_added_and_removed_variables() {
+        let mut state = ParserState::new();
+        let mut rewind = RewindBuffer::new(8);
+        state.variables.insert(1, BytecodeObject::Numeric32(1));
+        state.variables.insert(2, BytecodeObject::Numeric32(2));
+        rewind.record(&state);
+
+        state.variables.insert(1, BytecodeObject::Numeric32(10));
+        state.variables.remove(&2);
+        state.variables.insert(3, BytecodeObject::Numeric32(3));
+        rewind.record(&state);
+        state.variables.insert(1, BytecodeObject::Numeric32(100));
+        rewind.record(&state);
+        assert_eq!(rewind.len(), 2);
+
+        assert!(rewind.step_back(&mut state));
+        assert_eq!(number(&state, 1), Some(10));
+        assert!(rewind.step_back(&mut state));
+        assert_eq!(number(&state, 1), Some(1));
+        assert_eq!(number(&state, 2), Some(2));
+        assert_eq!(number(&state, 3), None);
+        assert!(!rewind.step_back(&mut state));
+    }
+
+    #[test]
+    fn step_back_restores_the_rng_and_timelines() {
+        let mut state = ParserState::new();
+        let mut rewind = RewindBuffer::new(8);
+        state.variables.insert(1, BytecodeObject::Numeric32(1));
+        rewind.record(&state);
+        let seed = state.rng.state();
+
+        state.rng.next_u64();
+        let variables = state.variables.clone();
+        state.timelines.fork("other", &variables).unwrap();
+        state.variables.insert(1, BytecodeObject::Numeric32(2));
+        state
+            .timelines
+            .switch("other", &mut state.variables)
+            .unwrap();
+        rewind.record(&state);
+        assert_eq!(state.timelines.current(), "other");
+        assert_eq!(number(&state, 1), Some(1));
+
+        assert!(rewind.step_back(&mut state));
+        assert_eq!(state.rng.state(), seed);
+        assert_eq!(state.timelines.current(), MAIN_TIMELINE);
+        assert!(!state.timelines.exists("other"));
+        assert_eq!(number(&state, 1), Some(1));
+    }
+
+    #[test]
+    fn sync_keeps_what_on_rewind_changed() {
+        let mut state = ParserState::new();
+        let mut rewind = RewindBuffer::new(8);
+        state.variables.insert(1, BytecodeObject::Numeric32(1));
+        rewind.record(&state);
+        state.variables.insert(1, BytecodeObject::Numeric32(2));
+        rewind.record(&state);
+        state.variables.insert(1, BytecodeObject::Numeric32(3));
+        rewind.record(&state);
+
+        assert!(rewind.step_back(&mut state));
+        // what an on_rewind script would do
+        state.variables.insert(4, BytecodeObject::Numeric32(4));
+        rewind.sync(&state);
+
+        assert!(rewind.step_back(&mut state));
+        assert_eq!(number(&state, 1), Some(1));
+        assert_eq!(number(&state, 4), Some(4));
+
+        // recording starts from the synced state too
+        rewind.record(&state);
+        state.variables.remove(&4);
+        rewind.record(&state);
+        assert!(rewind.step_back(&mut state));
+        assert_eq!(number(&state, 4), Some(4));
+    }
+}
@@ -0,0 +1,66 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// rng.rs - Seeded random numbers, so that a game can be replayed exactly.
+
+/// The seed used when none is given.
+pub const DEFAULT_SEED: u64 = 0x4C49_5420_5345_4544;
+
+/// A small random number generator (SplitMix64). The same seed always gives the same numbers, on
+/// every platform.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    #[inline]
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// The state of the generator, which can be passed to `new` to carry on from where it is.
+    #[inline]
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 up to, but not including, a bound.
+    #[inline]
+    pub fn below(&mut self, bound: u32) -> u32 {
+        (((self.next_u64() >> 32) * bound as u64) >> 32) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_seed_gives_the_same_numbers() {
+        let mut a = Rng::new(DEFAULT_SEED);
+        let mut b = Rng::new(DEFAULT_SEED);
+        for _ in 0..16 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+
+        // carrying on from the state gives what the generator would have
+        let mut c = Rng::new(a.state());
+        assert_eq!(c.next_u64(), a.next_u64());
+    }
+
+    #[test]
+    fn below_stays_below_the_bound() {
+        let mut rng = Rng::new(1);
+        for bound in 1..100 {
+            assert!(rng.below(bound) < bound);
+        }
+        assert_eq!(rng.below(0), 0);
+    }
+}
@@ -0,0 +1,372 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// save/mod.rs - Versioned, checksummed save files.
+
+mod slots;
+pub use slots::SaveSlots;
+
+use crate::{BytecodeObject, Limits, LitError, MergeRule, DEFAULT_SEED, MAIN_TIMELINE};
+use lit_lz::crc32;
+use std::{
+    collections::BTreeMap,
+    io::{prelude::*, ErrorKind},
+};
+
+/// Magic bytes at the start of every save file.
+pub const SAVE_MAGIC: [u8; 4] = *b"LSAV";
+
+/// The version of the save format written by `SaveData::write`.
+pub const SAVE_VERSION: u16 = 3;
+
+// upgrades the contents of a save file from one version to the next
+type Migration = fn(&[u8]) -> Result<Vec<u8>, LitError>;
+
+// the migration at index i upgrades version i + 1 to version i + 2. when the format changes,
+// SAVE_VERSION is bumped and a migration from the previous version is added here, so that old
+// saves keep loading
+const MIGRATIONS: [Migration; SAVE_VERSION as usize - 1] = [add_rng_state, add_timelines];
+
+// version 2 keeps the state of the random number generator after when the save was made. older
+// saves get the default seed
+fn add_rng_state(contents: &[u8]) -> Result<Vec<u8>, LitError> {
+    let mut stream = contents;
+    read_string(&mut stream)?;
+    // the game's name and when the save was made come first
+    let at = contents.len() - stream.len() + 8;
+    if at > contents.len() {
+        return Err(LitError::DamagedSave);
+    }
+
+    let mut upgraded = contents[..at].to_vec();
+    upgraded.extend_from_slice(&DEFAULT_SEED.to_be_bytes());
+    upgraded.extend_from_slice(&contents[at..]);
+    Ok(upgraded)
+}
+
+// version 3 keeps the timelines after the variables. older saves were always in the main one
+fn add_timelines(contents: &[u8]) -> Result<Vec<u8>, LitError> {
+    let mut upgraded = contents.to_vec();
+    write_string(&mut upgraded, MAIN_TIMELINE)?;
+    // no other timelines and no merge rules
+    upgraded.extend_from_slice(&0u32.to_be_bytes());
+    upgraded.extend_from_slice(&0u32.to_be_bytes());
+    Ok(upgraded)
+}
+
+/// The state of a game that is kept between sessions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveData {
+    /// The name of the game the save was made in.
+    pub game: String,
+    /// When the save was made, in seconds since the Unix epoch.
+    pub saved_at: u64,
+    /// The state of the random number generator.
+    pub rng_state: u64,
+    /// The values of the named script variables, by name.
+    pub variables: BTreeMap<String, BytecodeObject>,
+    /// The name of the timeline the game was in. Its values are the ones in `variables`.
+    pub timeline: String,
+    /// The named values of every other timeline, by timeline and then by name.
+    pub timelines: BTreeMap<String, BTreeMap<String, BytecodeObject>>,
+    /// How the named variables that have a rule are merged between timelines.
+    pub merge_rules: BTreeMap<String, MergeRule>,
+}
+
+impl SaveData {
+    pub fn new(game: String, saved_at: u64) -> Self {
+        Self {
+            game,
+            saved_at,
+            rng_state: DEFAULT_SEED,
+            variables: BTreeMap::new(),
+            timeline: String::from(MAIN_TIMELINE),
+            timelines: BTreeMap::new(),
+            merge_rules: BTreeMap::new(),
+        }
+    }
+
+    /// Read a save file, upgrading it if it was written by an older version of the game.
+    pub fn read<T: Read>(stream: &mut T) -> Result<Self, LitError> {
+        let mut magic = [0; 4];
+        read_header(stream, &mut magic)?;
+        if magic != SAVE_MAGIC {
+            return Err(LitError::NotASave);
+        }
+
+        let mut buffer = [0; 2];
+        read_header(stream, &mut buffer)?;
+        let version = u16::from_be_bytes(buffer);
+        if version == 0 || version > SAVE_VERSION {
+            return Err(LitError::UnsupportedSaveVersion(version, SAVE_VERSION));
+        }
+
+        let mut buffer = [0; 4];
+        read_header(stream, &mut buffer)?;
+        let length = u32::from_be_bytes(buffer);
+        read_header(stream, &mut buffer)?;
+        let checksum = u32::from_be_bytes(buffer);
+
+        // the length isn't trusted until the checksum matches, so the contents are read as they
+        // come instead of allocating all of it up front
+        let mut contents = vec![];
+        stream
+            .by_ref()
+            .take(length as u64)
+            .read_to_end(&mut contents)?;
+        if contents.len() != length as usize || crc32(&contents) != checksum {
+            return Err(LitError::DamagedSave);
+        }
+
+        for migration in &MIGRATIONS[version as usize - 1..] {
+            contents = migration(&contents)?;
+        }
+        Self::read_contents(&mut &contents[..])
+    }
+
+    fn read_contents(stream: &mut &[u8]) -> Result<Self, LitError> {
+        let game = read_string(stream)?;
+        let mut buffer = [0; 8];
+        stream.read_exact(&mut buffer)?;
+        let saved_at = u64::from_be_bytes(buffer);
+
+        let mut data = Self::new(game, saved_at);
+        stream.read_exact(&mut buffer)?;
+        data.rng_state = u64::from_be_bytes(buffer);
+
+        let limits = Limits::new();
+        data.variables = read_values(stream, &limits)?;
+
+        data.timeline = read_string(stream)?;
+        for _ in 0..read_count(stream)? {
+            let timeline = read_string(stream)?;
+            let values = read_values(stream, &limits)?;
+            data.timelines.insert(timeline, values);
+        }
+        for _ in 0..read_count(stream)? {
+            let name = read_string(stream)?;
+            let mut rule = [0; 1];
+            stream.read_exact(&mut rule)?;
+            data.merge_rules.insert(name, MergeRule::from_u8(rule[0])?);
+        }
+
+        // anything after the timelines means the file isn't what it claims to be
+        if !stream.is_empty() {
+            return Err(LitError::DamagedSave);
+        }
+        Ok(data)
+    }
+
+    /// Write a save file in the current version of the format.
+    pub fn write<T: Write>(&self, stream: &mut T) -> Result<(), LitError> {
+        let mut contents = vec![];
+        write_string(&mut contents, &self.game)?;
+        contents.extend_from_slice(&self.saved_at.to_be_bytes());
+        contents.extend_from_slice(&self.rng_state.to_be_bytes());
+        write_values(&mut contents, &self.variables)?;
+
+        write_string(&mut contents, &self.timeline)?;
+        contents.extend_from_slice(&(self.timelines.len() as u32).to_be_bytes());
+        for (timeline, values) in &self.timelines {
+            write_string(&mut contents, timeline)?;
+            write_values(&mut contents, values)?;
+        }
+        contents.extend_from_slice(&(self.merge_rules.len() as u32).to_be_bytes());
+        for (name, rule) in &self.merge_rules {
+            write_string(&mut contents, name)?;
+            contents.push(*rule as u8);
+        }
+
+        stream.write_all(&SAVE_MAGIC)?;
+        stream.write_all(&SAVE_VERSION.to_be_bytes())?;
+        stream.write_all(&(contents.len() as u32).to_be_bytes())?;
+        stream.write_all(&crc32(&contents).to_be_bytes())?;
+        stream.write_all(&contents)?;
+        Ok(())
+    }
+}
+
+// a save file that ends in the middle of its header was cut short, e.g. by a full disk
+fn read_header<T: Read>(stream: &mut T, buffer: &mut [u8]) -> Result<(), LitError> {
+    stream.read_exact(buffer).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => LitError::DamagedSave,
+        _ => e.into(),
+    })
+}
+
+#[inline]
+fn read_count(stream: &mut &[u8]) -> Result<u32, LitError> {
+    let mut buffer = [0; 4];
+    stream.read_exact(&mut buffer)?;
+    Ok(u32::from_be_bytes(buffer))
+}
+
+// values are a count followed by each name and value
+fn read_values(
+    stream: &mut &[u8],
+    limits: &Limits,
+) -> Result<BTreeMap<String, BytecodeObject>, LitError> {
+    let mut values = BTreeMap::new();
+    for _ in 0..read_count(stream)? {
+        let name = read_string(stream)?;
+        let value = BytecodeObject::read_limited(stream, limits)?;
+        if !value.is_plain() {
+            return Err(LitError::NotPlainValue);
+        }
+        values.insert(name, value);
+    }
+    Ok(values)
+}
+
+fn write_values(
+    contents: &mut Vec<u8>,
+    values: &BTreeMap<String, BytecodeObject>,
+) -> Result<(), LitError> {
+    contents.extend_from_slice(&(values.len() as u32).to_be_bytes());
+    for (name, value) in values {
+        write_string(contents, name)?;
+        value.write(contents)?;
+    }
+    Ok(())
+}
+
+// strings in save files are prefixed by their 16-bit length
+fn read_string(stream: &mut &[u8]) -> Result<String, LitError> {
+    let mut buffer = [0; 2];
+    stream.read_exact(&mut buffer)?;
+    let mut string = vec![0; u16::from_be_bytes(buffer) as usize];
+    stream.read_exact(&mut string)?;
+    Ok(String::from_utf8(string)?)
+}
+
+fn write_string(contents: &mut Vec<u8>, string: &str) -> Result<(), LitError> {
+    if string.len() > u16::MAX as usize {
+        return Err(LitError::StringTooLong(string.len(), u16::MAX as usize));
+    }
+
+    contents.extend_from_slice(&(string.len() as u16).to_be_bytes());
+    contents.extend_from_slice(string.as_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save() -> SaveData {
+        let mut save = SaveData::new(String::from("Lost in Time"), 1_600_000_000);
+        save.rng_state = 0x1234_5678_9ABC_DEF0;
+        save.variables
+            .insert(String::from("HEALTH"), BytecodeObject::Numeric8(3));
+        save.variables.insert(
+            String::from("ROOM"),
+            BytecodeObject::Str(String::from("cellar")),
+        );
+        save.variables.insert(
+            String::from("POSITION"),
+            BytecodeObject::Tuple(vec![
+                BytecodeObject::Numeric16(-20),
+                BytecodeObject::Numeric32(70_000),
+            ]),
+        );
+        save.timeline = String::from("past");
+        let mut main = BTreeMap::new();
+        main.insert(String::from("HEALTH"), BytecodeObject::Numeric8(5));
+        save.timelines.insert(String::from(MAIN_TIMELINE), main);
+        save.merge_rules
+            .insert(String::from("HEALTH"), MergeRule::Max);
+        save
+    }
+
+    // a save file in an older version, whose contents were written by hand
+    fn file(version: u16, contents: &[u8]) -> Vec<u8> {
+        let mut bytes = SAVE_MAGIC.to_vec();
+        bytes.extend_from_slice(&version.to_be_bytes());
+        bytes.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&crc32(contents).to_be_bytes());
+        bytes.extend_from_slice(contents);
+        bytes
+    }
+
+    fn written(save: &SaveData) -> Vec<u8> {
+        let mut bytes = vec![];
+        save.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let save = save();
+        let bytes = written(&save);
+        assert_eq!(SaveData::read(&mut &bytes[..]).unwrap(), save);
+    }
+
+    #[test]
+    fn checksum_mismatch_is_damage() {
+        let mut bytes = written(&save());
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(matches!(
+            SaveData::read(&mut &bytes[..]),
+            Err(LitError::DamagedSave)
+        ));
+    }
+
+    #[test]
+    fn truncated_saves_are_damaged() {
+        let bytes = written(&save());
+        for len in 0..bytes.len() {
+            assert!(
+                matches!(
+                    SaveData::read(&mut &bytes[..len]),
+                    Err(LitError::DamagedSave)
+                ),
+                "{} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn version_1_saves_get_the_default_seed() {
+        // version 1 had no random number generator state after when the save was made
+        let save = save();
+        let mut contents = vec![];
+        write_string(&mut contents, &save.game).unwrap();
+        contents.extend_from_slice(&save.saved_at.to_be_bytes());
+        write_values(&mut contents, &save.variables).unwrap();
+
+        let upgraded = SaveData::read(&mut &file(1, &contents)[..]).unwrap();
+        assert_eq!(upgraded.rng_state, DEFAULT_SEED);
+        assert_eq!(upgraded.game, save.game);
+        assert_eq!(upgraded.saved_at, save.saved_at);
+        assert_eq!(upgraded.variables, save.variables);
+        assert_eq!(upgraded.timeline, MAIN_TIMELINE);
+    }
+
+    #[test]
+    fn version_2_saves_are_in_the_main_timeline() {
+        // version 2 had no timelines after the variables
+        let save = save();
+        let mut contents = vec![];
+        write_string(&mut contents, &save.game).unwrap();
+        contents.extend_from_slice(&save.saved_at.to_be_bytes());
+        contents.extend_from_slice(&save.rng_state.to_be_bytes());
+        write_values(&mut contents, &save.variables).unwrap();
+
+        let upgraded = SaveData::read(&mut &file(2, &contents)[..]).unwrap();
+        assert_eq!(upgraded.rng_state, save.rng_state);
+        assert_eq!(upgraded.variables, save.variables);
+        assert_eq!(upgraded.timeline, MAIN_TIMELINE);
+        assert!(upgraded.timelines.is_empty());
+        assert!(upgraded.merge_rules.is_empty());
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let mut bytes = written(&save());
+        bytes[4..6].copy_from_slice(&(SAVE_VERSION + 1).to_be_bytes());
+        assert!(matches!(
+            SaveData::read(&mut &bytes[..]),
+            Err(LitError::UnsupportedSaveVersion(_, SAVE_VERSION))
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// save/slots.rs - Numbered save files in a directory.
+
+use super::SaveData;
+use crate::LitError;
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+const SAVE_EXTENSION: &str = "litsave";
+
+/// A directory of save files, one for each numbered slot.
+#[derive(Debug, Clone)]
+pub struct SaveSlots {
+    dir: PathBuf,
+}
+
+impl SaveSlots {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    #[inline]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file a slot is saved to.
+    #[inline]
+    pub fn path(&self, slot: u32) -> PathBuf {
+        self.dir.join(format!("slot{}.{}", slot, SAVE_EXTENSION))
+    }
+
+    /// Save to a slot, replacing whatever was saved there. The old save is only replaced once the
+    /// new one has been written in full, so a crash while saving doesn't lose both.
+    pub fn save(&self, slot: u32, data: &SaveData) -> Result<(), LitError> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(slot);
+        let partial = path.with_extension(format!("{}.partial", SAVE_EXTENSION));
+
+        let mut stream = BufWriter::new(File::create(&partial)?);
+        data.write(&mut stream)?;
+        stream.flush()?;
+        stream.get_ref().sync_all()?;
+        drop(stream);
+
+        fs::rename(&partial, &path)?;
+        Ok(())
+    }
+
+    /// Load the save in a slot. Returns None if nothing was saved there.
+    pub fn load(&self, slot: u32) -> Result<Option<SaveData>, LitError> {
+        let file = match File::open(self.path(slot)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        SaveData::read(&mut BufReader::new(file)).map(Some)
+    }
+
+    /// Delete the save in a slot, if there is one.
+    pub fn delete(&self, slot: u32) -> Result<(), LitError> {
+        match fs::remove_file(self.path(slot)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Every slot that has something saved in it, in order.
+    pub fn list(&self) -> Result<Vec<u32>, LitError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut slots = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SAVE_EXTENSION) {
+                continue;
+            }
+            if let Some(slot) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.strip_prefix("slot"))
+                .and_then(|s| s.parse().ok())
+            {
+                slots.push(slot);
+            }
+        }
+
+        slots.sort_unstable();
+        Ok(slots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn save_load_list_and_delete() {
+        let dir = env::temp_dir().join(format!("lit-save-slots-{}", process::id()));
+        let slots = SaveSlots::new(&dir);
+        assert_eq!(slots.list().unwrap(), Vec::<u32>::new());
+        assert!(slots.load(1).unwrap().is_none());
+
+        let save = SaveData::new(String::from("Lost in Time"), 42);
+        slots.save(3, &save).unwrap();
+        slots.save(1, &save).unwrap();
+        assert_eq!(slots.list().unwrap(), vec![1, 3]);
+        assert_eq!(slots.load(3).unwrap(), Some(save));
+
+        slots.delete(3).unwrap();
+        slots.delete(3).unwrap();
+        assert_eq!(slots.list().unwrap(), vec![1]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,10 +1,90 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // script/eval.rs - Evaluate a bytecode statement
 
-use super::{Bytecode, BytecodeObject, GameData, ParserState};
-use crate::{ImgMaterial, LitError};
+use super::{BytecodeObject, DataType, GameData, ParserState, Script};
+use crate::{
+    draw::{Composite, DrawBuffer, DrawHandle, IndexedBuffer},
+    logging::{self, Level, Target},
+    Color, LitError, PaletteCycle,
+};
+use lit_fmt::{Argument, FormatError, Piece, Value};
 use std::{convert::TryInto, io::prelude::*};
 
+// read an object, translating its variable ids into global ones
+#[inline]
+fn read_object<T: Read>(
+    stream: &mut T,
+    state: &mut ParserState,
+) -> Result<BytecodeObject, LitError> {
+    let object = BytecodeObject::read_limited(stream, &state.limits)?;
+    Ok(state.globalize(object))
+}
+
+/// Format a log message. Arguments are taken from the tuple by their position, or from variables
+/// by their name.
+pub fn format_log(
+    format: &str,
+    tuple: &[BytecodeObject],
+    state: &ParserState,
+) -> Result<String, LitError> {
+    // unused arguments are only reported by the compiler, as the tuple may come from a variable
+    let pieces = lit_fmt::parse(format)?;
+
+    let mut formatted_str = String::new();
+    for piece in pieces {
+        let (object, spec) = match piece {
+            Piece::Literal(text) => {
+                formatted_str.push_str(text);
+                continue;
+            }
+            Piece::Argument {
+                arg: Argument::Index(i),
+                spec,
+            } => match tuple.get(i) {
+                Some(object) => (object, spec),
+                None => return Err(FormatError::MissingArgument(i, tuple.len()).into()),
+            },
+            Piece::Argument {
+                arg: Argument::Name(name),
+                spec,
+            } => match state.names.get(name) {
+                Some(id) => (state.get_variable(*id)?, spec),
+                None => return Err(LitError::UnknownVariableName(name.to_string())),
+            },
+        };
+
+        // numbers can be written in other bases, everything else is written as a string
+        match object.as_number(state) {
+            Ok(n) => lit_fmt::write_value(&mut formatted_str, Value::Number(n), &spec)?,
+            Err(_) => {
+                let s = object.stringify(state)?;
+                lit_fmt::write_value(&mut formatted_str, Value::Str(&s), &spec)?
+            }
+        }
+    }
+
+    Ok(formatted_str)
+}
+
+// draw commands take a color id, followed by a color if the id isn't registered. indexed textures
+// keep the id, so the color becomes part of their palette
+fn draw_color<T: Read>(
+    stream: &mut T,
+    state: &mut ParserState,
+    draw_id: u32,
+    clr_id: u8,
+) -> Result<Color, LitError> {
+    if let Ok(c) = state.get_color(draw_id, clr_id) {
+        return Ok(*c);
+    }
+
+    let color = read_object(stream, state)?.as_color(state)?;
+    if state.is_indexed(draw_id) {
+        state.register_color_id(draw_id, clr_id, color);
+    }
+    Ok(color)
+}
+
 pub fn eval<T: Read>(
     stream: &mut T,
     data: &mut GameData,
@@ -21,7 +101,7 @@ pub fn eval<T: Read>(
         1 => {
             // gamedef statement, define the game's name
             data.set_name(String::from(
-                BytecodeObject::read(stream)?.as_string(state)?,
+                read_object(stream, state)?.as_string(state)?,
             ));
             Ok(true)
         }
@@ -29,30 +109,22 @@ pub fn eval<T: Read>(
             // def statement, define a runtime variable
             let mut buffer = [0; 4];
             stream.read_exact(&mut buffer)?;
-            let id = u32::from_be_bytes(buffer);
-            state.register_variable(id, BytecodeObject::read(stream)?);
+            let id = state.global_id(u32::from_be_bytes(buffer));
+            let object = read_object(stream, state)?;
+            state.assign(id, object)?;
             Ok(true)
         }
-        3 => {
+        3 | 10 | 11 => {
             // log statement, output something to the debug log
-            // home grown format statement, could be improved
-            let format = BytecodeObject::read(stream)?;
-            let tuple = BytecodeObject::read(stream)?;
-            let tuple = tuple.as_tuple(state)?;
-            let formatted_str = format
-                .as_string(state)?
-                .split("{}")
-                .enumerate()
-                .map(|(i, part)| {
-                    if i != tuple.len() {
-                        format!("{}{}", part, tuple[i].stringify(state).unwrap())
-                    } else {
-                        String::from(part)
-                    }
-                })
-                .collect::<Vec<String>>()
-                .join("");
-            println!("{}", formatted_str);
+            let level = match res {
+                3 => Level::Info,
+                10 => Level::Warn,
+                _ => Level::Error,
+            };
+            let format = read_object(stream, state)?;
+            let tuple = read_object(stream, state)?;
+            let message = format_log(format.as_string(state)?, tuple.as_tuple(state)?, state)?;
+            logging::log(level, Target::Script, format_args!("{}", message));
 
             Ok(true)
         }
@@ -60,48 +132,52 @@ pub fn eval<T: Read>(
             // create a new texture material
             let mut buffer = [0; 4];
             stream.read_exact(&mut buffer)?;
-            let id = u32::from_be_bytes(buffer);
+            let id = state.global_id(u32::from_be_bytes(buffer));
 
-            let width = BytecodeObject::read(stream)?;
+            let width = read_object(stream, state)?;
             let width = width.as_number(state)?.try_into()?;
 
-            let height = BytecodeObject::read(stream)?;
+            let height = read_object(stream, state)?;
             let height = height.as_number(state)?.try_into()?;
 
-            let bg_color = BytecodeObject::read(stream)?.as_color(state)?;
+            let bg_color = read_object(stream, state)?.as_color(state)?;
+
+            // check the size before anything is allocated for the texture
+            state.reserve_texture(id, width, height)?;
+            let mat = DrawBuffer::new(width, height, bg_color);
+            state.register_variable(id, BytecodeObject::DrawBufferImg(mat))?;
 
-            let mat = ImgMaterial::new(width, height, bg_color);
-            state.register_variable(id, BytecodeObject::ImgMaterial(mat));
-            state.img_material_ids.push(id);
+            // a texture may be redefined by a later layer, but should only be built once
+            state.add_material(id, DataType::DrawBufferImg);
 
             Ok(true)
         }
         5 => {
             // assign a color id to an invocation
-            let buf_id = BytecodeObject::read(stream)?;
-            let buf_id = buf_id.get_var_id(state)?;
+            let buf_id = read_object(stream, state)?;
+            let (buf_id, _) = state.material_id(&buf_id)?;
 
-            let clr_id = BytecodeObject::read(stream)?.as_number(state)?.try_into()?;
+            let clr_id = read_object(stream, state)?.as_number(state)?.try_into()?;
 
-            let color = BytecodeObject::read(stream)?.as_color(state)?;
+            let color = read_object(stream, state)?.as_color(state)?;
 
             state.register_color_id(buf_id, clr_id, color);
             Ok(true)
         }
         6 => {
             // draw a single pixel
-            let mut draw_buffer = BytecodeObject::read(stream)?;
-            let draw_id = draw_buffer.get_var_id(state)?;
-
-            let x = BytecodeObject::read(stream)?.as_number(state)?.try_into()?;
-            let y = BytecodeObject::read(stream)?.as_number(state)?.try_into()?;
-            let color = match state.get_color(
-                draw_id,
-                BytecodeObject::read(stream)?.as_number(state)?.try_into()?,
-            ) {
-                Ok(c) => *c,
-                Err(_e) => BytecodeObject::read(stream)?.as_color(state)?,
-            };
+            let mut draw_buffer = read_object(stream, state)?;
+            let (draw_id, _) = state.material_id(&draw_buffer)?;
+
+            let x = read_object(stream, state)?.as_number(state)?.try_into()?;
+            let y = read_object(stream, state)?.as_number(state)?.try_into()?;
+            let clr_id = read_object(stream, state)?.as_number(state)?.try_into()?;
+            let color = draw_color(stream, state, draw_id, clr_id)?;
+
+            if let BytecodeObject::IndexedImg(ref mut indexed) = state.get_variable_mut(draw_id)? {
+                indexed.draw_pixel(x, y, clr_id);
+                return Ok(true);
+            }
 
             let draw_handle = draw_buffer.as_draw_handle_mut(state)?;
 
@@ -110,28 +186,145 @@ pub fn eval<T: Read>(
         }
         7 => {
             // draw a rectangle
-            let mut draw_buffer = BytecodeObject::read(stream)?;
-            let draw_id = draw_buffer.get_var_id(state)?;
-
-            let x = BytecodeObject::read(stream)?.as_number(state)?.try_into()?;
-            let y = BytecodeObject::read(stream)?.as_number(state)?.try_into()?;
-            let width = BytecodeObject::read(stream)?.as_number(state)?.try_into()?;
-            let height = BytecodeObject::read(stream)?.as_number(state)?.try_into()?;
-
-            let color = match state.get_color(
-                draw_id,
-                BytecodeObject::read(stream)?.as_number(state)?.try_into()?,
-            ) {
-                Ok(c) => *c,
-                Err(_e) => BytecodeObject::read(stream)?.as_color(state)?,
-            };
+            let mut draw_buffer = read_object(stream, state)?;
+            let (draw_id, _) = state.material_id(&draw_buffer)?;
+
+            let x = read_object(stream, state)?.as_number(state)?.try_into()?;
+            let y = read_object(stream, state)?.as_number(state)?.try_into()?;
+            let width = read_object(stream, state)?.as_number(state)?.try_into()?;
+            let height = read_object(stream, state)?.as_number(state)?.try_into()?;
+
+            let clr_id = read_object(stream, state)?.as_number(state)?.try_into()?;
+            let color = draw_color(stream, state, draw_id, clr_id)?;
+
+            if let BytecodeObject::IndexedImg(ref mut indexed) = state.get_variable_mut(draw_id)? {
+                indexed.draw_rectangle(x, y, width, height, clr_id);
+                return Ok(true);
+            }
 
             let draw_handle = draw_buffer.as_draw_handle_mut(state)?;
 
             draw_handle.draw_rectangle(x, y, width, height, color)?;
             Ok(true)
         }
+        8 => {
+            // name a variable, so that later layers can override it
+            let mut buffer = [0; 4];
+            stream.read_exact(&mut buffer)?;
+            let id = u32::from_be_bytes(buffer);
+
+            let name = String::from(read_object(stream, state)?.as_string(state)?);
+            state.name_variable(id, name);
+            Ok(true)
+        }
+        9 => {
+            // define an entry point that is run by the VM after loading
+            let name = String::from(read_object(stream, state)?.as_string(state)?);
+            let script = Script::load(stream, state)?;
+            data.add_script(name, script);
+            Ok(true)
+        }
+        12 | 13 => {
+            // copy one texture onto another
+            let dst = read_object(stream, state)?;
+            let dst = state.texture_id(&dst)?;
+            let source = read_object(stream, state)?;
+            let source = state.texture_id(&source)?;
+
+            let x = read_object(stream, state)?.as_number(state)?.try_into()?;
+            let y = read_object(stream, state)?.as_number(state)?.try_into()?;
+
+            let op = if res == 12 {
+                Composite::Blit { source, x, y }
+            } else {
+                Composite::Overlay { source, x, y }
+            };
+            state.composite(dst, op)?;
+            Ok(true)
+        }
+        14 => {
+            // multiply the colors of a texture
+            let tex = read_object(stream, state)?;
+            let tex = state.texture_id(&tex)?;
+            let color = read_object(stream, state)?.as_color(state)?;
+            state.composite(tex, Composite::Tint(color))?;
+            Ok(true)
+        }
+        15..=17 => {
+            // flip or turn a texture
+            let tex = read_object(stream, state)?;
+            let tex = state.texture_id(&tex)?;
+            let op = match res {
+                15 => Composite::FlipHorizontal,
+                16 => Composite::FlipVertical,
+                _ => Composite::Rotate90,
+            };
+            state.composite(tex, op)?;
+            Ok(true)
+        }
+        18 => {
+            // create a new texture that keeps the color id of every pixel
+            let mut buffer = [0; 4];
+            stream.read_exact(&mut buffer)?;
+            let id = state.global_id(u32::from_be_bytes(buffer));
+
+            let width = read_object(stream, state)?.as_number(state)?.try_into()?;
+            let height = read_object(stream, state)?.as_number(state)?.try_into()?;
+            let background = read_object(stream, state)?.as_number(state)?.try_into()?;
+
+            state.reserve_texture(id, width, height)?;
+            let indexed = IndexedBuffer::new(width, height, background);
+            state.register_variable(id, BytecodeObject::IndexedImg(indexed))?;
+
+            state.add_material(id, DataType::IndexedImg);
+            Ok(true)
+        }
+        19 => {
+            // create a palette, whose colors are given with color ids
+            let mut buffer = [0; 4];
+            stream.read_exact(&mut buffer)?;
+            let id = state.global_id(u32::from_be_bytes(buffer));
+
+            state.register_variable(id, BytecodeObject::Palette)?;
+            state.add_material(id, DataType::Palette);
+            Ok(true)
+        }
+        20 => {
+            // rotate a range of the palette of an indexed texture while the game runs
+            let tex = read_object(stream, state)?;
+            let tex = match state.material_id(&tex)? {
+                (id, DataType::IndexedImg) => id,
+                (_, kind) => return Err(LitError::IncorrectDataType(kind, DataType::IndexedImg)),
+            };
+
+            let start: u8 = read_object(stream, state)?.as_number(state)?.try_into()?;
+            let end: u8 = read_object(stream, state)?.as_number(state)?.try_into()?;
+            let speed: u16 = read_object(stream, state)?.as_number(state)?.try_into()?;
+
+            // a range that is given backwards rotates the other way
+            state.add_palette_cycle(tex, PaletteCycle::new(start, end, speed as i32));
+            Ok(true)
+        }
         0 => Ok(false),
         _ => Err(LitError::BytecodeRead16(res)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_log_allows_unused_arguments() {
+        let state = ParserState::new();
+        let tuple = [
+            BytecodeObject::Numeric16(-5),
+            BytecodeObject::Str("unused".to_string()),
+        ];
+        assert_eq!(format_log("{:>3}", &tuple, &state).unwrap(), " -5");
+        assert!(matches!(
+            format_log("{2}", &tuple, &state),
+            Err(LitError::Format(FormatError::MissingArgument(2, 2)))
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// script/graph.rs - Order materials so that each one is built after the materials it depends on.
+
+use super::{Dependancy, ParserState};
+use crate::LitError;
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Mark {
+    // the material is on the path being walked
+    Visiting,
+    // the material and everything it depends on has been ordered
+    Done,
+}
+
+impl ParserState {
+    /// The name of a variable for error messages, or its id if it doesn't have one.
+    pub fn variable_name(&self, id: u32) -> String {
+        self.names
+            .iter()
+            .find(|(_, i)| **i == id)
+            .map_or_else(|| format!("#{}", id), |(name, _)| name.clone())
+    }
+
+    /// Sort a set of materials and everything they depend on, so that dependencies come before
+    /// the materials that use them. Each material appears once, no matter how many materials
+    /// depend on it.
+    pub fn material_order(&self, roots: &[Dependancy]) -> Result<Vec<Dependancy>, LitError> {
+        let mut marks: HashMap<u32, Mark> = HashMap::new();
+        let mut order = vec![];
+
+        // the walk keeps its own stack, so that long chains of dependencies can't overflow the
+        // real one. each entry is a material and the index of the next dependency to look at
+        let mut stack: Vec<(Dependancy, usize)> = vec![];
+
+        for root in roots {
+            if marks.contains_key(&root.id) {
+                continue;
+            }
+            marks.insert(root.id, Mark::Visiting);
+            stack.push((*root, 0));
+
+            while let Some((node, next)) = stack.last().copied() {
+                let dep = self
+                    .dependency_relations
+                    .get(&node.id)
+                    .and_then(|deps| deps.get(next))
+                    .copied();
+
+                let dep = match dep {
+                    Some(d) => d,
+                    None => {
+                        // every dependency has been ordered
+                        marks.insert(node.id, Mark::Done);
+                        order.push(node);
+                        stack.pop();
+                        continue;
+                    }
+                };
+
+                if let Some(top) = stack.last_mut() {
+                    top.1 += 1;
+                }
+
+                match marks.get(&dep.id) {
+                    Some(Mark::Done) => {}
+                    Some(Mark::Visiting) => {
+                        // the dependency is already on the path, so the path from it back to
+                        // itself is a cycle
+                        let start = stack.iter().position(|(n, _)| n.id == dep.id).unwrap_or(0);
+                        let mut names: Vec<String> = stack[start..]
+                            .iter()
+                            .map(|(n, _)| self.variable_name(n.id))
+                            .collect();
+                        names.push(self.variable_name(dep.id));
+                        return Err(LitError::DependencyCycle(names));
+                    }
+                    None => {
+                        marks.insert(dep.id, Mark::Visiting);
+                        stack.push((dep, 0));
+                    }
+                }
+            }
+        }
+
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MaterialType;
+
+    fn state(edges: &[(u32, u32)]) -> ParserState {
+        let mut state = ParserState::new();
+        for &(from, to) in edges {
+            state.add_dependency(from, material(to));
+        }
+        state
+    }
+
+    fn material(id: u32) -> Dependancy {
+        Dependancy {
+            kind: MaterialType::ImgMaterial,
+            id,
+        }
+    }
+
+    fn order(state: &ParserState, roots: &[u32]) -> Result<Vec<u32>, LitError> {
+        let roots: Vec<_> = roots.iter().map(|id| material(*id)).collect();
+        let order = state.material_order(&roots)?;
+        Ok(order.iter().map(|d| d.id).collect())
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        let state = state(&[(1, 2), (2, 3)]);
+        assert_eq!(order(&state, &[1]).unwrap(), [3, 2, 1]);
+        assert_eq!(order(&state, &[3, 1]).unwrap(), [3, 2, 1]);
+    }
+
+    #[test]
+    fn shared_dependencies_appear_once() {
+        // 1 uses 2 and 3, which both use 4
+        let state = state(&[(1, 2), (1, 3), (2, 4), (3, 4)]);
+        assert_eq!(order(&state, &[1]).unwrap(), [4, 2, 3, 1]);
+        assert_eq!(order(&state, &[2, 3, 1]).unwrap(), [4, 2, 3, 1]);
+    }
+
+    #[test]
+    fn cycles_are_named() {
+        let mut state = state(&[(1, 2), (2, 3), (3, 1), (4, 4)]);
+        for (name, id) in [("A", 1), ("B", 2), ("C", 3)].iter() {
+            state.names.insert(name.to_string(), *id);
+        }
+
+        match order(&state, &[1]) {
+            Err(LitError::DependencyCycle(names)) => assert_eq!(names, ["A", "B", "C", "A"]),
+            r => panic!("expected a cycle, got {:?}", r),
+        }
+        // the path into a cycle isn't part of it, and unnamed materials go by their id
+        state.add_dependency(5, material(4));
+        match order(&state, &[5]) {
+            Err(LitError::DependencyCycle(names)) => assert_eq!(names, ["#4", "#4"]),
+            r => panic!("expected a cycle, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn long_chains_fit_on_the_stack() {
+        let edges: Vec<_> = (1..100_000).map(|id| (id, id + 1)).collect();
+        let order = order(&state(&edges), &[1]).unwrap();
+        assert_eq!(order.len(), 100_000);
+        assert_eq!((order[0], order[99_999]), (100_000, 1));
+    }
+}
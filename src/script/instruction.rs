@@ -0,0 +1,267 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// script/instruction.rs - Instructions run by the script VM.
+
+use super::{Bytecode, BytecodeObject, Limits, MergeRule, ParserState};
+use crate::{logging::Level, LitError};
+use std::{
+    collections::HashMap,
+    io::{self, prelude::*, Cursor},
+};
+
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    // stop running the script
+    Return,
+    // push a constant onto the stack
+    Push(BytecodeObject),
+    // push the value of a variable onto the stack
+    Load(u32),
+    // pop a value off of the stack into a variable
+    Store(u32),
+    // arithmetic, pops two numbers and pushes the result
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    // comparisons, pops two values and pushes 1 or 0
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    // pops a value and pushes 1 if it is zero, 0 otherwise
+    Not,
+    // jump to another instruction
+    Jump(usize),
+    // pop a value and jump to another instruction if it is zero
+    JumpIfZero(usize),
+    // pop a tuple and a format string, and output them to the debug log at a level
+    Log(Level),
+    // pop a number and start rewinding time by that many frames every frame
+    Rewind,
+    // stop rewinding time
+    RewindStop,
+    // pop a timeline name, and start a timeline that branches off from the current one
+    TimelineFork,
+    // pop a timeline name, and switch to that timeline
+    TimelineSwitch,
+    // pop a timeline name, and merge that timeline into the current one
+    TimelineMerge,
+    // pop a timeline name, and push the value of a variable in that timeline
+    TimelineGet(u32),
+    // pop a timeline name, and push the number of variables that differ from the current timeline
+    TimelineDiff,
+    // set how a variable is merged
+    MergeRule(u32, MergeRule),
+    // pop a timeline name, and delete that timeline
+    TimelineDelete,
+    // push the name of the current timeline
+    TimelineCurrent,
+    // pop a number above zero, and push a random number from 0 up to it
+    Random,
+    // pop a keycode, and push 1 if the key is held down, 0 otherwise
+    KeyHeld,
+}
+
+impl Instruction {
+    #[inline]
+    fn read_target<T: Read>(stream: &mut T) -> Result<usize, LitError> {
+        let mut buffer = [0; 4];
+        stream.read_exact(&mut buffer)?;
+        Ok(u32::from_be_bytes(buffer) as usize)
+    }
+
+    #[inline]
+    pub fn jump_target(&self) -> Option<usize> {
+        match *self {
+            Instruction::Jump(t) | Instruction::JumpIfZero(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    /// Read an instruction, making sure any constant stays within a set of limits.
+    // note: jump targets are read as byte offsets, and need to be converted to instruction indices
+    pub fn read_limited<T: Read>(stream: &mut T, limits: &Limits) -> Result<Self, LitError> {
+        let mut buffer = [0; 1];
+        stream.read_exact(&mut buffer)?;
+
+        match buffer[0] {
+            0x00 => Ok(Instruction::Return),
+            0x01 => Ok(Instruction::Push(BytecodeObject::read_limited(
+                stream, limits,
+            )?)),
+            0x02 | 0x03 => {
+                let mut id = [0; 4];
+                stream.read_exact(&mut id)?;
+                let id = u32::from_be_bytes(id);
+                Ok(if buffer[0] == 0x02 {
+                    Instruction::Load(id)
+                } else {
+                    Instruction::Store(id)
+                })
+            }
+            0x10 => Ok(Instruction::Add),
+            0x11 => Ok(Instruction::Sub),
+            0x12 => Ok(Instruction::Mul),
+            0x13 => Ok(Instruction::Div),
+            0x14 => Ok(Instruction::Mod),
+            0x20 => Ok(Instruction::Eq),
+            0x21 => Ok(Instruction::Ne),
+            0x22 => Ok(Instruction::Lt),
+            0x23 => Ok(Instruction::Le),
+            0x24 => Ok(Instruction::Gt),
+            0x25 => Ok(Instruction::Ge),
+            0x26 => Ok(Instruction::Not),
+            0x30 => Ok(Instruction::Jump(Self::read_target(stream)?)),
+            0x31 => Ok(Instruction::JumpIfZero(Self::read_target(stream)?)),
+            0x40 => Ok(Instruction::Log(Level::Info)),
+            0x41 => Ok(Instruction::Log(Level::Warn)),
+            0x42 => Ok(Instruction::Log(Level::Error)),
+            0x50 => Ok(Instruction::Rewind),
+            0x51 => Ok(Instruction::RewindStop),
+            0x60 => Ok(Instruction::TimelineFork),
+            0x61 => Ok(Instruction::TimelineSwitch),
+            0x62 => Ok(Instruction::TimelineMerge),
+            0x63 => {
+                let mut id = [0; 4];
+                stream.read_exact(&mut id)?;
+                Ok(Instruction::TimelineGet(u32::from_be_bytes(id)))
+            }
+            0x64 => Ok(Instruction::TimelineDiff),
+            0x65 => {
+                let mut id = [0; 4];
+                stream.read_exact(&mut id)?;
+                let mut rule = [0; 1];
+                stream.read_exact(&mut rule)?;
+                Ok(Instruction::MergeRule(
+                    u32::from_be_bytes(id),
+                    MergeRule::from_u8(rule[0])?,
+                ))
+            }
+            0x66 => Ok(Instruction::TimelineDelete),
+            0x67 => Ok(Instruction::TimelineCurrent),
+            0x70 => Ok(Instruction::Random),
+            0x71 => Ok(Instruction::KeyHeld),
+            _ => Err(LitError::BytecodeRead8(buffer[0])),
+        }
+    }
+}
+
+impl Bytecode for Instruction {
+    fn read<T: Read>(stream: &mut T) -> Result<Self, LitError> {
+        Self::read_limited(stream, &Limits::new())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Script {
+    instructions: Vec<Instruction>,
+}
+
+impl Script {
+    /// Read a script body, prefixed by its length in bytes. Variable ids are translated into global
+    /// ones using the parser state.
+    pub fn load<T: Read>(stream: &mut T, state: &mut ParserState) -> Result<Self, LitError> {
+        let mut buffer = [0; 4];
+        stream.read_exact(&mut buffer)?;
+        let len = u32::from_be_bytes(buffer) as u64;
+
+        // the length comes from the file, so only allocate for the data that is actually there
+        let mut body = vec![];
+        stream.by_ref().take(len).read_to_end(&mut body)?;
+        if (body.len() as u64) < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        // decode every instruction, remembering where each one started
+        let mut offsets = HashMap::new();
+        let mut instructions = vec![];
+        let body_len = body.len() as u64;
+        let mut cursor = Cursor::new(body);
+        while cursor.position() < body_len {
+            offsets.insert(cursor.position() as usize, instructions.len());
+            instructions.push(match Instruction::read_limited(&mut cursor, &state.limits)? {
+                Instruction::Push(obj) => Instruction::Push(state.globalize(obj)),
+                Instruction::Load(id) => Instruction::Load(state.global_id(id)),
+                Instruction::Store(id) => Instruction::Store(state.global_id(id)),
+                Instruction::TimelineGet(id) => Instruction::TimelineGet(state.global_id(id)),
+                Instruction::MergeRule(id, rule) => {
+                    Instruction::MergeRule(state.global_id(id), rule)
+                }
+                instr => instr,
+            });
+        }
+        offsets.insert(body_len as usize, instructions.len());
+
+        // convert jump targets from byte offsets to instruction indices
+        for instr in instructions.iter_mut() {
+            let target = match instr.jump_target() {
+                Some(t) => *offsets
+                    .get(&t)
+                    .ok_or_else(|| LitError::InvalidJumpTarget(t))?,
+                None => continue,
+            };
+
+            *instr = match *instr {
+                Instruction::Jump(_) => Instruction::Jump(target),
+                _ => Instruction::JumpIfZero(target),
+            };
+        }
+
+        Ok(Self { instructions })
+    }
+
+    #[inline]
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(body: &[u8]) -> Result<Script, LitError> {
+        let mut bytes = (body.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(body);
+        Script::load(&mut &bytes[..], &mut ParserState::new())
+    }
+
+    #[test]
+    fn jump_targets_become_instruction_indices() {
+        // jump to the second jump; return; jump if zero to the return; the end is a valid target
+        let script = load(&[0x30, 0, 0, 0, 6, 0x00, 0x31, 0, 0, 0, 5, 0x30, 0, 0, 0, 16]).unwrap();
+        let targets: Vec<_> = script
+            .instructions()
+            .iter()
+            .map(Instruction::jump_target)
+            .collect();
+        assert_eq!(targets, [Some(2), None, Some(1), Some(4)]);
+    }
+
+    #[test]
+    fn rejects_jumps_into_an_instruction() {
+        assert!(matches!(
+            load(&[0x01, 1, 0, 0x31, 0, 0, 0, 1]),
+            Err(LitError::InvalidJumpTarget(1))
+        ));
+        assert!(matches!(
+            load(&[0x30, 0, 0, 0, 6]),
+            Err(LitError::InvalidJumpTarget(6))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_opcodes() {
+        assert!(matches!(
+            Instruction::read(&mut &[0xFF][..]),
+            Err(LitError::BytecodeRead8(0xFF))
+        ));
+        assert!(matches!(
+            load(&[0x00, 0x27]),
+            Err(LitError::BytecodeRead8(0x27))
+        ));
+    }
+}
@@ -0,0 +1,102 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// script/layer.rs - Data files and packs that are stacked on top of one another.
+
+use crate::{LitError, DEBUG_EXTENSION};
+use lits_pack::{EntryKind, PackReader};
+use std::{
+    fmt,
+    fs::File,
+    io::{prelude::*, BufReader, Cursor},
+    path::{Path, PathBuf},
+};
+
+/// The name of the pack entry that is loaded when none is specified.
+pub const DEFAULT_PACK_ENTRY: &str = "main";
+
+#[derive(Debug, Clone)]
+pub enum DataLayer {
+    File(PathBuf),
+    Pack { path: PathBuf, entry: String },
+}
+
+impl DataLayer {
+    /// Parse a layer from the command line. Packs are given as "game.litpack" or
+    /// "game.litpack:entry".
+    pub fn parse(arg: &str) -> Self {
+        if let Some(i) = arg.rfind(':') {
+            if arg[..i].ends_with(".litpack") {
+                return DataLayer::Pack {
+                    path: PathBuf::from(&arg[..i]),
+                    entry: String::from(&arg[i + 1..]),
+                };
+            }
+        }
+
+        if arg.ends_with(".litpack") {
+            DataLayer::Pack {
+                path: PathBuf::from(arg),
+                entry: String::from(DEFAULT_PACK_ENTRY),
+            }
+        } else {
+            DataLayer::File(PathBuf::from(arg))
+        }
+    }
+
+    /// The file the layer is read from.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        match *self {
+            DataLayer::File(ref path) => path,
+            DataLayer::Pack { ref path, .. } => path,
+        }
+    }
+
+    /// The file that the debug symbols of this layer are kept in, e.g. "game.litdbg" for
+    /// "game.dat". Packs don't keep debug symbols, so layers in them have none.
+    pub fn symbols_path(&self) -> Option<PathBuf> {
+        match *self {
+            DataLayer::File(ref path) => Some(path.with_extension(DEBUG_EXTENSION)),
+            DataLayer::Pack { .. } => None,
+        }
+    }
+
+    /// Open a stream over the compiled script this layer refers to.
+    pub fn open(&self) -> Result<Box<dyn Read>, LitError> {
+        let file = BufReader::new(File::open(self.path())?);
+
+        match *self {
+            DataLayer::File(_) => Ok(Box::new(file)),
+            DataLayer::Pack { ref entry, .. } => {
+                let mut pack = PackReader::new(file)?;
+                Ok(Box::new(Cursor::new(read_script_entry(&mut pack, entry)?)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for DataLayer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DataLayer::File(ref path) => write!(f, "{}", path.display()),
+            DataLayer::Pack {
+                ref path,
+                ref entry,
+            } => write!(f, "{}:{}", path.display(), entry),
+        }
+    }
+}
+
+// read a compiled script out of a pack, making sure it actually is a script
+pub fn read_script_entry<R: Read + Seek>(
+    pack: &mut PackReader<R>,
+    name: &str,
+) -> Result<Vec<u8>, LitError> {
+    match pack.entry(name) {
+        Some(e) if e.kind != EntryKind::Script => {
+            return Err(LitError::NotAScript(String::from(name)))
+        }
+        _ => {}
+    }
+
+    Ok(pack.read_entry(name)?)
+}
@@ -0,0 +1,306 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// script/limits.rs - Limits on what a data file may ask for.
+
+use crate::LitError;
+
+/// Limits on the resources a data file may use. Data files can come from anywhere, so nothing
+/// they ask for is allocated without checking it against these first.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// The largest width or height of a texture.
+    pub max_texture_size: u32,
+    /// The number of bytes all textures may take up together.
+    pub max_pixel_memory: usize,
+    /// The longest string, in bytes.
+    pub max_string_len: usize,
+    /// The number of variables across every data file.
+    pub max_variables: usize,
+    /// How deeply tuples may be nested.
+    pub max_depth: usize,
+    /// The number of objects in a single value, counting every element of every tuple.
+    pub max_elements: usize,
+    /// The number of instructions a script may run each time it is called.
+    pub max_instructions: usize,
+    /// The number of bytes a data file may take up once it is decompressed.
+    pub max_data_size: usize,
+}
+
+// textures are stored as RGBA
+const BYTES_PER_PIXEL: usize = 4;
+
+impl Limits {
+    /// The default limits, which are generous for hand-made data but stop a file from exhausting
+    /// memory or hanging the game.
+    pub fn new() -> Self {
+        Self {
+            max_texture_size: 4096,
+            max_pixel_memory: 256 * 1024 * 1024,
+            max_string_len: 255,
+            max_variables: 65536,
+            max_depth: 32,
+            max_elements: 65536,
+            max_instructions: 1_000_000,
+            max_data_size: 64 * 1024 * 1024,
+        }
+    }
+
+    /// No limits at all, for data that is trusted.
+    pub fn unlimited() -> Self {
+        Self {
+            max_texture_size: u32::MAX,
+            max_pixel_memory: usize::MAX,
+            max_string_len: usize::MAX,
+            max_variables: usize::MAX,
+            max_depth: usize::MAX,
+            max_elements: usize::MAX,
+            max_instructions: usize::MAX,
+            max_data_size: usize::MAX,
+        }
+    }
+
+    /// Change a limit by the name it is given on the command line, e.g. "texture-size" for
+    /// `--max-texture-size`. Returns false if there is no limit with that name.
+    pub fn set(&mut self, name: &str, value: usize) -> bool {
+        match name {
+            "texture-size" => self.max_texture_size = value.min(u32::MAX as usize) as u32,
+            "pixel-memory" => self.max_pixel_memory = value,
+            "string-len" => self.max_string_len = value,
+            "variables" => self.max_variables = value,
+            "depth" => self.max_depth = value,
+            "elements" => self.max_elements = value,
+            "instructions" => self.max_instructions = value,
+            "data-size" => self.max_data_size = value,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Check the size of a texture, returning the number of bytes it takes up.
+    pub fn check_texture(&self, width: u32, height: u32) -> Result<usize, LitError> {
+        if width > self.max_texture_size || height > self.max_texture_size {
+            return Err(LitError::TextureTooLarge {
+                width,
+                height,
+                max: self.max_texture_size,
+            });
+        }
+
+        (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|p| p.checked_mul(BYTES_PER_PIXEL))
+            .ok_or(LitError::PixelMemoryExceeded(self.max_pixel_memory))
+    }
+
+    #[inline]
+    pub fn check_string(&self, len: usize) -> Result<(), LitError> {
+        if len > self.max_string_len {
+            Err(LitError::StringTooLong(len, self.max_string_len))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    pub fn check_elements(&self, elements: usize) -> Result<(), LitError> {
+        if elements > self.max_elements {
+            Err(LitError::ValueTooLarge(self.max_elements))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    pub fn check_depth(&self, depth: usize) -> Result<(), LitError> {
+        if depth > self.max_depth {
+            Err(LitError::TooDeep(self.max_depth))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BytecodeObject, ParserState, Script, Vm};
+
+    // a number nested inside some tuples
+    fn nested(depth: usize) -> Vec<u8> {
+        let mut bytes = vec![];
+        for _ in 0..depth {
+            bytes.extend_from_slice(&[5, 1]);
+        }
+        bytes.extend_from_slice(&[1, 0]);
+        bytes
+    }
+
+    // a tuple of some numbers
+    fn tuple(len: u8) -> BytecodeObject {
+        BytecodeObject::Tuple(vec![BytecodeObject::Numeric8(0); len as usize])
+    }
+
+    fn read(bytes: &[u8], limits: &Limits) -> Result<BytecodeObject, LitError> {
+        BytecodeObject::read_limited(&mut &bytes[..], limits)
+    }
+
+    // a script that pushes a number some number of times
+    fn pushes(count: usize, state: &mut ParserState) -> Script {
+        let mut bytes = ((count * 3) as u32).to_be_bytes().to_vec();
+        for _ in 0..count {
+            bytes.extend_from_slice(&[0x01, 1, 0]);
+        }
+        Script::load(&mut &bytes[..], state).unwrap()
+    }
+
+    #[test]
+    fn texture_size() {
+        let mut limits = Limits::new();
+        limits.max_texture_size = 16;
+        assert_eq!(limits.check_texture(16, 16).unwrap(), 16 * 16 * 4);
+        assert!(matches!(
+            limits.check_texture(17, 1),
+            Err(LitError::TextureTooLarge { max: 16, .. })
+        ));
+        assert!(limits.check_texture(1, 17).is_err());
+    }
+
+    #[test]
+    fn pixel_memory() {
+        let mut limits = Limits::new();
+        limits.max_pixel_memory = 4 * 4 * 4;
+        let mut state = ParserState::with_limits(limits);
+        state.reserve_texture(1, 4, 4).unwrap();
+        // redefining a texture gives back the memory it used
+        state.reserve_texture(1, 4, 4).unwrap();
+        assert!(matches!(
+            state.reserve_texture(2, 1, 1),
+            Err(LitError::PixelMemoryExceeded(64))
+        ));
+    }
+
+    #[test]
+    fn string_len() {
+        let mut limits = Limits::new();
+        limits.max_string_len = 3;
+        assert!(read(b"\x04\x03abc", &limits).is_ok());
+        assert!(matches!(
+            read(b"\x04\x04abcd", &limits),
+            Err(LitError::StringTooLong(4, 3))
+        ));
+    }
+
+    #[test]
+    fn variables() {
+        let mut limits = Limits::new();
+        limits.max_variables = 2;
+        let mut state = ParserState::with_limits(limits);
+        state
+            .register_variable(1, BytecodeObject::Numeric8(1))
+            .unwrap();
+        state
+            .register_variable(2, BytecodeObject::Numeric8(2))
+            .unwrap();
+        // a variable that already exists can be set again
+        state
+            .register_variable(2, BytecodeObject::Numeric8(3))
+            .unwrap();
+        assert!(matches!(
+            state.register_variable(3, BytecodeObject::Numeric8(4)),
+            Err(LitError::TooManyVariables(2))
+        ));
+    }
+
+    #[test]
+    fn depth() {
+        let mut limits = Limits::new();
+        limits.max_depth = 2;
+        assert!(read(&nested(2), &limits).is_ok());
+        assert!(matches!(
+            read(&nested(3), &limits),
+            Err(LitError::TooDeep(2))
+        ));
+    }
+
+    #[test]
+    fn elements() {
+        let mut limits = Limits::new();
+        // the tuple counts as well as its elements
+        limits.max_elements = 3;
+        let state = ParserState::with_limits(limits);
+        assert!(state.resolve(tuple(2)).is_ok());
+        assert!(matches!(
+            state.resolve(tuple(3)),
+            Err(LitError::ValueTooLarge(3))
+        ));
+    }
+
+    #[test]
+    fn instructions() {
+        let mut limits = Limits::new();
+        limits.max_instructions = 4;
+        let mut state = ParserState::with_limits(limits);
+        let mut vm = Vm::new();
+        let script = pushes(4, &mut state);
+        vm.run(&script, &mut state).unwrap();
+        let script = pushes(5, &mut state);
+        assert!(matches!(
+            vm.run(&script, &mut state),
+            Err(LitError::InstructionLimit(4))
+        ));
+    }
+
+    #[test]
+    fn data_size() {
+        let mut limits = Limits::new();
+        limits.max_data_size = 8;
+        let data = [0; 8];
+        assert_eq!(
+            super::super::read_layer_bytes(&mut &data[..], &limits)
+                .unwrap()
+                .len(),
+            8
+        );
+        limits.max_data_size = 7;
+        assert!(matches!(
+            super::super::read_layer_bytes(&mut &data[..], &limits),
+            Err(LitError::DataTooLarge(7))
+        ));
+    }
+
+    #[test]
+    fn set_takes_the_names_of_options() {
+        let mut limits = Limits::new();
+        for name in [
+            "texture-size",
+            "pixel-memory",
+            "string-len",
+            "variables",
+            "depth",
+            "elements",
+            "instructions",
+            "data-size",
+        ]
+        .iter()
+        {
+            assert!(limits.set(name, 7), "{}", name);
+        }
+        assert_eq!(
+            limits,
+            Limits {
+                max_texture_size: 7,
+                max_pixel_memory: 7,
+                max_string_len: 7,
+                max_variables: 7,
+                max_depth: 7,
+                max_elements: 7,
+                max_instructions: 7,
+                max_data_size: 7,
+            }
+        );
+
+        assert!(!limits.set("max-depth", 1));
+        assert!(!limits.set("colors", 1));
+        limits.set("texture-size", usize::MAX);
+        assert_eq!(limits.max_texture_size, u32::MAX);
+    }
+}
@@ -5,12 +5,57 @@ mod bytecode;
 pub use bytecode::Bytecode;
 
 mod eval;
+pub use eval::eval;
+
+mod graph;
+
+mod instruction;
+pub use instruction::{Instruction, Script};
+
+mod layer;
+pub use layer::DataLayer;
+
+mod limits;
+pub use limits::Limits;
+
+mod policy;
+pub use policy::{LoadPolicy, LoadWarning};
+
+mod timeline;
+pub use timeline::{MergeRule, Timelines, MAIN_TIMELINE};
 
 mod types;
 pub use types::{BytecodeObject, DataType};
 
-use super::{Color, ImgMaterial, LitError, Resource, ResourceType, ResourceDictionary};
-use std::{collections::HashMap, io::prelude::*};
+mod verify;
+pub use verify::{split_statements, verify, Verifier, VerifyError};
+
+mod vm;
+pub use vm::{Vm, VmRequest};
+
+mod watch;
+pub use watch::DataWatcher;
+
+use super::{
+    draw::{Composite, DrawBuffer},
+    logging::{self, Level, Target},
+    Color, Debugger, Handle, Image, IndexedTexture, LitError, Loadable, Loader, Palette, PaletteCycle,
+    ReloadReport, Resource, ResourceDictionary, ResourceStats, ResourceType, Rng, SaveData,
+    DEFAULT_SEED,
+};
+use lit_gl_wrapper::gl;
+use lit_lz::Crc32;
+use lits_pack::PackReader;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{prelude::*, Cursor},
+    mem,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MaterialType {
+    ImgMaterial,
+}
 
 #[derive(Debug, Copy, Clone)]
 pub struct Dependancy {
@@ -18,27 +63,277 @@ pub struct Dependancy {
     pub id: u32,
 }
 
+#[derive(Debug)]
 pub struct ParserState {
     pub variables: HashMap<u32, BytecodeObject>,
     pub dependency_relations: HashMap<u32, Vec<Dependancy>>,
     color_ids: HashMap<u32, HashMap<u8, Color>>,
+    palette_cycles: HashMap<u32, Vec<PaletteCycle>>,
+
+    // each data file numbers its variables starting from 1, so the ids are translated to
+    // ids that are unique across every layer
+    layer: usize,
+    layer_ids: HashMap<u32, u32>,
+    next_id: u32,
+    pub names: HashMap<String, u32>,
+    pub variable_layers: HashMap<u32, usize>,
+
+    // copies of the variables that scripts can switch between
+    pub timelines: Timelines,
+    // the random numbers scripts get, seeded so that a game can be replayed
+    pub rng: Rng,
 
     // storage for various types of resources
     pub img_material_ids: Vec<u32>,
+    pub indexed_ids: Vec<u32>,
+    pub palette_ids: Vec<u32>,
+
+    // what the data files are allowed to use, and how much of it they use
+    pub limits: Limits,
+    texture_memory: HashMap<u32, usize>,
+    pixel_memory: usize,
 }
 
 impl ParserState {
     pub fn new() -> Self {
+        Self::with_limits(Limits::new())
+    }
+
+    pub fn with_limits(limits: Limits) -> Self {
         Self {
             color_ids: HashMap::new(),
+            palette_cycles: HashMap::new(),
             dependency_relations: HashMap::new(),
             variables: HashMap::new(),
+            layer: 0,
+            layer_ids: HashMap::new(),
+            next_id: 1,
+            names: HashMap::new(),
+            variable_layers: HashMap::new(),
+            timelines: Timelines::new(),
+            rng: Rng::new(DEFAULT_SEED),
             img_material_ids: vec![],
+            indexed_ids: vec![],
+            palette_ids: vec![],
+            limits,
+            texture_memory: HashMap::new(),
+            pixel_memory: 0,
         }
     }
 
-    pub fn register_variable(&mut self, index: u32, object: BytecodeObject) {
+    /// Start reading a new data file.
+    pub fn begin_layer(&mut self, layer: usize) {
+        self.layer = layer;
+        self.layer_ids.clear();
+    }
+
+    #[inline]
+    pub fn layer(&self) -> usize {
+        self.layer
+    }
+
+    /// Translate an id used by the current data file into a global id.
+    pub fn global_id(&mut self, local: u32) -> u32 {
+        match self.layer_ids.get(&local) {
+            Some(id) => *id,
+            None => {
+                let id = self.next_id;
+                self.next_id += 1;
+                self.layer_ids.insert(local, id);
+                id
+            }
+        }
+    }
+
+    /// Translate every variable invocation in an object into global ids.
+    pub fn globalize(&mut self, object: BytecodeObject) -> BytecodeObject {
+        match object {
+            BytecodeObject::VarInvocation(i) => BytecodeObject::VarInvocation(self.global_id(i)),
+            BytecodeObject::Tuple(t) => {
+                BytecodeObject::Tuple(t.into_iter().map(|o| self.globalize(o)).collect())
+            }
+            o => o,
+        }
+    }
+
+    /// Give a name to a variable in the current data file. If an earlier data file already has a
+    /// variable with this name, the variable is overridden instead.
+    pub fn name_variable(&mut self, local: u32, name: String) {
+        match self.names.get(&name) {
+            Some(id) => {
+                // the colors of the variable being replaced don't carry over to the new one
+                self.color_ids.remove(id);
+                self.palette_cycles.remove(id);
+                self.layer_ids.insert(local, *id);
+            }
+            None => {
+                let id = self.global_id(local);
+                self.names.insert(name, id);
+            }
+        }
+    }
+
+    pub fn register_variable(
+        &mut self,
+        index: u32,
+        object: BytecodeObject,
+    ) -> Result<(), LitError> {
+        if !self.variables.contains_key(&index)
+            && self.variables.len() >= self.limits.max_variables
+        {
+            return Err(LitError::TooManyVariables(self.limits.max_variables));
+        }
+
         self.variables.insert(index, object);
+        self.variable_layers.insert(index, self.layer);
+        Ok(())
+    }
+
+    /// Replace every variable invocation in an object with the value of the variable, so that
+    /// variables never refer to each other. Textures are drawn to in place, so references to them
+    /// are kept, but always point at the variable that holds the texture.
+    pub fn resolve(&self, object: BytecodeObject) -> Result<BytecodeObject, LitError> {
+        let mut elements = 0;
+        self.resolve_nested(object, 0, &mut elements)
+    }
+
+    fn resolve_nested(
+        &self,
+        object: BytecodeObject,
+        depth: usize,
+        elements: &mut usize,
+    ) -> Result<BytecodeObject, LitError> {
+        self.limits.check_depth(depth)?;
+        *elements += 1;
+        self.limits.check_elements(*elements)?;
+
+        match object {
+            BytecodeObject::VarInvocation(mut id) => {
+                // references are only ever made to texture variables, so this can't loop forever
+                for _ in 0..=self.variables.len() {
+                    match self.get_variable(id)? {
+                        BytecodeObject::VarInvocation(next) => id = *next,
+                        v if v.data_type(self).is_material() => {
+                            return Ok(BytecodeObject::VarInvocation(id))
+                        }
+                        v => return self.resolve_nested(v.clone(), depth, elements),
+                    }
+                }
+
+                Err(LitError::VariableNotFound(id))
+            }
+            BytecodeObject::Tuple(t) => Ok(BytecodeObject::Tuple(
+                t.into_iter()
+                    .map(|o| self.resolve_nested(o, depth + 1, elements))
+                    .collect::<Result<_, _>>()?,
+            )),
+            o => Ok(o),
+        }
+    }
+
+    /// Keep the values that named numbers and strings have in another state, e.g. the one a game
+    /// was running with before its data files were reloaded, along with its timelines. Variables
+    /// that changed type keep their new value.
+    pub fn carry_over(&mut self, old: &ParserState) {
+        let ids: HashMap<u32, u32> = self
+            .names
+            .iter()
+            .filter_map(|(name, id)| old.names.get(name).map(|old_id| (*old_id, *id)))
+            .collect();
+        self.timelines = old.timelines.clone();
+        self.timelines.remap(&ids);
+        self.rng = old.rng;
+
+        for (name, id) in &self.names {
+            let value = match old
+                .names
+                .get(name)
+                .and_then(|old_id| old.variables.get(old_id))
+            {
+                Some(value) => value,
+                None => continue,
+            };
+            let same_type =
+                self.variables.get(id).map(mem::discriminant) == Some(mem::discriminant(value));
+            let keep = same_type
+                && matches!(
+                    value,
+                    BytecodeObject::Numeric8(_)
+                        | BytecodeObject::Numeric16(_)
+                        | BytecodeObject::Numeric32(_)
+                        | BytecodeObject::Str(_)
+                );
+            if keep {
+                self.variables.insert(*id, value.clone());
+            }
+        }
+    }
+
+    /// A checksum of everything that scripts can change, to tell whether two runs of a game have
+    /// stayed in step.
+    pub fn checksum(&self) -> u32 {
+        let mut ids: Vec<u32> = self
+            .variables
+            .iter()
+            .filter(|(_, v)| v.is_plain())
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+
+        let mut crc = Crc32::new();
+        let mut bytes = vec![];
+        for id in ids {
+            bytes.clear();
+            bytes.extend_from_slice(&id.to_be_bytes());
+            // a value that is too long to write is cut short the same way every time
+            let _ = self.variables[&id].write(&mut bytes);
+            crc.update(&bytes);
+        }
+        crc.update(&self.rng.state().to_be_bytes());
+        crc.update(self.timelines.current().as_bytes());
+        crc.finish()
+    }
+
+    /// The values of every named variable that holds a plain value, e.g. to save them.
+    pub fn named_values(&self) -> BTreeMap<String, BytecodeObject> {
+        self.names
+            .iter()
+            .filter_map(|(name, id)| {
+                self.variables
+                    .get(id)
+                    .filter(|v| v.is_plain())
+                    .map(|v| (name.clone(), v.clone()))
+            })
+            .collect()
+    }
+
+    /// Assign a value to a variable. The values of the variables it refers to are copied as they
+    /// are now, rather than kept as references, except for textures, which are drawn to in place.
+    pub fn assign(&mut self, index: u32, object: BytecodeObject) -> Result<(), LitError> {
+        match self.resolve(object)? {
+            // a texture variable assigned to itself stays the same
+            BytecodeObject::VarInvocation(id) if id == index => Ok(()),
+            object => self.register_variable(index, object),
+        }
+    }
+
+    /// Account for the memory used by a texture, replacing whatever the variable used before.
+    pub fn reserve_texture(
+        &mut self,
+        index: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<(), LitError> {
+        let size = self.limits.check_texture(width, height)?;
+        let previous = self.texture_memory.get(&index).copied().unwrap_or(0);
+        let total = (self.pixel_memory - previous)
+            .checked_add(size)
+            .filter(|t| *t <= self.limits.max_pixel_memory)
+            .ok_or(LitError::PixelMemoryExceeded(self.limits.max_pixel_memory))?;
+
+        self.texture_memory.insert(index, size);
+        self.pixel_memory = total;
+        Ok(())
     }
 
     pub fn get_variable(&self, index: u32) -> Result<&BytecodeObject, LitError> {
@@ -51,6 +346,105 @@ impl ParserState {
         self.dependency_relations.insert(index, dependencies);
     }
 
+    /// Record that a material is built from another one.
+    pub fn add_dependency(&mut self, index: u32, dependency: Dependancy) {
+        let deps = self.dependency_relations.entry(index).or_default();
+        if !deps.iter().any(|d| d.id == dependency.id) {
+            deps.push(dependency);
+        }
+    }
+
+    /// Get the id of the variable that holds the material an object refers to, along with the
+    /// type of the material.
+    pub fn material_id(&self, object: &BytecodeObject) -> Result<(u32, DataType), LitError> {
+        let mut id = object.get_var_id(self)?;
+
+        // references to materials always point at the holder, but may go through a variable
+        for _ in 0..=self.variables.len() {
+            match self.get_variable(id)? {
+                BytecodeObject::VarInvocation(next) => id = *next,
+                v if v.data_type(self).is_material() => return Ok((id, v.data_type(self))),
+                v => {
+                    return Err(LitError::IncorrectDataType(
+                        v.data_type(self),
+                        DataType::DrawBufferImg,
+                    ))
+                }
+            }
+        }
+
+        Err(LitError::VariableNotFound(id))
+    }
+
+    /// Get the id of the variable that holds the texture an object refers to.
+    pub fn texture_id(&self, object: &BytecodeObject) -> Result<u32, LitError> {
+        match self.material_id(object)? {
+            (id, DataType::DrawBufferImg) => Ok(id),
+            (_, kind) => Err(LitError::IncorrectDataType(kind, DataType::DrawBufferImg)),
+        }
+    }
+
+    /// Record that a variable holds a material of some type, so that it is built once. A later
+    /// layer may redefine the variable as a different type of material.
+    pub fn add_material(&mut self, index: u32, kind: DataType) {
+        for ids in [
+            &mut self.img_material_ids,
+            &mut self.indexed_ids,
+            &mut self.palette_ids,
+        ]
+        .iter_mut()
+        {
+            ids.retain(|i| *i != index);
+        }
+
+        match kind {
+            DataType::IndexedImg => self.indexed_ids.push(index),
+            DataType::Palette => self.palette_ids.push(index),
+            _ => self.img_material_ids.push(index),
+        }
+
+        // the new material doesn't use whatever the old one was built from
+        self.dependency_relations.remove(&index);
+        self.palette_cycles.remove(&index);
+    }
+
+    /// Add a cycle that rotates some colors of an indexed texture while the game runs.
+    #[inline]
+    pub fn add_palette_cycle(&mut self, index: u32, cycle: PaletteCycle) {
+        self.palette_cycles.entry(index).or_default().push(cycle);
+    }
+
+    /// Whether a variable holds an indexed texture.
+    #[inline]
+    pub fn is_indexed(&self, index: u32) -> bool {
+        matches!(self.variables.get(&index), Some(BytecodeObject::IndexedImg(_)))
+    }
+
+    /// Add an operation that uses other textures to a texture, recording that the texture
+    /// depends on them.
+    pub fn composite(&mut self, index: u32, op: Composite) -> Result<(), LitError> {
+        if let Some(source) = op.source() {
+            self.add_dependency(
+                index,
+                Dependancy {
+                    kind: MaterialType::ImgMaterial,
+                    id: source,
+                },
+            );
+        }
+
+        match self.get_variable_mut(index)? {
+            BytecodeObject::DrawBufferImg(buffer) => {
+                buffer.composite(op);
+                Ok(())
+            }
+            _ => Err(LitError::IncorrectDataType(
+                DataType::Unknown,
+                DataType::DrawBufferImg,
+            )),
+        }
+    }
+
     pub fn get_variable_mut(&mut self, index: u32) -> Result<&mut BytecodeObject, LitError> {
         self.variables
             .get_mut(&index)
@@ -69,6 +463,12 @@ impl ParserState {
         dict.insert(index, clr);
     }
 
+    /// Every color id registered for an object, e.g. to show them in the debugger.
+    #[inline]
+    pub fn color_map(&self, object: u32) -> Option<&HashMap<u8, Color>> {
+        self.color_ids.get(&object)
+    }
+
     pub fn get_color(&self, object: u32, index: u8) -> Result<&Color, LitError> {
         self.color_ids
             .get(&object)
@@ -77,72 +477,113 @@ impl ParserState {
             .ok_or_else(|| LitError::ColorIdNotFound(object, index))
     }
 
-    pub fn into_resource_dict(self) -> Result<ResourceDictionary, LitError> {
+    /// Move every material out of the variables and into a resource dictionary. The remaining
+    /// variables are kept around for scripts to use.
+    pub fn build_resource_dict(&mut self) -> Result<ResourceDictionary, LitError> {
         let mut rd = ResourceDictionary::new();
-        let ParserState {
-            mut variables,
-            dependency_relations,
-            img_material_ids,
-            ..
-        } = self;
-
-        insert_materials::<ImgMaterial>(
-            &mut rd,
-            &dependency_relations,
-            &mut variables,
-            img_material_ids,
-        )?;
+        let img_material_ids = mem::take(&mut self.img_material_ids);
+        let roots: Vec<Dependancy> = img_material_ids
+            .into_iter()
+            .map(|id| Dependancy {
+                kind: MaterialType::ImgMaterial,
+                id,
+            })
+            .collect();
 
-        Ok(rd)
-    }
-}
+        // textures that others are built from are kept until everything is built
+        let sources: HashSet<u32> = self
+            .dependency_relations
+            .values()
+            .flatten()
+            .map(|d| d.id)
+            .collect();
+        let mut resolved: HashMap<u32, DrawBuffer> = HashMap::new();
 
-// helper function: insert resources for a certain type
-#[inline]
-fn insert_material<T: Resource>(
-    rd: &mut ResourceDictionary,
-    dep_rels: &HashMap<u32, Vec<Dependancy>>,
-    variables: &mut HashMap<u32, BytecodeObject>,
-    id: u32,
-) -> Result<u32, LitError> {
-    // loop through dependencies to get ids for these dependencies
-    let dep_ids: Vec<Result<u32, LitError>> = if let Some(deps) = dep_rels.get(&id) {
-        deps.iter()
-            .map(|dep| match dep.kind {
+        for material in self.material_order(&roots)? {
+            let limits = self.limits;
+            if let Some(BytecodeObject::DrawBufferImg(buffer)) =
+                self.variables.get_mut(&material.id)
+            {
+                buffer.resolve(&resolved, &limits)?;
+                if sources.contains(&material.id) {
+                    resolved.insert(material.id, buffer.clone());
+                }
+            }
+
+            match material.kind {
                 MaterialType::ImgMaterial => {
-                    insert_material::<ImgMaterial>(rd, dep_rels, variables, dep.id)
+                    let handle = self.insert_image(&mut rd, material.id)?;
+                    self.name_resource(&mut rd, material.id, handle);
                 }
-            })
-            .collect()
-    } else {
-        vec![]
-    };
+            }
+        }
 
-    if let Some(mat) = variables.remove(&id) {
-        Ok(rd.add_res(T::from_bytecode_object(mat, &dep_ids)?))
-    } else {
-        Err(LitError::VariableNotFound(id))
+        // indexed textures and palettes take their colors from their color ids
+        for id in mem::take(&mut self.indexed_ids) {
+            let palette = self.palette(id);
+            let buffer = match self.variables.remove(&id) {
+                Some(BytecodeObject::IndexedImg(buffer)) => buffer,
+                _ => return Err(LitError::VariableNotFound(id)),
+            };
+            let mut texture = IndexedTexture::new(buffer, palette);
+            for cycle in self.palette_cycles.remove(&id).unwrap_or_default() {
+                texture.add_cycle(cycle);
+            }
+            let handle = rd.add_res(texture);
+            rd.set_origin(handle, self.variable_layers.get(&id).copied().unwrap_or(0));
+            self.name_resource(&mut rd, id, handle);
+        }
+
+        for id in mem::take(&mut self.palette_ids) {
+            let palette = self.palette(id);
+            self.variables.remove(&id);
+            let handle = rd.add_res(palette);
+            rd.set_origin(handle, self.variable_layers.get(&id).copied().unwrap_or(0));
+            self.name_resource(&mut rd, id, handle);
+        }
+
+        Ok(rd)
     }
-}
 
-#[inline]
-fn insert_materials<T: Resource>(
-    rd: &mut ResourceDictionary,
-    dep_rels: &HashMap<u32, Vec<Dependancy>>,
-    variables: &mut HashMap<u32, BytecodeObject>,
-    ids: Vec<u32>,
-) -> Result<(), LitError> {
-    for id in ids {
-        insert_material::<T>(rd, dep_rels, variables, id)?;
+    // resources can be looked up by the name of the variable they were built from
+    fn name_resource<T: Resource>(&self, rd: &mut ResourceDictionary, id: u32, handle: Handle<T>) {
+        if let Some((name, _)) = self.names.iter().find(|(_, i)| **i == id) {
+            rd.set_name(name.clone(), handle);
+        }
     }
 
-    Ok(())
+    // the palette made up of the color ids of a variable
+    #[inline]
+    fn palette(&self, id: u32) -> Palette {
+        self.color_ids
+            .get(&id)
+            .map_or_else(Palette::new, Palette::from_color_ids)
+    }
+
+    // move a single texture into the resource dictionary. it is drawn when it is first used,
+    // so its composites have to be resolved already
+    fn insert_image(
+        &mut self,
+        rd: &mut ResourceDictionary,
+        id: u32,
+    ) -> Result<Handle<Image>, LitError> {
+        let buffer = match self.variables.remove(&id) {
+            Some(BytecodeObject::DrawBufferImg(buffer)) => buffer,
+            _ => return Err(LitError::VariableNotFound(id)),
+        };
+        let handle = rd.add_res(Image::new(buffer));
+        rd.set_origin(handle, self.variable_layers.get(&id).copied().unwrap_or(0));
+        Ok(handle)
+    }
 }
 
 #[derive(Debug)]
 pub struct GameData {
     name: String,
     resource_dict: Option<ResourceDictionary>,
+    state: ParserState,
+    scripts: HashMap<String, Script>,
+    warnings: Vec<LoadWarning>,
 }
 
 impl GameData {
@@ -150,6 +591,9 @@ impl GameData {
         Self {
             name: String::from("Unnamed"),
             resource_dict: None,
+            state: ParserState::new(),
+            scripts: HashMap::new(),
+            warnings: vec![],
         }
     }
 
@@ -161,29 +605,666 @@ impl GameData {
         self.name = name;
     }
 
-    pub fn get_resource<T: Resource>(&mut self, id: u32) -> Result<&T, LitError> {
-        self.resource_dict.as_mut().unwrap().load_res(id)
+    /// Look up a resource by the name of the variable it was built from.
+    pub fn handle<T: Resource>(&self, name: &str) -> Result<Handle<T>, LitError> {
+        self.resource_dict
+            .as_ref()
+            .ok_or(LitError::NoResources)?
+            .handle(name)
+    }
+
+    /// Every named resource and its type.
+    pub fn resource_names(&self) -> Vec<(&str, ResourceType)> {
+        self.resource_dict
+            .as_ref()
+            .map_or_else(Vec::new, |rd| rd.names().collect())
+    }
+
+    pub fn get_resource<T: Resource>(&mut self, handle: Handle<T>) -> Result<&T, LitError> {
+        self.resource_dict
+            .as_mut()
+            .ok_or(LitError::NoResources)?
+            .get(handle)
+    }
+
+    /// Get a resource to draw with, loading it onto the GPU if it isn't already.
+    pub fn load_resource<T: Loadable>(
+        &mut self,
+        gl: &gl::Gl,
+        handle: Handle<T>,
+    ) -> Result<&mut T, LitError> {
+        self.resource_dict
+            .as_mut()
+            .ok_or(LitError::NoResources)?
+            .load(gl, handle)
+    }
+
+    /// Queue the images that are referenced but not loaded to be drawn on the loader's threads.
+    pub fn preload(&self, loader: &mut Loader) -> Result<(), LitError> {
+        loader.queue_referenced(self.resource_dict.as_ref().ok_or(LitError::NoResources)?)
+    }
+
+    /// Upload some of the images that the loader has drawn. Returns the number of images that
+    /// were uploaded.
+    pub fn upload_preloaded(
+        &mut self,
+        loader: &mut Loader,
+        gl: &gl::Gl,
+        max: usize,
+    ) -> Result<usize, LitError> {
+        loader.upload(
+            self.resource_dict.as_mut().ok_or(LitError::NoResources)?,
+            gl,
+            max,
+        )
+    }
+
+    /// Hold a reference to a resource, so that it isn't evicted while it is in use.
+    pub fn acquire_resource<T: Resource>(&mut self, handle: Handle<T>) -> Result<(), LitError> {
+        self.resource_dict
+            .as_mut()
+            .ok_or(LitError::NoResources)?
+            .acquire(handle)
+    }
+
+    pub fn release_resource<T: Resource>(&mut self, handle: Handle<T>) -> Result<(), LitError> {
+        self.resource_dict
+            .as_mut()
+            .ok_or(LitError::NoResources)?
+            .release(handle);
+        Ok(())
+    }
+
+    /// Evict the resources that haven't been used for a while. This is called once every frame.
+    pub fn collect_resources(&mut self) -> Result<(), LitError> {
+        self.resource_dict
+            .as_mut()
+            .ok_or(LitError::NoResources)?
+            .collect();
+        Ok(())
+    }
+
+    /// Set the most GPU memory that resources should use, in bytes.
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) -> Result<(), LitError> {
+        self.resource_dict
+            .as_mut()
+            .ok_or(LitError::NoResources)?
+            .set_memory_budget(budget);
+        Ok(())
+    }
+
+    pub fn resource_stats(&self) -> Result<ResourceStats, LitError> {
+        self.resource_dict
+            .as_ref()
+            .map(|rd| rd.stats())
+            .ok_or(LitError::NoResources)
+    }
+
+    /// Get an indexed texture, e.g. to change some colors of its palette.
+    pub fn indexed_texture_mut(
+        &mut self,
+        handle: Handle<IndexedTexture>,
+    ) -> Result<&mut IndexedTexture, LitError> {
+        self.resource_dict
+            .as_mut()
+            .ok_or(LitError::NoResources)?
+            .get_mut(handle)
+    }
+
+    /// Advance the palette cycles of every indexed texture by a frame.
+    pub fn advance_palettes(&mut self) -> Result<(), LitError> {
+        let rd = self.resource_dict.as_mut().ok_or(LitError::NoResources)?;
+        for texture in rd.res_indexed_subdict_mut().values_mut() {
+            texture.advance();
+        }
+        Ok(())
+    }
+
+    /// Swap the palette of an indexed texture for a copy of another palette, e.g. to recolor an
+    /// enemy or to flash a sprite when it takes damage.
+    pub fn set_palette(
+        &mut self,
+        texture: Handle<IndexedTexture>,
+        palette: Handle<Palette>,
+    ) -> Result<(), LitError> {
+        let rd = self.resource_dict.as_mut().ok_or(LitError::NoResources)?;
+        let palette = rd.get(palette)?.clone();
+        rd.get_mut(texture)?.set_palette(&palette);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn state(&self) -> &ParserState {
+        &self.state
+    }
+
+    #[inline]
+    pub fn state_mut(&mut self) -> &mut ParserState {
+        &mut self.state
+    }
+
+    /// Add an entry point, replacing any earlier one with the same name.
+    pub fn add_script(&mut self, name: String, script: Script) {
+        self.scripts.insert(name, script);
+    }
+
+    #[inline]
+    pub fn script(&self, name: &str) -> Option<&Script> {
+        self.scripts.get(name)
+    }
+
+    /// Run an entry point on a VM. Returns false if there is no entry point with that name.
+    pub fn run_script(&mut self, vm: &mut Vm, name: &str) -> Result<bool, LitError> {
+        match self.scripts.get(name) {
+            Some(script) => {
+                vm.run(script, &mut self.state)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Read game data from a compiled script stored in a pack.
+    pub fn read_from_pack<R: Read + Seek>(
+        pack: &mut PackReader<R>,
+        name: &str,
+    ) -> Result<Self, LitError> {
+        let data = layer::read_script_entry(pack, name)?;
+        Self::read(&mut Cursor::new(data))
+    }
+
+    /// Read game data from an ordered list of data files. Variables and resources defined in later
+    /// layers override those with the same name in earlier layers. Fails on the first error.
+    pub fn read_layers<T: Read>(layers: &mut [T]) -> Result<Self, LitError> {
+        Self::read_layers_with(layers, LoadPolicy::Strict, Limits::new())
+    }
+
+    /// Read game data from an ordered list of data files, handling errors according to a policy
+    /// and keeping within a set of limits. Errors that were skipped over are available through
+    /// `warnings`.
+    pub fn read_layers_with<T: Read>(
+        layers: &mut [T],
+        policy: LoadPolicy,
+        limits: Limits,
+    ) -> Result<Self, LitError> {
+        Self::read_layers_debug(layers, policy, limits, None)
+    }
+
+    /// Read game data like `read_layers_with`, letting a debugger stop before each statement is
+    /// evaluated.
+    pub fn read_layers_debug<T: Read>(
+        layers: &mut [T],
+        policy: LoadPolicy,
+        limits: Limits,
+        mut debugger: Option<&mut Debugger>,
+    ) -> Result<Self, LitError> {
+        let mut data = Self::new();
+        let mut state = ParserState::with_limits(limits);
+        let mut warnings = vec![];
+
+        for (i, layer) in layers.iter_mut().enumerate() {
+            state.begin_layer(i);
+            read_layer(
+                layer,
+                &mut data,
+                &mut state,
+                policy,
+                &mut warnings,
+                debugger.as_deref_mut(),
+            )?;
+        }
+
+        // building resources uploads them, which isn't needed to report errors
+        if policy != LoadPolicy::ReportOnly {
+            data.resource_dict = Some(state.build_resource_dict()?);
+        }
+        data.state = state;
+        data.warnings = warnings;
+
+        Ok(data)
+    }
+
+    /// Take the data read from data files that changed while the game was running. Changed
+    /// resources are swapped in place, so that handles to them keep working, and named numbers
+    /// and strings keep the values the game gave them.
+    pub fn reload(&mut self, mut new: GameData) -> Result<ReloadReport, LitError> {
+        let report = match (self.resource_dict.as_mut(), new.resource_dict.take()) {
+            (Some(rd), Some(new_rd)) => rd.merge(new_rd),
+            _ => return Err(LitError::NoResources),
+        };
+
+        new.state.carry_over(&self.state);
+        self.name = new.name;
+        self.state = new.state;
+        self.scripts = new.scripts;
+        self.warnings = new.warnings;
+        Ok(report)
+    }
+
+    /// Collect the state that is kept in save files.
+    pub fn save_data(&self, saved_at: u64) -> SaveData {
+        let mut save = SaveData::new(self.name.clone(), saved_at);
+        save.rng_state = self.state.rng.state();
+        save.variables = self.state.named_values();
+
+        // unnamed variables can't be found again in another session, so they aren't saved
+        let names: HashMap<u32, &String> = self
+            .state
+            .names
+            .iter()
+            .map(|(name, id)| (*id, name))
+            .collect();
+        let timelines = &self.state.timelines;
+        save.timeline = timelines.current().to_string();
+        for (timeline, values) in timelines.stored() {
+            let values = values
+                .iter()
+                .filter_map(|(id, v)| names.get(id).map(|name| (name.to_string(), v.clone())))
+                .collect();
+            save.timelines.insert(timeline.clone(), values);
+        }
+        save.merge_rules = timelines
+            .rules()
+            .iter()
+            .filter_map(|(id, rule)| names.get(id).map(|name| (name.to_string(), *rule)))
+            .collect();
+        save
+    }
+
+    /// Restore the state kept in a save file. Variables that the data files no longer have, or
+    /// that no longer hold a plain value of the same kind as the saved one, are skipped, in every
+    /// timeline.
+    pub fn restore(&mut self, save: &SaveData) {
+        if save.game != self.name {
+            logging::log(
+                Level::Warn,
+                Target::Loader,
+                format_args!("Loading a save made in {} into {}", save.game, self.name),
+            );
+        }
+
+        for (name, value) in &save.variables {
+            if let Some(id) = self.saved_variable(&save.timeline, name, value) {
+                self.state.variables.insert(id, value.clone());
+            }
+        }
+
+        let mut timelines = BTreeMap::new();
+        for (timeline, values) in &save.timelines {
+            let values = values
+                .iter()
+                .filter_map(|(name, value)| {
+                    self.saved_variable(timeline, name, value)
+                        .map(|id| (id, value.clone()))
+                })
+                .collect();
+            timelines.insert(timeline.clone(), values);
+        }
+        let rules = save
+            .merge_rules
+            .iter()
+            .filter_map(|(name, rule)| self.state.names.get(name).map(|id| (*id, *rule)))
+            .collect();
+        self.state
+            .timelines
+            .restore(&save.timeline, timelines, rules);
+        self.state.rng = Rng::new(save.rng_state);
+    }
+
+    // the variable a saved value can be put back into, if the game still has it as the same kind
+    // of plain value
+    fn saved_variable(&self, timeline: &str, name: &str, value: &BytecodeObject) -> Option<u32> {
+        let variable = self
+            .state
+            .names
+            .get(name)
+            .and_then(|id| self.state.variables.get(id).map(|v| (*id, v)));
+        match variable {
+            Some((id, current)) if current.is_plain() && current.same_kind(value) => Some(id),
+            Some((_, current)) if current.is_plain() => {
+                logging::log(
+                    Level::Warn,
+                    Target::Loader,
+                    format_args!(
+                        "Skipping saved variable {} in timeline {}, which the game has as a {:?}",
+                        name,
+                        timeline,
+                        current.data_type(&self.state)
+                    ),
+                );
+                None
+            }
+            _ => {
+                logging::log(
+                    Level::Warn,
+                    Target::Loader,
+                    format_args!(
+                        "Skipping saved variable {} in timeline {}, which the game doesn't have",
+                        name, timeline
+                    ),
+                );
+                None
+            }
+        }
+    }
+
+    /// Get the errors that were skipped over while loading.
+    #[inline]
+    pub fn warnings(&self) -> &[LoadWarning] {
+        &self.warnings
+    }
+
+    /// Get the index of the layer that a resource was defined in.
+    #[inline]
+    pub fn resource_layer<T: Resource>(&self, handle: Handle<T>) -> Option<usize> {
+        self.resource_dict.as_ref().and_then(|rd| rd.origin(handle))
     }
 }
 
+// read every byte of a data file, decompressing it if needed
+fn read_layer_bytes<T: Read>(stream: &mut T, limits: &Limits) -> Result<Vec<u8>, LitError> {
+    // peek at the header to see if the data file is compressed
+    let mut header = [0; 4];
+    let mut header_len = 0;
+    while header_len < header.len() {
+        match stream.read(&mut header[header_len..])? {
+            0 => break,
+            n => header_len += n,
+        }
+    }
+
+    // put the header back in front of the rest of the stream
+    let stream = (&header[..header_len]).chain(stream);
+    let stream: Box<dyn Read + '_> = if lit_lz::is_compressed(&header[..header_len]) {
+        Box::new(lit_lz::Decoder::new(stream))
+    } else {
+        Box::new(stream)
+    };
+
+    // a small compressed file can expand to far more than it takes up, so reading stops just
+    // past the limit
+    let mut bytes = vec![];
+    let cap = (limits.max_data_size as u64).saturating_add(1);
+    stream.take(cap).read_to_end(&mut bytes)?;
+    if bytes.len() > limits.max_data_size {
+        return Err(LitError::DataTooLarge(limits.max_data_size));
+    }
+
+    Ok(bytes)
+}
+
+fn read_layer<T: Read>(
+    stream: &mut T,
+    data: &mut GameData,
+    state: &mut ParserState,
+    policy: LoadPolicy,
+    warnings: &mut Vec<LoadWarning>,
+    mut debugger: Option<&mut Debugger>,
+) -> Result<(), LitError> {
+    let layer = state.layer();
+    let bytes = match read_layer_bytes(stream, &state.limits) {
+        Ok(b) => b,
+        Err(e) => return policy.handle(warnings, layer, None, e),
+    };
+
+    // a file that can't be split into statements is cut off where it stops making sense. when
+    // loading strictly, nothing is evaluated
+    let (statements, split_error) = split_statements(&bytes, &state.limits);
+    if let Some(e) = split_error {
+        policy.handle(warnings, layer, None, e)?;
+    }
+
+    // every statement is verified before any of them are evaluated, so that loading strictly
+    // never evaluates part of a file that turns out to be unsound
+    let mut verifier = Verifier::new(&state.limits);
+    let mut sound = Vec::with_capacity(statements.len());
+    for statement in statements {
+        match verifier.check(&bytes, statement.clone()) {
+            Ok(()) => sound.push(statement),
+            Err(e) => policy.handle(warnings, layer, Some(statement.start), e)?,
+        }
+    }
+
+    if let Err(e) = verifier.finish() {
+        policy.handle(warnings, layer, None, e)?;
+    }
+
+    for statement in sound {
+        let offset = statement.start;
+        let code = &bytes[statement.clone()];
+        if let Some(debugger) = debugger.as_mut() {
+            debugger.before_statement(layer, offset, code, state)?;
+        }
+
+        if let Err(e) = eval::eval(&mut &bytes[statement], data, state) {
+            if let Some(debugger) = debugger.as_mut() {
+                debugger.on_error(layer, offset, code, &e, state)?;
+            }
+            policy.handle(warnings, layer, Some(offset), e)?;
+        }
+    }
+
+    Ok(())
+}
+
 impl Bytecode for GameData {
     fn read<T: Read>(stream: &mut T) -> Result<Self, LitError> {
-        let mut data = Self::new();
-        let mut state = ParserState::new();
+        Self::read_layers(&mut [stream])
+    }
+}
 
-        'parse: loop {
-            match eval::eval(stream, &mut data, &mut state) {
-                Err(e) => {
-                    eprintln!("Error encountered: {}", e);
-                    break 'parse;
-                }
-                Ok(false) => break 'parse,
-                Ok(true) => {}
-            } // go until error is encountered
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(variables: Vec<(&str, BytecodeObject)>) -> GameData {
+        let mut data = GameData::new();
+        let state = data.state_mut();
+        for (local, (name, value)) in (1..).zip(variables) {
+            state.name_variable(local, name.to_string());
+            let id = state.global_id(local);
+            state.register_variable(id, value).unwrap();
         }
+        data
+    }
 
-        data.resource_dict = Some(state.into_resource_dict()?);
+    fn value<'a>(data: &'a GameData, name: &str) -> &'a BytecodeObject {
+        let state = data.state();
+        &state.variables[&state.names[name]]
+    }
 
-        Ok(data)
+    #[test]
+    fn decompressed_data_files_are_limited() {
+        // a few kilobytes that expand to a megabyte
+        let mut encoder = lit_lz::Encoder::new(vec![]).unwrap();
+        encoder.write_all(&vec![0; 1024 * 1024]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.len() < 16 * 1024);
+
+        let mut limits = Limits::new();
+        limits.max_data_size = 64 * 1024;
+        assert!(matches!(
+            read_layer_bytes(&mut &compressed[..], &limits),
+            Err(LitError::DataTooLarge(_))
+        ));
+
+        limits.max_data_size = 1024 * 1024;
+        let bytes = read_layer_bytes(&mut &compressed[..], &limits).unwrap();
+        assert_eq!(bytes.len(), 1024 * 1024);
+    }
+
+    #[test]
+    fn restore_skips_values_of_another_kind() {
+        let mut data = game(vec![
+            ("HEALTH", BytecodeObject::Numeric8(3)),
+            ("ROOM", BytecodeObject::Str(String::from("cellar"))),
+        ]);
+
+        let mut save = data.save_data(0);
+        save.rng_state = 99;
+        save.variables
+            .insert(String::from("HEALTH"), BytecodeObject::Numeric32(300));
+        save.variables
+            .insert(String::from("ROOM"), BytecodeObject::Numeric8(1));
+        save.variables
+            .insert(String::from("GONE"), BytecodeObject::Numeric8(1));
+        data.restore(&save);
+
+        assert_eq!(value(&data, "HEALTH"), &BytecodeObject::Numeric32(300));
+        assert_eq!(
+            value(&data, "ROOM"),
+            &BytecodeObject::Str(String::from("cellar"))
+        );
+        assert!(!data.state().names.contains_key("GONE"));
+        assert_eq!(data.state().rng.state(), 99);
+    }
+
+    #[test]
+    fn timelines_survive_a_save() {
+        let mut data = game(vec![
+            ("HEALTH", BytecodeObject::Numeric8(3)),
+            ("ROOM", BytecodeObject::Str(String::from("cellar"))),
+        ]);
+        let health = data.state().names["HEALTH"];
+        let state = data.state_mut();
+        state.timelines.fork("past", &state.variables).unwrap();
+        state.timelines.set_rule(health, MergeRule::Max);
+        state
+            .timelines
+            .switch("past", &mut state.variables)
+            .unwrap();
+        state.variables.insert(health, BytecodeObject::Numeric8(7));
+
+        let save = data.save_data(0);
+        let mut bytes = vec![];
+        save.write(&mut bytes).unwrap();
+        let save = SaveData::read(&mut &bytes[..]).unwrap();
+
+        let mut loaded = game(vec![
+            ("HEALTH", BytecodeObject::Numeric8(0)),
+            ("ROOM", BytecodeObject::Str(String::new())),
+        ]);
+        loaded.restore(&save);
+        let timelines = &loaded.state().timelines;
+        assert_eq!(timelines.current(), "past");
+        assert_eq!(value(&loaded, "HEALTH"), &BytecodeObject::Numeric8(7));
+        assert_eq!(
+            timelines
+                .get(MAIN_TIMELINE, health, &loaded.state().variables)
+                .unwrap(),
+            &BytecodeObject::Numeric8(3)
+        );
+        assert_eq!(timelines.rules().get(&health), Some(&MergeRule::Max));
+    }
+
+    // statements that name a variable, define it as an object, or create a black 1x1 texture
+    fn name(local: u8, name: &str) -> Vec<u8> {
+        let mut bytes = vec![0, 8, 0, 0, 0, local, 4, name.len() as u8];
+        bytes.extend_from_slice(name.as_bytes());
+        bytes
+    }
+
+    fn def(local: u8, object: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0, 2, 0, 0, 0, local];
+        bytes.extend_from_slice(object);
+        bytes
+    }
+
+    fn texture(local: u8) -> Vec<u8> {
+        vec![0, 4, 0, 0, 0, local, 1, 1, 1, 1, 5, 3, 1, 0, 1, 0, 1, 0]
+    }
+
+    fn load(layers: &[Vec<Vec<u8>>]) -> GameData {
+        let layers: Vec<Vec<u8>> = layers.iter().map(|l| l.concat()).collect();
+        let mut streams: Vec<&[u8]> = layers.iter().map(|l| &l[..]).collect();
+        let data = GameData::read_layers_with(&mut streams, LoadPolicy::ReportOnly, Limits::new())
+            .unwrap();
+        assert!(data.warnings().is_empty(), "{:?}", data.warnings());
+        data
+    }
+
+    #[test]
+    fn layers_number_their_own_variables() {
+        let layer = |name_of_1: &str, value: u8| {
+            vec![
+                name(1, name_of_1),
+                def(1, &[1, value]),
+                def(2, &[5, 1, 6, 0, 0, 0, 1]),
+            ]
+        };
+        let data = load(&[layer("A", 1), layer("B", 2)]);
+        let state = data.state();
+
+        // every layer's 1 and 2 are variables of their own, and refer to each other
+        let tuple = |v| BytecodeObject::Tuple(vec![BytecodeObject::Numeric8(v)]);
+        assert_eq!(state.names["A"], 1);
+        assert_eq!(state.names["B"], 3);
+        assert_eq!(state.variables.len(), 4);
+        assert_eq!(state.variables[&2], tuple(1));
+        assert_eq!(state.variables[&4], tuple(2));
+        assert_eq!(state.variable_layers[&2], 0);
+        assert_eq!(state.variable_layers[&4], 1);
+    }
+
+    #[test]
+    fn names_override_variables_of_earlier_layers() {
+        let base = || {
+            vec![
+                name(1, "A"),
+                def(1, &[1, 1]),
+                name(2, "T"),
+                texture(2),
+                vec![0, 5, 6, 0, 0, 0, 2, 1, 0, 5, 3, 1, 255, 1, 0, 1, 0],
+            ]
+        };
+        let data = load(&[base()]);
+        assert!(data.state().color_ids.contains_key(&2));
+
+        // the second layer uses other local ids for the same names
+        let data = load(&[
+            base(),
+            vec![
+                name(5, "A"),
+                def(5, &[1, 7]),
+                def(6, &[5, 1, 6, 0, 0, 0, 5]),
+                name(3, "T"),
+                texture(3),
+            ],
+        ]);
+        let state = data.state();
+        assert_eq!(state.names.len(), 2);
+        assert_eq!(state.names["A"], 1);
+        assert_eq!(state.variables[&1], BytecodeObject::Numeric8(7));
+        assert_eq!(state.variable_layers[&1], 1);
+        assert_eq!(
+            state.variables[&3],
+            BytecodeObject::Tuple(vec![BytecodeObject::Numeric8(7)])
+        );
+
+        // the texture that replaced T doesn't have the colors of the old one
+        assert_eq!(state.names["T"], 2);
+        assert_eq!(state.variable_layers[&2], 1);
+        assert!(!state.color_ids.contains_key(&2));
+    }
+
+    #[test]
+    fn objects_are_translated_into_global_ids() {
+        let mut state = ParserState::new();
+        state.begin_layer(0);
+        assert_eq!(state.global_id(7), 1);
+        state.begin_layer(1);
+        let object = BytecodeObject::Tuple(vec![
+            BytecodeObject::VarInvocation(7),
+            BytecodeObject::Numeric8(7),
+            BytecodeObject::Tuple(vec![BytecodeObject::VarInvocation(7)]),
+        ]);
+        assert_eq!(
+            state.globalize(object),
+            BytecodeObject::Tuple(vec![
+                BytecodeObject::VarInvocation(2),
+                BytecodeObject::Numeric8(7),
+                BytecodeObject::Tuple(vec![BytecodeObject::VarInvocation(2)]),
+            ])
+        );
     }
 }
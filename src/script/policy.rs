@@ -0,0 +1,195 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// script/policy.rs - Decide what happens when a data file contains errors.
+
+use crate::LitError;
+use std::{fmt, str::FromStr};
+
+/// What to do when a statement in a data file can't be loaded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoadPolicy {
+    /// Fail on the first error.
+    Strict,
+    /// Skip the bad statement and keep loading, collecting every error as a warning.
+    Lenient,
+    /// Load like `Lenient`, but only to report the errors. No resources are built, so the game
+    /// data can't be run.
+    ReportOnly,
+}
+
+impl FromStr for LoadPolicy {
+    type Err = LitError;
+
+    fn from_str(s: &str) -> Result<Self, LitError> {
+        match s {
+            "strict" => Ok(LoadPolicy::Strict),
+            "lenient" => Ok(LoadPolicy::Lenient),
+            "report" | "report-only" => Ok(LoadPolicy::ReportOnly),
+            _ => Err(LitError::UnknownLoadPolicy(s.to_string())),
+        }
+    }
+}
+
+/// An error that was skipped over while loading.
+#[derive(Debug)]
+pub struct LoadWarning {
+    /// The index of the data file the error is in.
+    pub layer: usize,
+    /// The offset of the statement the error is in, if the error belongs to a statement.
+    pub offset: Option<usize>,
+    pub error: LitError,
+}
+
+impl fmt::Display for LoadWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(
+                f,
+                "layer {}, statement at {:#X}: {}",
+                self.layer, offset, self.error
+            ),
+            None => write!(f, "layer {}: {}", self.layer, self.error),
+        }
+    }
+}
+
+impl LoadPolicy {
+    /// Handle an error according to the policy. Strict loading fails with the error, while the
+    /// others record it and carry on.
+    pub fn handle(
+        self,
+        warnings: &mut Vec<LoadWarning>,
+        layer: usize,
+        offset: Option<usize>,
+        error: LitError,
+    ) -> Result<(), LitError> {
+        match self {
+            LoadPolicy::Strict => Err(match (offset, error) {
+                // verification errors already say where they are
+                (_, e @ LitError::Verify { .. }) | (None, e) => e,
+                (Some(offset), e) => LitError::Statement {
+                    layer,
+                    offset,
+                    error: Box::new(e),
+                },
+            }),
+            LoadPolicy::Lenient | LoadPolicy::ReportOnly => {
+                warnings.push(LoadWarning {
+                    layer,
+                    offset,
+                    error,
+                });
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GameData, Limits, VerifyError};
+
+    // defines A = 1
+    const GOOD: &[u8] = &[0, 8, 0, 0, 0, 1, 4, 1, b'A', 0, 2, 0, 0, 0, 1, 1, 1];
+    // flips a texture that doesn't exist, then defines B = 5
+    const BAD: &[u8] = &[
+        0, 15, 6, 0, 0, 0, 9, 0, 8, 0, 0, 0, 2, 4, 1, b'B', 0, 2, 0, 0, 0, 2, 1, 5,
+    ];
+
+    fn load(policy: LoadPolicy, layers: &[&[u8]]) -> Result<GameData, LitError> {
+        GameData::read_layers_with(&mut layers.to_vec(), policy, Limits::new())
+    }
+
+    fn defines(data: &GameData, name: &str) -> bool {
+        data.state().names.contains_key(name)
+    }
+
+    #[test]
+    fn strict_loading_fails_on_the_first_error() {
+        assert!(load(LoadPolicy::Strict, &[GOOD]).is_ok());
+        assert!(matches!(
+            load(LoadPolicy::Strict, &[GOOD, BAD]),
+            Err(LitError::Verify {
+                offset: 2,
+                error: VerifyError::UndefinedVariable(9)
+            })
+        ));
+    }
+
+    #[test]
+    fn lenient_loading_skips_bad_statements() {
+        for &policy in [LoadPolicy::Lenient, LoadPolicy::ReportOnly].iter() {
+            let data = load(policy, &[GOOD, BAD]).unwrap();
+            assert!(defines(&data, "A") && defines(&data, "B"));
+            assert_eq!(data.resource_dict.is_some(), policy == LoadPolicy::Lenient);
+
+            let warnings = data.warnings();
+            assert_eq!(warnings.len(), 1);
+            assert_eq!((warnings[0].layer, warnings[0].offset), (1, Some(0)));
+            assert!(matches!(
+                warnings[0].error,
+                LitError::Verify { offset: 2, .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn files_that_stop_making_sense_are_cut_off() {
+        let mut cut_off = GOOD.to_vec();
+        cut_off.extend_from_slice(&[0, 0x63, 1, 2, 3]);
+        assert!(load(LoadPolicy::Strict, &[&cut_off]).is_err());
+
+        let data = load(LoadPolicy::Lenient, &[&cut_off]).unwrap();
+        assert!(defines(&data, "A"));
+        let warnings = data.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].layer, warnings[0].offset), (0, None));
+        assert_eq!(
+            warnings[0].to_string(),
+            format!("layer 0: {}", warnings[0].error)
+        );
+    }
+
+    #[test]
+    fn strict_errors_say_which_statement_failed() {
+        let mut warnings = vec![];
+        let error = LoadPolicy::Strict
+            .handle(&mut warnings, 2, Some(0x10), LitError::DivideByZero)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            LitError::Statement {
+                layer: 2,
+                offset: 0x10,
+                ..
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Error in layer 2, statement at offset 0x10: Script attempted to divide by zero"
+        );
+        assert!(warnings.is_empty());
+
+        LoadPolicy::Lenient
+            .handle(&mut warnings, 2, Some(0x10), LitError::DivideByZero)
+            .unwrap();
+        assert_eq!(
+            warnings[0].to_string(),
+            "layer 2, statement at 0x10: Script attempted to divide by zero"
+        );
+    }
+
+    #[test]
+    fn policies_are_parsed_from_their_names() {
+        assert_eq!("strict".parse::<LoadPolicy>().unwrap(), LoadPolicy::Strict);
+        assert_eq!(
+            "lenient".parse::<LoadPolicy>().unwrap(),
+            LoadPolicy::Lenient
+        );
+        assert_eq!(
+            "report".parse::<LoadPolicy>().unwrap(),
+            LoadPolicy::ReportOnly
+        );
+        assert!("loose".parse::<LoadPolicy>().is_err());
+    }
+}
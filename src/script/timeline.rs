@@ -0,0 +1,343 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// script/timeline.rs - Alternate copies of the script variables that the game can switch between.
+
+use super::BytecodeObject;
+use crate::LitError;
+use std::collections::{BTreeMap, HashMap};
+
+/// The name of the timeline the game starts in.
+pub const MAIN_TIMELINE: &str = "main";
+
+/// How a variable is merged when another timeline is merged into the current one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MergeRule {
+    /// Keep the value from the current timeline. This is the default.
+    Keep = 0,
+    /// Take the value from the other timeline.
+    Take = 1,
+    /// Take the larger number.
+    Max = 2,
+    /// Take the smaller number.
+    Min = 3,
+}
+
+impl MergeRule {
+    pub fn from_u8(val: u8) -> Result<Self, LitError> {
+        match val {
+            0 => Ok(MergeRule::Keep),
+            1 => Ok(MergeRule::Take),
+            2 => Ok(MergeRule::Max),
+            3 => Ok(MergeRule::Min),
+            _ => Err(LitError::BytecodeRead8(val)),
+        }
+    }
+
+    // the value a variable ends up with. numbers are compared as numbers, whatever their size
+    fn merge(self, ours: &BytecodeObject, theirs: &BytecodeObject) -> BytecodeObject {
+        let numbers = match (number(ours), number(theirs)) {
+            (Some(o), Some(t)) => Some((o, t)),
+            _ => None,
+        };
+
+        match (self, numbers) {
+            (MergeRule::Take, _) => theirs,
+            (MergeRule::Max, Some((o, t))) if t > o => theirs,
+            (MergeRule::Min, Some((o, t))) if t < o => theirs,
+            _ => ours,
+        }
+        .clone()
+    }
+}
+
+#[inline]
+fn number(object: &BytecodeObject) -> Option<i32> {
+    match *object {
+        BytecodeObject::Numeric8(v) => Some(v as i32),
+        BytecodeObject::Numeric16(v) => Some(v as i32),
+        BytecodeObject::Numeric32(v) => Some(v),
+        _ => None,
+    }
+}
+
+/// Named copies of the script variables. The variables of the current timeline are the ones in
+/// the parser state, and every other timeline keeps its own copy of the plain values, so
+/// switching timelines swaps the copies around. Textures and palettes are shared by every
+/// timeline.
+#[derive(Debug, Clone)]
+pub struct Timelines {
+    current: String,
+    others: BTreeMap<String, HashMap<u32, BytecodeObject>>,
+    rules: HashMap<u32, MergeRule>,
+    // bumped whenever the timelines change, so that the rewind buffer can tell cheaply
+    generation: u64,
+}
+
+impl Timelines {
+    pub fn new() -> Self {
+        Self {
+            current: String::from(MAIN_TIMELINE),
+            others: BTreeMap::new(),
+            rules: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    #[inline]
+    pub fn current(&self) -> &str {
+        &self.current
+    }
+
+    /// A number that changes whenever the timelines do. The values of the variables in the
+    /// current timeline don't count, since they are kept in the parser state.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The names of every timeline, in order.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.others.keys().map(|n| n.as_str()).collect();
+        names.push(&self.current);
+        names.sort_unstable();
+        names
+    }
+
+    #[inline]
+    pub fn exists(&self, name: &str) -> bool {
+        name == self.current || self.others.contains_key(name)
+    }
+
+    /// Start a new timeline that branches off from the current one as it is now. The game stays
+    /// in the current timeline.
+    pub fn fork(
+        &mut self,
+        name: &str,
+        variables: &HashMap<u32, BytecodeObject>,
+    ) -> Result<(), LitError> {
+        if self.exists(name) {
+            return Err(LitError::TimelineExists(name.to_string()));
+        }
+
+        self.others
+            .insert(name.to_string(), plain_values(variables));
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Make another timeline the current one, keeping the values of the one that is left.
+    /// Variables that the other timeline doesn't have are removed.
+    pub fn switch(
+        &mut self,
+        name: &str,
+        variables: &mut HashMap<u32, BytecodeObject>,
+    ) -> Result<(), LitError> {
+        if name == self.current {
+            return Ok(());
+        }
+        let values = self
+            .others
+            .remove(name)
+            .ok_or_else(|| LitError::UnknownTimeline(name.to_string()))?;
+
+        let left = plain_values(variables);
+        variables.retain(|id, value| !value.is_plain() || values.contains_key(id));
+        for (id, value) in values {
+            variables.insert(id, value);
+        }
+        let previous = std::mem::replace(&mut self.current, name.to_string());
+        self.others.insert(previous, left);
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Forget a timeline. The current timeline can't be deleted.
+    pub fn delete(&mut self, name: &str) -> Result<(), LitError> {
+        if name == self.current {
+            return Err(LitError::CurrentTimeline(name.to_string()));
+        }
+        self.others
+            .remove(name)
+            .ok_or_else(|| LitError::UnknownTimeline(name.to_string()))?;
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// The value a variable has in a timeline.
+    pub fn get<'a>(
+        &'a self,
+        name: &str,
+        id: u32,
+        variables: &'a HashMap<u32, BytecodeObject>,
+    ) -> Result<&'a BytecodeObject, LitError> {
+        let values = if name == self.current {
+            variables
+        } else {
+            self.others
+                .get(name)
+                .ok_or_else(|| LitError::UnknownTimeline(name.to_string()))?
+        };
+        values.get(&id).ok_or(LitError::VariableNotFound(id))
+    }
+
+    /// The variables whose values differ between the current timeline and another one, including
+    /// the ones that only one of them has, in order.
+    pub fn diff(
+        &self,
+        name: &str,
+        variables: &HashMap<u32, BytecodeObject>,
+    ) -> Result<Vec<u32>, LitError> {
+        if name == self.current {
+            return Ok(vec![]);
+        }
+        let theirs = self
+            .others
+            .get(name)
+            .ok_or_else(|| LitError::UnknownTimeline(name.to_string()))?;
+
+        let mut ids: Vec<u32> = theirs
+            .iter()
+            .filter(|(id, value)| variables.get(id) != Some(value))
+            .map(|(id, _)| *id)
+            .chain(
+                variables
+                    .iter()
+                    .filter(|(id, value)| value.is_plain() && !theirs.contains_key(id))
+                    .map(|(id, _)| *id),
+            )
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// The copies of every timeline other than the current one, by name.
+    #[inline]
+    pub fn stored(&self) -> &BTreeMap<String, HashMap<u32, BytecodeObject>> {
+        &self.others
+    }
+
+    /// How the variables that have a rule are merged.
+    #[inline]
+    pub fn rules(&self) -> &HashMap<u32, MergeRule> {
+        &self.rules
+    }
+
+    /// Replace every timeline, e.g. when a save is loaded. The values of the current timeline
+    /// are the ones in the parser state, so a copy of it in `others` is ignored.
+    pub fn restore(
+        &mut self,
+        current: &str,
+        mut others: BTreeMap<String, HashMap<u32, BytecodeObject>>,
+        rules: HashMap<u32, MergeRule>,
+    ) {
+        others.remove(current);
+        self.current = current.to_string();
+        self.others = others;
+        self.rules = rules;
+        self.generation += 1;
+    }
+
+    /// Set how a variable is merged. Variables without a rule keep their current value.
+    #[inline]
+    pub fn set_rule(&mut self, id: u32, rule: MergeRule) {
+        if self.rules.insert(id, rule) != Some(rule) {
+            self.generation += 1;
+        }
+    }
+
+    /// Merge another timeline into the current one, following the rule of each variable that
+    /// differs. The other timeline is kept. Returns the number of variables that changed.
+    pub fn merge(
+        &self,
+        name: &str,
+        variables: &mut HashMap<u32, BytecodeObject>,
+    ) -> Result<usize, LitError> {
+        let mut changed = 0;
+        for id in self.diff(name, variables)? {
+            let theirs = match self.others[name].get(&id) {
+                Some(theirs) => theirs,
+                // a variable that only the current timeline has is always kept
+                None => continue,
+            };
+            let rule = self.rules.get(&id).copied().unwrap_or(MergeRule::Keep);
+            let merged = match variables.get(&id) {
+                Some(ours) => rule.merge(ours, theirs),
+                // a variable that only the other timeline has is always taken
+                None => theirs.clone(),
+            };
+
+            if variables.get(&id) != Some(&merged) {
+                variables.insert(id, merged);
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Translate the variables of every timeline to new ids, e.g. after the data files were
+    /// reloaded. Variables without a new id are dropped.
+    pub fn remap(&mut self, ids: &HashMap<u32, u32>) {
+        for values in self.others.values_mut() {
+            *values = values
+                .drain()
+                .filter_map(|(id, value)| ids.get(&id).map(|new| (*new, value)))
+                .collect();
+        }
+        self.rules = self
+            .rules
+            .drain()
+            .filter_map(|(id, rule)| ids.get(&id).map(|new| (*new, rule)))
+            .collect();
+        self.generation += 1;
+    }
+}
+
+fn plain_values(variables: &HashMap<u32, BytecodeObject>) -> HashMap<u32, BytecodeObject> {
+    variables
+        .iter()
+        .filter(|(_, v)| v.is_plain())
+        .map(|(id, v)| (*id, v.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(values: &[(u32, i32)]) -> HashMap<u32, BytecodeObject> {
+        values
+            .iter()
+            .map(|(id, v)| (*id, BytecodeObject::Numeric32(*v)))
+            .collect()
+    }
+
+    #[test]
+    fn switch_removes_variables_the_other_timeline_lacks() {
+        let mut timelines = Timelines::new();
+        let mut vars = variables(&[(1, 1)]);
+        timelines.fork("other", &vars).unwrap();
+        vars.insert(2, BytecodeObject::Numeric32(2));
+        vars.insert(3, BytecodeObject::Palette);
+
+        timelines.switch("other", &mut vars).unwrap();
+        assert_eq!(vars.get(&2), None);
+        assert_eq!(vars.get(&3), Some(&BytecodeObject::Palette));
+
+        timelines.switch(MAIN_TIMELINE, &mut vars).unwrap();
+        assert_eq!(vars.get(&2), Some(&BytecodeObject::Numeric32(2)));
+    }
+
+    #[test]
+    fn diff_counts_variables_either_timeline_lacks() {
+        let mut timelines = Timelines::new();
+        let mut vars = variables(&[(1, 1), (2, 2)]);
+        timelines.fork("other", &vars).unwrap();
+        vars.remove(&1);
+        vars.insert(3, BytecodeObject::Numeric32(3));
+        assert_eq!(timelines.diff("other", &vars).unwrap(), vec![1, 3]);
+
+        // a variable only the current timeline has is kept, and one only the other has is taken
+        assert_eq!(timelines.merge("other", &mut vars).unwrap(), 1);
+        assert_eq!(vars, variables(&[(1, 1), (2, 2), (3, 3)]));
+    }
+}
@@ -1,9 +1,9 @@
 // Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
 // script/types.rs - Types used in bytecode reading.
 
-use super::{Bytecode, ParserState};
-use crate::{draw::{DrawBuffer, DrawHandle}, Color, LitError};
-use std::io::prelude::*;
+use super::{Bytecode, Limits, ParserState};
+use crate::{draw::{DrawBuffer, DrawHandle, IndexedBuffer}, Color, LitError};
+use std::{convert::TryFrom, io::prelude::*};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataType {
@@ -14,10 +14,24 @@ pub enum DataType {
     Str,
     Tuple,
     DrawBufferImg,
+    IndexedImg,
+    Palette,
     VarInvocation,
 }
 
-#[derive(Debug, Clone)]
+impl DataType {
+    /// Whether variables of this type hold a material, which is built into a resource. References
+    /// to materials are kept instead of copying them.
+    #[inline]
+    pub fn is_material(self) -> bool {
+        matches!(
+            self,
+            DataType::DrawBufferImg | DataType::IndexedImg | DataType::Palette
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum BytecodeObject {
     Numeric8(u8),
     Numeric16(i16),
@@ -26,6 +40,9 @@ pub enum BytecodeObject {
     Tuple(Vec<BytecodeObject>),
     VarInvocation(u32),
     DrawBufferImg(DrawBuffer), 
+    IndexedImg(IndexedBuffer),
+    // the colors of a palette are kept with the color ids
+    Palette,
 }
 
 impl BytecodeObject {
@@ -37,8 +54,12 @@ impl BytecodeObject {
             BytecodeObject::Numeric32(_) => DataType::Numeric32,
             BytecodeObject::Str(_) => DataType::Str,
             BytecodeObject::Tuple(_) => DataType::Tuple,
-            BytecodeObject::VarInvocation(i) => state.get_variable(i).unwrap().data_type(state),
+            BytecodeObject::VarInvocation(i) => state
+                .get_variable(i)
+                .map_or(DataType::Unknown, |v| v.data_type(state)),
             BytecodeObject::DrawBufferImg(_) => DataType::DrawBufferImg,
+            BytecodeObject::IndexedImg(_) => DataType::IndexedImg,
+            BytecodeObject::Palette => DataType::Palette,
         }
     }
 
@@ -88,7 +109,9 @@ impl BytecodeObject {
             BytecodeObject::Str(ref u) => Ok(u.to_string()),
             BytecodeObject::Tuple(ref s) => Ok(format!("{:?}", s)),
             BytecodeObject::VarInvocation(u) => Self::stringify(state.get_variable(u)?, state),
-            BytecodeObject::ImgMaterial(ref i) => Ok(format!("{}", i)),
+            BytecodeObject::DrawBufferImg(ref i) => Ok(format!("{}", i)),
+            BytecodeObject::IndexedImg(ref i) => Ok(format!("{}", i)),
+            BytecodeObject::Palette => Ok(String::from("Palette")),
         }
     }
 
@@ -158,8 +181,80 @@ impl BytecodeObject {
     }
 }
 
-impl Bytecode for BytecodeObject {
-    fn read<T: Read>(stream: &mut T) -> Result<Self, LitError> {
+impl BytecodeObject {
+    /// Whether the object is a plain value, i.e. a number, a string or a tuple of plain values.
+    /// Only plain values can be written back out.
+    pub fn is_plain(&self) -> bool {
+        match *self {
+            BytecodeObject::Numeric8(_)
+            | BytecodeObject::Numeric16(_)
+            | BytecodeObject::Numeric32(_)
+            | BytecodeObject::Str(_) => true,
+            BytecodeObject::Tuple(ref t) => t.iter().all(|o| o.is_plain()),
+            _ => false,
+        }
+    }
+
+    /// Whether two plain values are of the same kind, so that one can take the place of the
+    /// other. Numbers of any width are the same kind, and tuples match element by element.
+    pub fn same_kind(&self, other: &BytecodeObject) -> bool {
+        use BytecodeObject::*;
+        match (self, other) {
+            (Numeric8(_), Numeric8(_) | Numeric16(_) | Numeric32(_))
+            | (Numeric16(_), Numeric8(_) | Numeric16(_) | Numeric32(_))
+            | (Numeric32(_), Numeric8(_) | Numeric16(_) | Numeric32(_))
+            | (Str(_), Str(_)) => true,
+            (Tuple(a), Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.same_kind(b))
+            }
+            _ => false,
+        }
+    }
+
+    /// Write a plain value in the same format it is read in.
+    pub fn write<T: Write>(&self, stream: &mut T) -> Result<(), LitError> {
+        match *self {
+            BytecodeObject::Numeric8(val) => stream.write_all(&[1, val])?,
+            BytecodeObject::Numeric16(val) => {
+                stream.write_all(&[2])?;
+                stream.write_all(&val.to_be_bytes())?;
+            }
+            BytecodeObject::Numeric32(val) => {
+                stream.write_all(&[3])?;
+                stream.write_all(&val.to_be_bytes())?;
+            }
+            BytecodeObject::Str(ref val) => {
+                // the length is stored in a single byte
+                let len = u8::try_from(val.len())
+                    .map_err(|_| LitError::StringTooLong(val.len(), u8::MAX as usize))?;
+                stream.write_all(&[4, len])?;
+                stream.write_all(val.as_bytes())?;
+            }
+            BytecodeObject::Tuple(ref t) => {
+                let len =
+                    u8::try_from(t.len()).map_err(|_| LitError::ValueTooLarge(u8::MAX as usize))?;
+                stream.write_all(&[5, len])?;
+                for element in t {
+                    element.write(stream)?;
+                }
+            }
+            _ => return Err(LitError::NotPlainValue),
+        }
+        Ok(())
+    }
+
+    /// Read an object, making sure it stays within a set of limits.
+    pub fn read_limited<T: Read>(stream: &mut T, limits: &Limits) -> Result<Self, LitError> {
+        Self::read_nested(stream, limits, 0)
+    }
+
+    fn read_nested<T: Read>(
+        stream: &mut T,
+        limits: &Limits,
+        depth: usize,
+    ) -> Result<Self, LitError> {
+        limits.check_depth(depth)?;
+
         // variable type is signified by an 8-bit number
         let mut buffer = [0; 1];
         stream.read_exact(&mut buffer)?;
@@ -189,6 +284,7 @@ impl Bytecode for BytecodeObject {
                 // UTF-8 string
                 // first, get the length
                 stream.read_exact(&mut buffer)?;
+                limits.check_string(buffer[0] as usize)?;
 
                 // then, read into buffer
                 let mut buffer = vec![0; buffer[0] as usize];
@@ -208,7 +304,7 @@ impl Bytecode for BytecodeObject {
                 let mut buffer = Vec::with_capacity(element_num as usize);
 
                 for _ in 0..element_num {
-                    buffer.push(BytecodeObject::read(stream)?);
+                    buffer.push(BytecodeObject::read_nested(stream, limits, depth + 1)?);
                 }
 
                 Ok(BytecodeObject::Tuple(buffer))
@@ -225,3 +321,9 @@ impl Bytecode for BytecodeObject {
         }
     }
 }
+
+impl Bytecode for BytecodeObject {
+    fn read<T: Read>(stream: &mut T) -> Result<Self, LitError> {
+        Self::read_limited(stream, &Limits::new())
+    }
+}
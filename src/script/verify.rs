@@ -0,0 +1,743 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// script/verify.rs - Validate an entire data file before any of it is evaluated.
+
+use super::{DataType, Limits, MergeRule};
+use crate::LitError;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum VerifyError {
+    #[error("unexpected end of data")]
+    UnexpectedEof,
+    #[error("unknown statement {0:#06X}")]
+    UnknownStatement(u16),
+    #[error("unknown object type {0:#04X}")]
+    UnknownObject(u8),
+    #[error("unknown instruction {0:#04X}")]
+    UnknownInstruction(u8),
+    #[error("string is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("expected {0}, found {1:?}")]
+    WrongType(&'static str, DataType),
+    #[error("variable {0:X?} is used before it is defined")]
+    UndefinedVariable(u32),
+    #[error("colors must have 3 or 4 numerical members")]
+    InvalidColor,
+    #[error("value {0} is out of range")]
+    OutOfRange(i32),
+    #[error("jump target {0} is not the start of an instruction")]
+    InvalidJumpTarget(u32),
+    #[error("instruction pops a value off of an empty stack")]
+    StackUnderflow,
+    #[error("stack depth differs between branches ({0} and {1})")]
+    StackMismatch(usize, usize),
+    #[error("statement has unexpected trailing data")]
+    TrailingData,
+}
+
+// the verifier's view of an object read from the file
+#[derive(Debug, Clone)]
+enum Object {
+    Number(i32),
+    Str,
+    Tuple(Vec<Object>),
+    Var(u32),
+}
+
+// what is known about a variable's value at load time
+#[derive(Debug, Clone)]
+enum Value {
+    Number(i32),
+    Str,
+    Tuple(Vec<Value>),
+    Texture,
+    IndexedTexture,
+    Palette,
+}
+
+impl Value {
+    fn data_type(&self) -> DataType {
+        match *self {
+            Value::Number(_) => DataType::Numeric32,
+            Value::Str => DataType::Str,
+            Value::Tuple(_) => DataType::Tuple,
+            Value::Texture => DataType::DrawBufferImg,
+            Value::IndexedTexture => DataType::IndexedImg,
+            Value::Palette => DataType::Palette,
+        }
+    }
+
+    // the number of objects in the value, and how deeply it is nested
+    fn measure(&self) -> (usize, usize) {
+        match *self {
+            Value::Tuple(ref t) => t.iter().fold((1, 0), |(size, depth), v| {
+                let (s, d) = v.measure();
+                (size + s, depth.max(d + 1))
+            }),
+            _ => (1, 0),
+        }
+    }
+}
+
+// a bounded view of the data, where offsets are relative to the start of the file
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    base: usize,
+    limits: Limits,
+}
+
+impl<'a> Reader<'a> {
+    #[inline]
+    fn new(bytes: &'a [u8], base: usize, limits: Limits) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            base,
+            limits,
+        }
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.base + self.pos
+    }
+
+    #[inline]
+    fn at_end(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    #[inline]
+    fn fail<T>(&self, offset: usize, error: VerifyError) -> Result<T, LitError> {
+        Err(LitError::Verify { offset, error })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LitError> {
+        let end = self.pos.saturating_add(len);
+        match self.bytes.get(self.pos..end) {
+            Some(b) => {
+                self.pos += len;
+                Ok(b)
+            }
+            None => self.fail(self.offset(), VerifyError::UnexpectedEof),
+        }
+    }
+
+    #[inline]
+    fn u8(&mut self) -> Result<u8, LitError> {
+        Ok(self.take(1)?[0])
+    }
+
+    #[inline]
+    fn u16(&mut self) -> Result<u16, LitError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    #[inline]
+    fn u32(&mut self) -> Result<u32, LitError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    #[inline]
+    fn object(&mut self) -> Result<Object, LitError> {
+        self.nested_object(0)
+    }
+
+    // mirrors BytecodeObject::read
+    fn nested_object(&mut self, depth: usize) -> Result<Object, LitError> {
+        self.limits.check_depth(depth)?;
+
+        let offset = self.offset();
+        match self.u8()? {
+            1 => Ok(Object::Number(self.u8()? as i32)),
+            2 => Ok(Object::Number(self.u16()? as i16 as i32)),
+            3 => Ok(Object::Number(self.u32()? as i32)),
+            4 => {
+                let len = self.u8()? as usize;
+                self.limits.check_string(len)?;
+                let str_offset = self.offset();
+                if std::str::from_utf8(self.take(len)?).is_err() {
+                    return self.fail(str_offset, VerifyError::InvalidUtf8);
+                }
+                Ok(Object::Str)
+            }
+            5 => {
+                let len = self.u8()?;
+                let mut elements = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    elements.push(self.nested_object(depth + 1)?);
+                }
+                Ok(Object::Tuple(elements))
+            }
+            6 => Ok(Object::Var(self.u32()?)),
+            b => self.fail(offset, VerifyError::UnknownObject(b)),
+        }
+    }
+}
+
+// collect the ids of every variable an object refers to
+fn collect_vars(object: &Object, vars: &mut Vec<u32>) {
+    match *object {
+        Object::Var(id) => vars.push(id),
+        Object::Tuple(ref t) => t.iter().for_each(|o| collect_vars(o, vars)),
+        _ => {}
+    }
+}
+
+/// Checks the statements of a data file one at a time, keeping track of what earlier statements
+/// defined.
+pub struct Verifier {
+    limits: Limits,
+    values: HashMap<u32, Value>,
+    color_ids: HashSet<(u32, i32)>,
+    // variables used by scripts, which only need to be defined somewhere in the file
+    script_vars: Vec<(usize, u32)>,
+}
+
+impl Verifier {
+    pub fn new(limits: &Limits) -> Self {
+        Self {
+            limits: *limits,
+            values: HashMap::new(),
+            color_ids: HashSet::new(),
+            script_vars: vec![],
+        }
+    }
+
+    /// Check a single statement, as found by `split_statements`. A statement that fails the check
+    /// defines nothing, so later statements using its variables fail as well.
+    pub fn check(&mut self, bytes: &[u8], statement: Range<usize>) -> Result<(), LitError> {
+        let base = statement.start;
+        let mut r = Reader::new(&bytes[statement], base, self.limits);
+        self.statement(&mut r)?;
+        if !r.at_end() {
+            return r.fail(r.offset(), VerifyError::TrailingData);
+        }
+
+        Ok(())
+    }
+
+    /// Finish verifying a file. Scripts run after loading, so the variables they use only need to
+    /// be defined somewhere in the file.
+    pub fn finish(self) -> Result<(), LitError> {
+        for (offset, id) in self.script_vars {
+            if !self.values.contains_key(&id) {
+                return Err(LitError::Verify {
+                    offset,
+                    error: VerifyError::UndefinedVariable(id),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    // mirrors ParserState::resolve, including its limits
+    fn resolve(
+        &self,
+        offset: usize,
+        object: &Object,
+        depth: usize,
+        elements: &mut usize,
+    ) -> Result<Value, LitError> {
+        self.limits.check_depth(depth)?;
+        *elements += 1;
+        self.limits.check_elements(*elements)?;
+
+        match *object {
+            Object::Number(n) => Ok(Value::Number(n)),
+            Object::Str => Ok(Value::Str),
+            Object::Tuple(ref t) => Ok(Value::Tuple(
+                t.iter()
+                    .map(|o| self.resolve(offset, o, depth + 1, elements))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Object::Var(id) => match self.values.get(&id) {
+                Some(v) => {
+                    // check the size before copying the value
+                    let (size, value_depth) = v.measure();
+                    self.limits.check_depth(depth + value_depth)?;
+                    *elements += size;
+                    self.limits.check_elements(*elements)?;
+                    Ok(v.clone())
+                }
+                None => Err(LitError::Verify {
+                    offset,
+                    error: VerifyError::UndefinedVariable(id),
+                }),
+            },
+        }
+    }
+
+    fn value(&self, r: &mut Reader) -> Result<(usize, Value), LitError> {
+        let offset = r.offset();
+        let object = r.object()?;
+        let mut elements = 0;
+        Ok((offset, self.resolve(offset, &object, 0, &mut elements)?))
+    }
+
+    // read a number, making sure it falls within a range
+    fn number(&self, r: &mut Reader, min: i32, max: i32) -> Result<i32, LitError> {
+        match self.value(r)? {
+            (offset, Value::Number(n)) if !(min..=max).contains(&n) => {
+                r.fail(offset, VerifyError::OutOfRange(n))
+            }
+            (_, Value::Number(n)) => Ok(n),
+            (offset, v) => r.fail(offset, VerifyError::WrongType("a number", v.data_type())),
+        }
+    }
+
+    fn string(&self, r: &mut Reader) -> Result<(), LitError> {
+        match self.value(r)? {
+            (_, Value::Str) => Ok(()),
+            (offset, v) => r.fail(offset, VerifyError::WrongType("a string", v.data_type())),
+        }
+    }
+
+    fn tuple(&self, r: &mut Reader) -> Result<Vec<Value>, LitError> {
+        match self.value(r)? {
+            (_, Value::Tuple(t)) => Ok(t),
+            (offset, v) => r.fail(offset, VerifyError::WrongType("a tuple", v.data_type())),
+        }
+    }
+
+    fn color(&self, r: &mut Reader) -> Result<(), LitError> {
+        let offset = r.offset();
+        let tuple = self.tuple(r)?;
+        if (tuple.len() == 3 || tuple.len() == 4)
+            && tuple.iter().all(|v| match *v {
+                Value::Number(n) => (0..=255).contains(&n),
+                _ => false,
+            })
+        {
+            Ok(())
+        } else {
+            r.fail(offset, VerifyError::InvalidColor)
+        }
+    }
+
+    // read a variable holding a material of one of the kinds accepted
+    fn material(
+        &self,
+        r: &mut Reader,
+        what: &'static str,
+        accept: fn(&Value) -> bool,
+    ) -> Result<u32, LitError> {
+        let offset = r.offset();
+        match r.object()? {
+            Object::Var(id) => match self.values.get(&id) {
+                Some(v) if accept(v) => Ok(id),
+                Some(v) => r.fail(offset, VerifyError::WrongType(what, v.data_type())),
+                None => r.fail(offset, VerifyError::UndefinedVariable(id)),
+            },
+            _ => r.fail(offset, VerifyError::WrongType(what, DataType::Unknown)),
+        }
+    }
+
+    fn texture(&self, r: &mut Reader) -> Result<u32, LitError> {
+        self.material(r, "a texture", |v| matches!(v, Value::Texture))
+    }
+
+    // a texture that can be drawn on
+    fn drawable(&self, r: &mut Reader) -> Result<u32, LitError> {
+        self.material(r, "a texture", |v| {
+            matches!(v, Value::Texture | Value::IndexedTexture)
+        })
+    }
+
+    // draw commands take a color id, followed by a color if the id isn't registered
+    // draw commands on indexed textures register the color, like eval does
+    fn draw_color(&mut self, r: &mut Reader, texture: u32) -> Result<(), LitError> {
+        let clr_id = self.number(r, 0, 255)?;
+        if self.color_ids.contains(&(texture, clr_id)) {
+            return Ok(());
+        }
+
+        self.color(r)?;
+        if let Some(Value::IndexedTexture) = self.values.get(&texture) {
+            self.color_ids.insert((texture, clr_id));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, r: &mut Reader) -> Result<(), LitError> {
+        let offset = r.offset();
+        match r.u16()? {
+            1 => self.string(r),
+            2 => {
+                let id = r.u32()?;
+                let (_, value) = self.value(r)?;
+                self.values.insert(id, value);
+                Ok(())
+            }
+            3 | 10 | 11 => {
+                self.string(r)?;
+                self.tuple(r)?;
+                Ok(())
+            }
+            4 => {
+                let id = r.u32()?;
+                self.number(r, 0, i32::MAX)?;
+                self.number(r, 0, i32::MAX)?;
+                self.color(r)?;
+                self.values.insert(id, Value::Texture);
+                Ok(())
+            }
+            5 => {
+                let texture = self.material(r, "a texture or palette", |v| {
+                    matches!(v, Value::Texture | Value::IndexedTexture | Value::Palette)
+                })?;
+                let clr_id = self.number(r, 0, 255)?;
+                self.color(r)?;
+                self.color_ids.insert((texture, clr_id));
+                Ok(())
+            }
+            6 => {
+                let texture = self.drawable(r)?;
+                self.number(r, 0, i32::MAX)?;
+                self.number(r, 0, i32::MAX)?;
+                self.draw_color(r, texture)
+            }
+            7 => {
+                let texture = self.drawable(r)?;
+                for _ in 0..4 {
+                    self.number(r, 0, i32::MAX)?;
+                }
+                self.draw_color(r, texture)
+            }
+            8 => {
+                r.u32()?;
+                self.string(r)
+            }
+            9 => {
+                self.string(r)?;
+                let len = r.u32()? as usize;
+                let base = r.offset();
+                let body = r.take(len)?;
+                self.script(&mut Reader::new(body, base, self.limits))
+            }
+            12 | 13 => {
+                self.texture(r)?;
+                self.texture(r)?;
+                self.number(r, i16::MIN as i32, i16::MAX as i32)?;
+                self.number(r, i16::MIN as i32, i16::MAX as i32)?;
+                Ok(())
+            }
+            14 => {
+                self.texture(r)?;
+                self.color(r)
+            }
+            15..=17 => self.texture(r).map(|_| ()),
+            18 => {
+                let id = r.u32()?;
+                self.number(r, 0, i32::MAX)?;
+                self.number(r, 0, i32::MAX)?;
+                self.number(r, 0, 255)?;
+                self.values.insert(id, Value::IndexedTexture);
+                Ok(())
+            }
+            19 => {
+                let id = r.u32()?;
+                self.values.insert(id, Value::Palette);
+                Ok(())
+            }
+            20 => {
+                self.material(r, "an indexed texture", |v| {
+                    matches!(v, Value::IndexedTexture)
+                })?;
+                self.number(r, 0, 255)?;
+                self.number(r, 0, 255)?;
+                self.number(r, 0, u16::MAX as i32)?;
+                Ok(())
+            }
+            op => r.fail(offset, VerifyError::UnknownStatement(op)),
+        }
+    }
+
+    // check a script body: decode every instruction, then simulate the depth of the stack
+    fn script(&mut self, r: &mut Reader) -> Result<(), LitError> {
+        // (offset, pops, pushes, jump target, falls through)
+        let mut instrs: Vec<(usize, usize, usize, Option<u32>, bool)> = vec![];
+
+        while !r.at_end() {
+            let offset = r.offset();
+            instrs.push(match r.u8()? {
+                0x00 => (offset, 0, 0, None, false),
+                0x01 => {
+                    let object = r.object()?;
+                    let mut vars = vec![];
+                    collect_vars(&object, &mut vars);
+                    self.script_vars
+                        .extend(vars.into_iter().map(|v| (offset, v)));
+                    (offset, 0, 1, None, true)
+                }
+                op @ 0x02 | op @ 0x03 => {
+                    self.script_vars.push((offset, r.u32()?));
+                    if op == 0x02 {
+                        (offset, 0, 1, None, true)
+                    } else {
+                        (offset, 1, 0, None, true)
+                    }
+                }
+                0x10..=0x14 | 0x20..=0x25 => (offset, 2, 1, None, true),
+                0x26 => (offset, 1, 1, None, true),
+                0x30 => (offset, 0, 0, Some(r.u32()?), false),
+                0x31 => (offset, 1, 0, Some(r.u32()?), true),
+                0x40..=0x42 => (offset, 2, 0, None, true),
+                0x50 => (offset, 1, 0, None, true),
+                0x51 => (offset, 0, 0, None, true),
+                0x60..=0x62 | 0x66 => (offset, 1, 0, None, true),
+                0x63 => {
+                    self.script_vars.push((offset, r.u32()?));
+                    (offset, 1, 1, None, true)
+                }
+                0x64 => (offset, 1, 1, None, true),
+                0x65 => {
+                    self.script_vars.push((offset, r.u32()?));
+                    let rule = r.u8()?;
+                    if rule > MergeRule::Min as u8 {
+                        return r.fail(offset, VerifyError::OutOfRange(rule as i32));
+                    }
+                    (offset, 0, 0, None, true)
+                }
+                0x67 => (offset, 0, 1, None, true),
+                0x70 | 0x71 => (offset, 1, 1, None, true),
+                b => return r.fail(offset, VerifyError::UnknownInstruction(b)),
+            });
+        }
+
+        // map byte offsets to instruction indices; the end of the body is a valid target
+        let mut indices: HashMap<usize, usize> = instrs
+            .iter()
+            .enumerate()
+            .map(|(i, instr)| (instr.0 - r.base, i))
+            .collect();
+        indices.insert(r.bytes.len(), instrs.len());
+
+        let mut depths: Vec<Option<usize>> = vec![None; instrs.len() + 1];
+        let mut work = vec![(0, 0)];
+        while let Some((index, depth)) = work.pop() {
+            match depths[index] {
+                Some(d) if d == depth => continue,
+                Some(d) => {
+                    let offset = instrs.get(index).map_or(r.offset(), |i| i.0);
+                    return r.fail(offset, VerifyError::StackMismatch(d, depth));
+                }
+                None => depths[index] = Some(depth),
+            }
+
+            let (offset, pops, pushes, target, falls_through) = match instrs.get(index) {
+                Some(i) => *i,
+                None => continue,
+            };
+            if depth < pops {
+                return r.fail(offset, VerifyError::StackUnderflow);
+            }
+            let depth = depth - pops + pushes;
+
+            if let Some(target) = target {
+                match indices.get(&(target as usize)) {
+                    Some(i) => work.push((*i, depth)),
+                    None => return r.fail(offset, VerifyError::InvalidJumpTarget(target)),
+                }
+            }
+            if falls_through {
+                work.push((index + 1, depth));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// the operands of a statement: whether it starts with a variable id, how many objects follow and
+// whether it ends with the color of a draw command
+fn operands(op: u16) -> Option<(bool, usize, bool)> {
+    Some(match op {
+        1 => (false, 1, false),
+        2 | 8 => (true, 1, false),
+        3 | 10 | 11 => (false, 2, false),
+        4 | 18 => (true, 3, false),
+        5 => (false, 3, false),
+        6 => (false, 4, true),
+        7 => (false, 6, true),
+        12 | 13 => (false, 4, false),
+        14 => (false, 2, false),
+        15..=17 => (false, 1, false),
+        19 => (true, 0, false),
+        20 => (false, 4, false),
+        _ => return None,
+    })
+}
+
+// skip over a single statement without looking at what it means. returns false once the end
+// statement is reached
+fn skip_statement(r: &mut Reader) -> Result<bool, LitError> {
+    let offset = r.offset();
+    let op = r.u16()?;
+    match op {
+        0 => return Ok(false),
+        9 => {
+            // an entry point is a name followed by the length of its code
+            r.object()?;
+            let len = r.u32()? as usize;
+            r.take(len)?;
+            return Ok(true);
+        }
+        _ => {}
+    }
+
+    let (id, objects, color) = match operands(op) {
+        Some(operands) => operands,
+        None => return r.fail(offset, VerifyError::UnknownStatement(op)),
+    };
+    if id {
+        r.u32()?;
+    }
+    for _ in 0..objects {
+        r.object()?;
+    }
+
+    // a draw command only has a color if its color id isn't registered yet, which depends on
+    // the statements before it. statements start with a zero byte, since every opcode is below
+    // 256, while objects never do
+    if color && r.peek().is_some_and(|b| b != 0) {
+        r.object()?;
+    }
+
+    Ok(true)
+}
+
+/// Split a data file into its statements without checking what they mean. If the file can't be
+/// split all the way through, the error is returned along with every statement found before it.
+pub fn split_statements(bytes: &[u8], limits: &Limits) -> (Vec<Range<usize>>, Option<LitError>) {
+    let mut reader = Reader::new(bytes, 0, *limits);
+    let mut statements = vec![];
+
+    while !reader.at_end() {
+        let start = reader.offset();
+        match skip_statement(&mut reader) {
+            Ok(true) => statements.push(start..reader.offset()),
+            Ok(false) => break,
+            Err(e) => return (statements, Some(e)),
+        }
+    }
+
+    (statements, None)
+}
+
+/// Verify a data file. On failure, the error contains the offset of the offending data.
+pub fn verify(bytes: &[u8], limits: &Limits) -> Result<(), LitError> {
+    let (statements, error) = split_statements(bytes, limits);
+    if let Some(e) = error {
+        return Err(e);
+    }
+
+    let mut verifier = Verifier::new(limits);
+    for statement in statements {
+        verifier.check(bytes, statement)?;
+    }
+
+    verifier.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a script statement named "main", whose body starts 12 bytes into it
+    fn script(body: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0, 9, 4, 4, b'm', b'a', b'i', b'n'];
+        bytes.extend(&(body.len() as u32).to_be_bytes());
+        bytes.extend(body);
+        bytes
+    }
+
+    fn error(bytes: &[u8]) -> (usize, VerifyError) {
+        match verify(bytes, &Limits::new()) {
+            Err(LitError::Verify { offset, error }) => (offset, error),
+            r => panic!("expected a verify error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn accepts_a_valid_script() {
+        // x = 1; push x; jump if zero to the end; push 2; pop
+        let mut bytes = vec![0, 2, 0, 0, 0, 7, 1, 1];
+        bytes.extend(script(&[
+            0x02, 0, 0, 0, 7, 0x31, 0, 0, 0, 14, 0x01, 1, 2, 0x50,
+        ]));
+        assert!(verify(&bytes, &Limits::new()).is_ok());
+    }
+
+    #[test]
+    fn rejects_jumps_into_an_instruction() {
+        let bytes = script(&[0x01, 1, 0, 0x31, 0, 0, 0, 1]);
+        assert_eq!(error(&bytes), (15, VerifyError::InvalidJumpTarget(1)));
+
+        let bytes = script(&[0x30, 0, 0, 0, 6]);
+        assert_eq!(error(&bytes), (12, VerifyError::InvalidJumpTarget(6)));
+    }
+
+    #[test]
+    fn rejects_stack_underflow() {
+        let bytes = script(&[0x01, 1, 1, 0x10]);
+        assert_eq!(error(&bytes), (15, VerifyError::StackUnderflow));
+    }
+
+    #[test]
+    fn rejects_stack_mismatch_at_a_join() {
+        // push 0; jump if zero past the push that follows; push 1; end
+        let bytes = script(&[0x01, 1, 0, 0x31, 0, 0, 0, 11, 0x01, 1, 1, 0x00]);
+        assert_eq!(error(&bytes), (23, VerifyError::StackMismatch(1, 0)));
+    }
+
+    #[test]
+    fn rejects_undefined_variables() {
+        // scripts may use variables defined after them, but not ones that are never defined
+        let mut bytes = script(&[0x02, 0, 0, 0, 7, 0x50]);
+        bytes.extend(&[0, 2, 0, 0, 0, 7, 1, 1]);
+        assert!(verify(&bytes, &Limits::new()).is_ok());
+
+        let bytes = script(&[0x02, 0, 0, 0, 8, 0x50]);
+        assert_eq!(error(&bytes), (12, VerifyError::UndefinedVariable(8)));
+
+        // statements may only use variables defined before them
+        let bytes = [0, 15, 6, 0, 0, 0, 7];
+        assert_eq!(error(&bytes), (2, VerifyError::UndefinedVariable(7)));
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        let bytes = [0, 1, 4, 1, b'a', 0xFF];
+        match Verifier::new(&Limits::new()).check(&bytes, 0..bytes.len()) {
+            Err(LitError::Verify { offset, error }) => {
+                assert_eq!((offset, error), (5, VerifyError::TrailingData))
+            }
+            r => panic!("expected a verify error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn reports_offsets_from_the_start_of_the_file() {
+        let mut bytes = vec![0, 1, 4, 2, b'h', b'i'];
+        bytes.extend(script(&[0x01, 1, 1, 0x10]));
+        assert_eq!(error(&bytes), (21, VerifyError::StackUnderflow));
+
+        let mut bytes = vec![0, 1, 4, 2, b'h', b'i'];
+        bytes.extend(&[0x00, 0x63]);
+        assert_eq!(error(&bytes), (6, VerifyError::UnknownStatement(0x63)));
+    }
+}
@@ -0,0 +1,404 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// script/vm.rs - Virtual machine that runs scripts after the game has loaded.
+
+use super::{eval::format_log, BytecodeObject, Instruction, ParserState, Script};
+use crate::{
+    logging::{self, Target},
+    LitError,
+};
+use lit_gl_wrapper::InputEvent;
+use std::{cmp::Ordering, collections::HashSet, convert::TryFrom, mem};
+
+/// Something a script asked the game to do, which is done once the script has finished.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VmRequest {
+    /// Start rewinding time, going back some number of frames every frame.
+    Rewind(u32),
+    RewindStop,
+}
+
+pub struct Vm {
+    stack: Vec<BytecodeObject>,
+    ip: usize,
+    requests: Vec<VmRequest>,
+    // the keys that are held down, as scripts see them
+    keys: HashSet<i32>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self {
+            stack: vec![],
+            ip: 0,
+            requests: vec![],
+            keys: HashSet::new(),
+        }
+    }
+
+    /// Update the keys that are held down from a frame's input.
+    pub fn handle_input(&mut self, events: &[InputEvent]) {
+        for event in events {
+            match *event {
+                InputEvent::KeyDown(key) => self.keys.insert(key),
+                InputEvent::KeyUp(key) => self.keys.remove(&key),
+            };
+        }
+    }
+
+    /// Take the requests that scripts have made since the last time this was called.
+    #[inline]
+    pub fn take_requests(&mut self) -> Vec<VmRequest> {
+        mem::take(&mut self.requests)
+    }
+
+    #[inline]
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    #[inline]
+    pub fn stack(&self) -> &[BytecodeObject] {
+        &self.stack
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Result<BytecodeObject, LitError> {
+        self.stack.pop().ok_or(LitError::StackUnderflow)
+    }
+
+    fn pop_numbers(&mut self, state: &ParserState) -> Result<(i32, i32), LitError> {
+        let rhs = self.pop()?.as_number(state)?;
+        let lhs = self.pop()?.as_number(state)?;
+        Ok((lhs, rhs))
+    }
+
+    // compare two values, numerically if possible and by their string form otherwise
+    fn pop_ordering(&mut self, state: &ParserState) -> Result<Ordering, LitError> {
+        let rhs = self.pop()?;
+        let lhs = self.pop()?;
+
+        match (lhs.as_number(state), rhs.as_number(state)) {
+            (Ok(l), Ok(r)) => Ok(l.cmp(&r)),
+            _ => Ok(lhs.stringify(state)?.cmp(&rhs.stringify(state)?)),
+        }
+    }
+
+    #[inline]
+    fn pop_timeline(&mut self, state: &ParserState) -> Result<String, LitError> {
+        Ok(self.pop()?.as_string(state)?.to_string())
+    }
+
+    #[inline]
+    fn push_bool(&mut self, val: bool) {
+        self.stack
+            .push(BytecodeObject::Numeric8(if val { 1 } else { 0 }));
+    }
+
+    /// Run a script from the beginning until it returns, or until it runs more instructions than
+    /// the limits allow.
+    pub fn run(&mut self, script: &Script, state: &mut ParserState) -> Result<(), LitError> {
+        self.ip = 0;
+        self.stack.clear();
+
+        let max = state.limits.max_instructions;
+        let mut count = 0;
+        while self.step(script, state)? {
+            count += 1;
+            if count > max {
+                return Err(LitError::InstructionLimit(max));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run a single instruction. Returns false once the script has finished.
+    pub fn step(&mut self, script: &Script, state: &mut ParserState) -> Result<bool, LitError> {
+        let instr = match script.instructions().get(self.ip) {
+            Some(i) => i,
+            None => return Ok(false),
+        };
+        self.ip += 1;
+
+        match *instr {
+            Instruction::Return => {
+                self.ip = script.instructions().len();
+                return Ok(false);
+            }
+            Instruction::Push(ref obj) => self.stack.push(obj.clone()),
+            Instruction::Load(id) => {
+                let val = state.get_variable(id)?.clone();
+                self.stack.push(val);
+            }
+            Instruction::Store(id) => {
+                let val = self.pop()?;
+                state.assign(id, val)?;
+            }
+            Instruction::Add => {
+                let (l, r) = self.pop_numbers(state)?;
+                self.stack.push(BytecodeObject::Numeric32(l.wrapping_add(r)));
+            }
+            Instruction::Sub => {
+                let (l, r) = self.pop_numbers(state)?;
+                self.stack.push(BytecodeObject::Numeric32(l.wrapping_sub(r)));
+            }
+            Instruction::Mul => {
+                let (l, r) = self.pop_numbers(state)?;
+                self.stack.push(BytecodeObject::Numeric32(l.wrapping_mul(r)));
+            }
+            Instruction::Div | Instruction::Mod => {
+                let (l, r) = self.pop_numbers(state)?;
+                if r == 0 {
+                    return Err(LitError::DivideByZero);
+                }
+
+                self.stack.push(BytecodeObject::Numeric32(match *instr {
+                    Instruction::Div => l.wrapping_div(r),
+                    _ => l.wrapping_rem(r),
+                }));
+            }
+            Instruction::Eq => {
+                let ord = self.pop_ordering(state)?;
+                self.push_bool(ord == Ordering::Equal);
+            }
+            Instruction::Ne => {
+                let ord = self.pop_ordering(state)?;
+                self.push_bool(ord != Ordering::Equal);
+            }
+            Instruction::Lt => {
+                let ord = self.pop_ordering(state)?;
+                self.push_bool(ord == Ordering::Less);
+            }
+            Instruction::Le => {
+                let ord = self.pop_ordering(state)?;
+                self.push_bool(ord != Ordering::Greater);
+            }
+            Instruction::Gt => {
+                let ord = self.pop_ordering(state)?;
+                self.push_bool(ord == Ordering::Greater);
+            }
+            Instruction::Ge => {
+                let ord = self.pop_ordering(state)?;
+                self.push_bool(ord != Ordering::Less);
+            }
+            Instruction::Not => {
+                let val = self.pop()?.as_number(state)?;
+                self.push_bool(val == 0);
+            }
+            Instruction::Jump(target) => self.ip = target,
+            Instruction::JumpIfZero(target) => {
+                if self.pop()?.as_number(state)? == 0 {
+                    self.ip = target;
+                }
+            }
+            Instruction::Log(level) => {
+                let tuple = self.pop()?;
+                let format = self.pop()?;
+                let message = format_log(format.as_string(state)?, tuple.as_tuple(state)?, state)?;
+                logging::log(level, Target::Script, format_args!("{}", message));
+            }
+            Instruction::Rewind => {
+                let speed = u32::try_from(self.pop()?.as_number(state)?)?;
+                self.requests.push(VmRequest::Rewind(speed));
+            }
+            Instruction::RewindStop => self.requests.push(VmRequest::RewindStop),
+            Instruction::TimelineFork => {
+                let name = self.pop_timeline(state)?;
+                state.timelines.fork(&name, &state.variables)?;
+            }
+            Instruction::TimelineSwitch => {
+                let name = self.pop_timeline(state)?;
+                state.timelines.switch(&name, &mut state.variables)?;
+            }
+            Instruction::TimelineMerge => {
+                let name = self.pop_timeline(state)?;
+                state.timelines.merge(&name, &mut state.variables)?;
+            }
+            Instruction::TimelineGet(id) => {
+                let name = self.pop_timeline(state)?;
+                let val = state.timelines.get(&name, id, &state.variables)?.clone();
+                self.stack.push(val);
+            }
+            Instruction::TimelineDiff => {
+                let name = self.pop_timeline(state)?;
+                let count = state.timelines.diff(&name, &state.variables)?.len();
+                self.stack
+                    .push(BytecodeObject::Numeric32(i32::try_from(count)?));
+            }
+            Instruction::MergeRule(id, rule) => state.timelines.set_rule(id, rule),
+            Instruction::TimelineDelete => {
+                let name = self.pop_timeline(state)?;
+                state.timelines.delete(&name)?;
+            }
+            Instruction::TimelineCurrent => {
+                let name = state.timelines.current().to_string();
+                self.stack.push(BytecodeObject::Str(name));
+            }
+            Instruction::Random => {
+                let bound = self.pop()?.as_number(state)?;
+                if bound <= 0 {
+                    return Err(LitError::InvalidRandomBound(bound));
+                }
+
+                let val = state.rng.below(bound as u32);
+                self.stack.push(BytecodeObject::Numeric32(val as i32));
+            }
+            Instruction::KeyHeld => {
+                let key = self.pop()?.as_number(state)?;
+                self.push_bool(self.keys.contains(&key));
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Limits;
+
+    fn script(body: &[u8], state: &mut ParserState) -> Script {
+        let mut bytes = (body.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(body);
+        Script::load(&mut &bytes[..], state).unwrap()
+    }
+
+    // run a script on its own, and return what it left on the stack
+    fn run(body: &[u8]) -> Result<Vec<BytecodeObject>, LitError> {
+        let mut state = ParserState::new();
+        let script = script(body, &mut state);
+        let mut vm = Vm::new();
+        vm.run(&script, &mut state)?;
+        Ok(vm.stack().to_vec())
+    }
+
+    // push a 32-bit number
+    fn num(n: i32) -> Vec<u8> {
+        let mut bytes = vec![0x01, 3];
+        bytes.extend_from_slice(&n.to_be_bytes());
+        bytes
+    }
+
+    // apply an instruction to two numbers
+    fn binary(lhs: i32, rhs: i32, op: u8) -> Result<Vec<BytecodeObject>, LitError> {
+        let mut body = num(lhs);
+        body.extend(num(rhs));
+        body.push(op);
+        run(&body)
+    }
+
+    #[test]
+    fn arithmetic_wraps() {
+        let n = |n| vec![BytecodeObject::Numeric32(n)];
+        assert_eq!(binary(i32::MAX, 1, 0x10).unwrap(), n(i32::MIN));
+        assert_eq!(binary(i32::MIN, 1, 0x11).unwrap(), n(i32::MAX));
+        assert_eq!(binary(i32::MIN, -1, 0x12).unwrap(), n(i32::MIN));
+        assert_eq!(binary(i32::MIN, -1, 0x13).unwrap(), n(i32::MIN));
+        assert_eq!(binary(i32::MIN, -1, 0x14).unwrap(), n(0));
+        assert_eq!(binary(-7, 2, 0x13).unwrap(), n(-3));
+        assert_eq!(binary(-7, 2, 0x14).unwrap(), n(-1));
+        assert!(matches!(binary(1, 0, 0x13), Err(LitError::DivideByZero)));
+        assert!(matches!(binary(1, 0, 0x14), Err(LitError::DivideByZero)));
+    }
+
+    #[test]
+    fn comparisons_push_one_or_zero() {
+        let results: Vec<_> = (0x20..=0x25)
+            .map(|op| {
+                [(1, 2), (2, 2), (3, 2)]
+                    .iter()
+                    .map(|&(l, r)| match binary(l, r, op).unwrap()[..] {
+                        [BytecodeObject::Numeric8(b)] => b,
+                        ref s => panic!("unexpected stack {:?}", s),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        // eq, ne, lt, le, gt, ge of 1, 2 and 3 against 2
+        assert_eq!(
+            results,
+            [
+                [0, 1, 0],
+                [1, 0, 1],
+                [1, 0, 0],
+                [1, 1, 0],
+                [0, 0, 1],
+                [0, 1, 1]
+            ]
+        );
+
+        // numbers of different sizes are equal, strings compare by their contents
+        let mut body = vec![0x01, 1, 2, 0x01, 2, 0, 2, 0x20];
+        body.extend_from_slice(&[0x01, 4, 1, b'a', 0x01, 4, 1, b'b', 0x22]);
+        body.extend_from_slice(&[0x01, 1, 0, 0x26]);
+        assert_eq!(
+            run(&body).unwrap(),
+            [
+                BytecodeObject::Numeric8(1),
+                BytecodeObject::Numeric8(1),
+                BytecodeObject::Numeric8(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn jumps_go_to_instructions() {
+        // while x { x = x - 1 }, counting the iterations in y
+        let mut state = ParserState::new();
+        let x = state.global_id(1);
+        let y = state.global_id(2);
+        state
+            .register_variable(x, BytecodeObject::Numeric8(3))
+            .unwrap();
+        state
+            .register_variable(y, BytecodeObject::Numeric8(0))
+            .unwrap();
+        let script = script(
+            &[
+                0x02, 0, 0, 0, 1, // load x
+                0x31, 0, 0, 0, 43, // jump if zero to the end
+                0x02, 0, 0, 0, 1, 0x01, 1, 1, 0x11, 0x03, 0, 0, 0, 1, // x = x - 1
+                0x02, 0, 0, 0, 2, 0x01, 1, 1, 0x10, 0x03, 0, 0, 0, 2, // y = y + 1
+                0x30, 0, 0, 0, 0, // jump back to the start
+            ],
+            &mut state,
+        );
+
+        Vm::new().run(&script, &mut state).unwrap();
+        assert_eq!(state.get_variable(x).unwrap().as_number(&state).unwrap(), 0);
+        assert_eq!(state.get_variable(y).unwrap().as_number(&state).unwrap(), 3);
+    }
+
+    #[test]
+    fn running_forever_is_cut_off() {
+        let mut limits = Limits::new();
+        limits.max_instructions = 100;
+        let mut state = ParserState::with_limits(limits);
+        let script = script(&[0x30, 0, 0, 0, 0], &mut state);
+        let mut vm = Vm::new();
+        assert!(matches!(
+            vm.run(&script, &mut state),
+            Err(LitError::InstructionLimit(100))
+        ));
+    }
+
+    #[test]
+    fn random_numbers_need_a_bound_above_zero() {
+        let mut body = num(6);
+        body.push(0x70);
+        match run(&body).unwrap()[..] {
+            [BytecodeObject::Numeric32(n)] => assert!((0..6).contains(&n)),
+            ref s => panic!("unexpected stack {:?}", s),
+        }
+
+        for &bound in [0, -1].iter() {
+            let mut body = num(bound);
+            body.push(0x70);
+            assert!(matches!(
+                run(&body),
+                Err(LitError::InvalidRandomBound(b)) if b == bound
+            ));
+        }
+    }
+}
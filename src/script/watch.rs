@@ -0,0 +1,79 @@
+// Licensed under the BSD 3-Clause License. See the LICENSE file in the repository root for more information.
+// script/watch.rs - Notice when data files change, so that they can be reloaded.
+
+use super::DataLayer;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+// how often the files are checked
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// what a file looked like when it was last checked. a file that can't be read has no stamp
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Watches the files that data layers are read from by checking when they were last modified.
+pub struct DataWatcher {
+    files: Vec<(PathBuf, Stamp)>,
+    last_poll: Instant,
+    // a file changed, but it may still be being written
+    changed: bool,
+}
+
+impl DataWatcher {
+    pub fn new(layers: &[DataLayer]) -> Self {
+        let mut files: Vec<(PathBuf, Stamp)> = vec![];
+        for layer in layers {
+            let path = layer.path().to_path_buf();
+            // several entries of a pack are read from the same file
+            if !files.iter().any(|(p, _)| *p == path) {
+                let stamp = stamp(&path);
+                files.push((path, stamp));
+            }
+        }
+
+        Self {
+            files,
+            last_poll: Instant::now(),
+            changed: false,
+        }
+    }
+
+    /// Check whether any of the files changed. This is cheap enough to call every frame, since the
+    /// files are only looked at every so often. A change is only reported once the file has
+    /// stayed the same for a whole interval, so that files aren't read while they are written.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = false;
+        for (path, old) in self.files.iter_mut() {
+            let new = stamp(path);
+            if new != *old {
+                *old = new;
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.changed = true;
+            return false;
+        }
+        // files that are missing are probably being replaced
+        let settled = self.files.iter().all(|(_, stamp)| stamp.is_some());
+        if self.changed && settled {
+            self.changed = false;
+            return true;
+        }
+        false
+    }
+}